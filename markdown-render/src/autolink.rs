@@ -0,0 +1,281 @@
+use std::{mem, ops::Range};
+
+use pulldown_cmark::LinkType;
+
+use crate::{
+    event::{LineIndex, Position},
+    mdast::{Arena, ElementNode, Link, Node, NodeId, Text, TextNode},
+};
+
+/// Turn the bare `http(s)://` and `ftp://` URLs, `www.` links and email
+/// addresses in the text under `id` into links, as the GFM autolink
+/// extension does. The text of links and of `<a>` elements is left alone.
+///
+/// Returns whether the tree changed; the hashes of changed nodes are
+/// recomputed.
+pub fn literals(arena: &mut Arena, id: NodeId, lines: &LineIndex) -> bool {
+    match &arena[id] {
+        Node::Link(_, _) => return false,
+        Node::LinkReference(x, _) if x.url.is_some() => return false,
+        Node::InlineHtml(x, _)
+            if x.value
+                .as_deref()
+                .unwrap_or_default()
+                .to_ascii_lowercase()
+                .starts_with("<a") =>
+        {
+            return false
+        }
+        _ => {}
+    }
+    let mut changed = false;
+    if let Some(children) = arena[id].children_mut() {
+        let mut children = mem::take(children);
+        changed = split(arena, &mut children, lines);
+        *arena[id].children_mut().unwrap() = children;
+        arena.adopt(id);
+    }
+    let mut idx = 0;
+    while let Some(child) = arena.child(id, idx) {
+        changed |= literals(arena, child, lines);
+        idx += 1;
+    }
+    if changed {
+        arena.compute_hash(id);
+    }
+    changed
+}
+
+/// Replace the runs of text among `children` holding literal links with
+/// text and link nodes.
+fn split(arena: &mut Arena, children: &mut Vec<NodeId>, lines: &LineIndex) -> bool {
+    let mut changed = false;
+    let mut idx = 0;
+    while idx < children.len() {
+        // The parser may cut a text in several nodes, look at the whole run.
+        let end = (idx..children.len())
+            .find(|&end| !matches!(arena[children[end]], Node::Text(_, _)))
+            .unwrap_or(children.len());
+        if end == idx {
+            idx += 1;
+            continue;
+        }
+        let run: Vec<_> = children[idx..end]
+            .iter()
+            .map(|x| arena[*x].clone())
+            .collect();
+        let text: String = run
+            .iter()
+            .map(|x| x.value().map(String::as_str).unwrap_or_default())
+            .collect();
+        let found = find(&text);
+        if found.is_empty() {
+            idx = end;
+            continue;
+        }
+        let position = match (run[0].position(), run[run.len() - 1].position()) {
+            (Some(start), Some(end)) => Some(Position::new(start.start.clone(), end.end.clone())),
+            _ => None,
+        };
+        // Exact positions when the text is written as is in the source.
+        let offset = position
+            .as_ref()
+            .filter(|x| lines.source().get(x.start.offset..x.end.offset) == Some(&text))
+            .map(|x| x.start.offset);
+        let position_of = |range: Range<usize>| match offset {
+            Some(offset) => Some(lines.position(offset + range.start..offset + range.end)),
+            None => position.clone(),
+        };
+        let mut nodes = Vec::new();
+        let mut last = 0;
+        for (range, url, link_type) in found {
+            if last < range.start {
+                nodes.push(text_node(
+                    arena,
+                    &text[last..range.start],
+                    position_of(last..range.start),
+                ));
+            }
+            let children = vec![text_node(
+                arena,
+                &text[range.clone()],
+                position_of(range.clone()),
+            )];
+            let link = arena.push(Node::Link(
+                Link {
+                    children,
+                    position: position_of(range.clone()),
+                    link_type,
+                    url: Some(url),
+                    title: Some(String::new()),
+                },
+                ElementNode::default(),
+            ));
+            arena.compute_hash(link);
+            nodes.push(link);
+            last = range.end;
+        }
+        if last < text.len() {
+            nodes.push(text_node(
+                arena,
+                &text[last..],
+                position_of(last..text.len()),
+            ));
+        }
+        let count = nodes.len();
+        children.splice(idx..end, nodes);
+        changed = true;
+        idx += count;
+    }
+    changed
+}
+
+fn text_node(arena: &mut Arena, value: &str, position: Option<Position>) -> NodeId {
+    let id = arena.push(Node::Text(
+        Text {
+            value: Some(value.to_string()),
+            position,
+        },
+        TextNode::default(),
+    ));
+    arena.compute_hash(id);
+    id
+}
+
+/// The byte ranges of the literal links of `text`, with their destination
+/// and kind.
+fn find(text: &str) -> Vec<(Range<usize>, String, LinkType)> {
+    let bytes = text.as_bytes();
+    let mut found = Vec::new();
+    let mut last = 0;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if !text.is_char_boundary(idx) {
+            idx += 1;
+            continue;
+        }
+        let boundary = idx == 0
+            || matches!(
+                bytes[idx - 1],
+                b' ' | b'\t' | b'\n' | b'*' | b'_' | b'~' | b'('
+            );
+        let rest = &text[idx..];
+        let prefix = ["www.", "http://", "https://", "ftp://"]
+            .iter()
+            .find(|prefix| starts_with_ignore_case(rest, prefix));
+        if let (true, Some(prefix)) = (boundary, prefix) {
+            let domain_start = if *prefix == "www." { 0 } else { prefix.len() };
+            let domain = domain_len(&rest[domain_start..]);
+            if domain > 0 && domain_start + domain > prefix.len() {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '<')
+                    .unwrap_or(rest.len());
+                let end = trim_end(&rest[..end]);
+                if end > domain_start {
+                    let literal = &rest[..end];
+                    let url = if *prefix == "www." {
+                        format!("http://{}", literal)
+                    } else {
+                        literal.to_string()
+                    };
+                    found.push((idx..idx + end, url, LinkType::Autolink));
+                    idx += end;
+                    last = idx;
+                    continue;
+                }
+            }
+        }
+        if bytes[idx] == b'@' {
+            if let Some(range) = email(text, idx, last) {
+                let url = format!("mailto:{}", &text[range.clone()]);
+                idx = range.end;
+                last = idx;
+                found.push((range, url, LinkType::Email));
+                continue;
+            }
+        }
+        idx += 1;
+    }
+    found
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.len() >= prefix.len()
+        && text.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+/// The length of the valid domain starting `text`: segments of
+/// alphanumerics, `-` and `_` separated by periods, without underscores in
+/// the last two segments. Zero when there is none.
+fn domain_len(text: &str) -> usize {
+    let len = text
+        .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_' || c == '.'))
+        .unwrap_or(text.len());
+    let domain = text[..len].trim_end_matches('.');
+    let segments: Vec<&str> = domain.split('.').collect();
+    let valid = !domain.is_empty()
+        && segments.iter().all(|segment| !segment.is_empty())
+        && segments
+            .iter()
+            .rev()
+            .take(2)
+            .all(|segment| !segment.contains('_'));
+    if valid {
+        domain.len()
+    } else {
+        0
+    }
+}
+
+/// The length of `link` once the trailing punctuation, unbalanced closing
+/// parentheses and entity references are dropped.
+fn trim_end(link: &str) -> usize {
+    let mut end = link.len();
+    loop {
+        let current = &link[..end];
+        let unbalanced =
+            current.ends_with(')') && current.matches(')').count() > current.matches('(').count();
+        if unbalanced || current.ends_with(['?', '!', '.', ',', ':', '*', '_', '~']) {
+            end -= 1;
+        } else if current.ends_with(';') {
+            let name = current[..end - 1]
+                .trim_end_matches(|c: char| c.is_ascii_alphanumeric())
+                .len();
+            if name > 0 && name < end - 1 && current.as_bytes()[name - 1] == b'&' {
+                end = name - 1;
+            } else {
+                return end;
+            }
+        } else {
+            return end;
+        }
+    }
+}
+
+/// The range of the email address around the `@` at `at`, not reaching
+/// before `from`.
+fn email(text: &str, at: usize, from: usize) -> Option<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut start = at;
+    while start > from
+        && (bytes[start - 1].is_ascii_alphanumeric()
+            || matches!(bytes[start - 1], b'.' | b'+' | b'-' | b'_'))
+    {
+        start -= 1;
+    }
+    let domain = &text[at + 1..];
+    let len = domain
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'))
+        .unwrap_or(domain.len());
+    let domain = domain[..len].trim_end_matches('.');
+    let valid = start < at
+        && domain.contains('.')
+        && !domain.contains("..")
+        && !domain.starts_with('.')
+        && domain.ends_with(|c: char| c.is_ascii_alphanumeric());
+    if valid {
+        Some(start..at + 1 + domain.len())
+    } else {
+        None
+    }
+}
@@ -1,290 +1,472 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    mem::{self, Discriminant},
+};
 
-use web_sys::window;
+use crate::{
+    highlight::{highlight, Token},
+    html,
+    mdast::{Arena, Node, NodeId},
+    patch::Patch,
+    sanitize::HtmlPolicy,
+};
 
-use crate::{mdast::Node, DocNode};
+/// Reconciliation key of a child: its node type plus its structural hash.
+type Key = (Discriminant<Node>, u64);
 
-pub fn update(old_rc: Rc<RefCell<Node>>, cur_rc: Rc<RefCell<Node>>) {
-    let value: Option<String>;
-    let title: Option<String>;
-    let url: Option<String>;
-    {
-        let old = old_rc.borrow();
-        let mut cur = cur_rc.borrow_mut();
-        // if *old==*cur{
-        //     return
-        // }
-        cur.set_node(old.node_mut());
-        // cur.set_parent(old.parent());
-        // element = cur.node_mut();
-        value = cur.value().cloned();
-        url = cur.url().cloned();
-        title = cur.title().cloned();
-    }
-    // let parent = old.parent().unwrap().borrow_mut().node_mut();
-    match value {
-        None => {
-            match url {
-                Some(cur_url) => {
-                    let cur = cur_rc.borrow_mut();
-                    let element = cur.node_mut();
-                    match element.unwrap() {
-                        DocNode::Element(node_element) => {
-                            if node_element.tag_name().to_lowercase() == "a" {
-                                let _ = node_element.set_attribute("href", &cur_url);
-                            } else {
-                                let _ = node_element.set_attribute("src", &cur_url);
-                            }
+/// A child of the node being reconciled, as it sits in the DOM while its
+/// patches are computed.
+#[derive(Clone, Copy, PartialEq)]
+enum Slot {
+    Old(usize),
+    New(usize),
+}
 
-                            let _ = node_element.set_attribute("title", &title.unwrap());
-                        }
-                        DocNode::Text(_) => {}
-                    }
-                }
-                None => {}
+/// The trees being compared, and the policy they are rendered with.
+#[derive(Clone, Copy)]
+struct Trees<'a> {
+    arena: &'a Arena,
+    policy: &'a HtmlPolicy,
+}
+
+/// Compute the patches turning the DOM rendered from the tree of `old` into
+/// the one of `new`, both in `arena` and rendered under `policy`.
+pub fn diff(arena: &Arena, old: NodeId, new: NodeId, policy: &HtmlPolicy) -> Vec<Patch> {
+    let mut patches = Vec::new();
+    let mut path = Vec::new();
+    let mut new_path = Vec::new();
+    let trees = Trees { arena, policy };
+    update(trees, old, new, &mut path, &mut new_path, &mut patches);
+    patches
+}
+
+/// Patch the DOM node at `path` rendered from `old` into the one of `new`,
+/// found at `new_path` in the new tree.
+fn update(
+    trees: Trees,
+    old_id: NodeId,
+    new_id: NodeId,
+    path: &mut Vec<usize>,
+    new_path: &mut Vec<usize>,
+    patches: &mut Vec<Patch>,
+) {
+    let arena = trees.arena;
+    let (old, new) = (&arena[old_id], &arena[new_id]);
+    if old_id == new_id || old == new {
+        return;
+    }
+    if let Some(value) = new.value() {
+        // Code blocks are patched token by token.
+        if old.value() != Some(value) && !matches!(new, Node::Code(_, _)) {
+            patches.push(Patch::SetText {
+                path: path.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    if old.url().is_some() || new.url().is_some() {
+        let url = |id: NodeId| arena[id].url().and_then(|url| trees.policy.link_url(url));
+        if url(old_id) != url(new_id) {
+            let name = match new {
+                Node::Link(_, _) | Node::LinkReference(_, _) => "href",
+                _ => "src",
+            };
+            patches.push(attribute(path, name, url(new_id).map(Cow::into_owned)));
+        }
+        if old.title() != new.title() {
+            let title = new.title().filter(|title| !title.is_empty()).cloned();
+            patches.push(attribute(path, "title", title));
+        }
+    }
+    let alt = match (old, new) {
+        (Node::Image(x, _), Node::Image(y, _)) if x.alt != y.alt => Some(&y.alt),
+        (Node::ImageReference(x, _), Node::ImageReference(y, _)) if x.alt != y.alt => Some(&y.alt),
+        _ => None,
+    };
+    if let Some(alt) = alt {
+        patches.push(Patch::SetAttr {
+            path: path.clone(),
+            name: "alt".to_string(),
+            value: alt.clone(),
+        });
+    }
+    match (old, new) {
+        (Node::FootnoteReference(x, _), Node::FootnoteReference(y, _)) => {
+            if x.number != y.number {
+                patches.push(Patch::SetText {
+                    path: path.clone(),
+                    value: y.number.to_string(),
+                });
+            }
+            if x.occurrence != y.occurrence {
+                patches.push(Patch::SetAttr {
+                    path: path.clone(),
+                    name: "id".to_string(),
+                    value: y.id(),
+                });
             }
-            update_children(old_rc.clone(), cur_rc.clone());
         }
-        Some(cur_str) => {
-            let old = old_rc.borrow();
-            match old.value().cloned() {
-                Some(old_str) => {
-                    if cur_str != old_str {
-                        let cur = cur_rc.borrow_mut();
-                        let element = cur.node_mut();
-                        match element.unwrap() {
-                            DocNode::Element(node_element) => {
-                                node_element
-                                    .first_child()
-                                    .unwrap()
-                                    .set_text_content(Some(&cur_str));
-                            }
-                            DocNode::Text(text_element) => {
-                                text_element.set_node_value(Some(&cur_str));
-                            }
-                        }
-                    }
-                }
-                None => {}
+        (Node::Heading(x, _), Node::Heading(y, _)) => {
+            if x.slug != y.slug {
+                let id = Some(y.slug.clone()).filter(|slug| !slug.is_empty());
+                patches.push(attribute(path, "id", id));
+            }
+            if x.classes != y.classes {
+                let class = Some(y.classes.join(" ")).filter(|class| !class.is_empty());
+                patches.push(attribute(path, "class", class));
             }
         }
+        (Node::List(x, _), Node::List(y, _)) if x.start != y.start => {
+            let start = y.start.filter(|start| *start != 1);
+            patches.push(attribute(path, "start", start.map(|x| x.to_string())));
+        }
+        (Node::TableCell(x, _), Node::TableCell(y, _)) if x.align != y.align => {
+            patches.push(attribute(path, "style", y.style().map(str::to_string)));
+        }
+        (Node::ListItem(x, _), Node::ListItem(y, _)) if x.checked != y.checked => {
+            patches.push(Patch::SetChecked {
+                path: child_path(path, 0),
+                checked: y.checked == Some(true),
+            });
+        }
+        (Node::FootnoteDefinition(x, _), Node::FootnoteDefinition(y, _)) => {
+            if x.number != y.number {
+                patches.push(Patch::SetText {
+                    path: child_path(path, 0),
+                    value: y.number.to_string(),
+                });
+            }
+            if x.occurrence != y.occurrence {
+                patches.push(Patch::SetAttr {
+                    path: path.clone(),
+                    name: "id".to_string(),
+                    value: y.id(),
+                });
+            }
+        }
+        (Node::Code(x, _), Node::Code(y, _)) if x.value != y.value => {
+            let old_tokens = highlight(x.lang.as_deref(), x.value.as_deref().unwrap_or_default());
+            let new_tokens = highlight(y.lang.as_deref(), y.value.as_deref().unwrap_or_default());
+            update_tokens(&old_tokens, &new_tokens, &child_path(path, 0), patches);
+        }
+        _ => {}
+    }
+    if let (Some(old_children), Some(new_children)) = (old.children(), new.children()) {
+        path_children(
+            trees,
+            new_id,
+            old_children,
+            new_children,
+            path,
+            new_path,
+            patches,
+        );
     }
-    // cur
 }
 
-fn update_children(old: Rc<RefCell<Node>>, new: Rc<RefCell<Node>>) {
-    let window = window().unwrap();
-    let doc = window.document().unwrap();
-    let old_el = old.borrow();
-    let new_el = new.borrow();
-    // log(&(*old_el==*new_el).to_string());
-    if let Some(old_children) = old_el.children() {
-        if let Some(new_children) = new_el.children() {
-            if old_children.len() > 0 && new_children.len() > 0 {
-                let parent_node = new_el.node_mut().unwrap();
-                path_children(old_children, new_children, new.clone(), parent_node);
-                // log(&old_children.len().to_string());
-                // log(&new_children.len().to_string());
-            } else if new_children.len() > 0 {
-                // log("555");
-                for child in new_children {
-                    let mut cur_child = child.borrow_mut();
-                    cur_child.create(&doc, new.clone());
-                }
-            } else {
-                // log("666");
-                match new_el.node_mut().unwrap() {
-                    crate::DocNode::Element(element) => {
-                        while let Some(child) = element.first_child() {
-                            element
-                                .remove_child(&child)
-                                .expect("Failed to remove child");
-                        }
-                    }
-                    crate::DocNode::Text(_) => {}
-                }
-            }
+/// Set the attribute `name` of the element at `path` to `value`, or remove
+/// it without one, as a fresh render leaves it out.
+fn attribute(path: &[usize], name: &str, value: Option<String>) -> Patch {
+    match value {
+        Some(value) => Patch::SetAttr {
+            path: path.to_vec(),
+            name: name.to_string(),
+            value,
+        },
+        None => Patch::RemoveAttr {
+            path: path.to_vec(),
+            name: name.to_string(),
+        },
+    }
+}
+
+/// Patch the highlighted tokens of a code block, children of the `<code>`
+/// element at `path`. Tokens around the edit that did not change are left
+/// untouched.
+fn update_tokens(old: &[Token], new: &[Token], path: &[usize], patches: &mut Vec<Patch>) {
+    let prefix = old.iter().zip(new).take_while(|(x, y)| x == y).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+    let insert = |idx: usize, token: &Token| Patch::InsertToken {
+        path: child_path(path, idx),
+        class: token.class.map(str::to_string),
+        text: token.text.to_string(),
+    };
+    let paired = (old_end - prefix).min(new_end - prefix);
+    for idx in prefix..prefix + paired {
+        if old[idx].class == new[idx].class {
+            patches.push(Patch::SetText {
+                path: child_path(path, idx),
+                value: new[idx].text.to_string(),
+            });
+        } else {
+            patches.push(Patch::Remove {
+                path: child_path(path, idx),
+            });
+            patches.push(insert(idx, &new[idx]));
+        }
+    }
+    for idx in (prefix + paired..old_end).rev() {
+        patches.push(Patch::Remove {
+            path: child_path(path, idx),
+        });
+    }
+    for (idx, token) in new.iter().enumerate().take(new_end).skip(prefix + paired) {
+        patches.push(insert(idx, token));
+    }
+}
+
+/// Whether `old` can be patched into `new`, rather than replaced.
+fn patchable(arena: &Arena, old_id: NodeId, new_id: NodeId) -> bool {
+    let (old, new) = (&arena[old_id], &arena[new_id]);
+    match (old, new) {
+        (Node::Heading(x, _), Node::Heading(y, _)) => x.depth == y.depth,
+        (Node::List(x, _), Node::List(y, _)) => x.start.is_some() == y.start.is_some(),
+        (Node::Code(x, _), Node::Code(y, _)) => x.lang == y.lang,
+        // The checkbox comes and goes with the task marker.
+        (Node::ListItem(x, _), Node::ListItem(y, _)) => x.checked.is_some() == y.checked.is_some(),
+        // So does the `<tbody>` with the body rows.
+        (Node::Table(_, _), Node::Table(_, _)) => {
+            arena.table_body(old_id) == arena.table_body(new_id)
         }
+        // Rendered as a whole, like math, but for the blocks between paired
+        // tags.
+        (Node::Html(x, _), Node::Html(y, _)) => {
+            !x.content.is_empty()
+                && x.content == y.content
+                && x.value == y.value
+                && x.close == y.close
+        }
+        (Node::InlineMath(_, _), Node::InlineMath(_, _)) => false,
+        (Node::Math(_, _), Node::Math(_, _)) => false,
+        // Only the content of a paired element is patched, its tags are not.
+        (Node::InlineHtml(x, _), Node::InlineHtml(y, _)) => {
+            x.close.is_some() && x.value == y.value && x.close == y.close
+        }
+        (Node::FootnoteDefinition(x, _), Node::FootnoteDefinition(y, _)) => x.label == y.label,
+        // Undefined references are rendered as their source text.
+        (Node::LinkReference(x, _), Node::LinkReference(y, _)) => {
+            x.url.is_some() == y.url.is_some()
+                && (x.url.is_some() || (x.label == y.label && x.link_type == y.link_type))
+        }
+        (Node::ImageReference(x, _), Node::ImageReference(y, _)) => {
+            x.url.is_some() && y.url.is_some()
+        }
+        // Undefined references have no link to patch.
+        (Node::FootnoteReference(x, _), Node::FootnoteReference(y, _)) => {
+            x.label == y.label && (x.number == 0) == (y.number == 0)
+        }
+        _ => mem::discriminant(old) == mem::discriminant(new),
     }
 }
 
+/// Reconcile the children of `parent`, rendered at `path`.
+///
+/// Children are matched by [`Key`]: the common prefix and suffix are left
+/// untouched, those shared by both trees without even looking into them, then the remaining old children are looked up through a key
+/// map. Unmatched children that can be patched into each other are paired in
+/// order so that an edited node is patched rather than recreated. Only the
+/// matched children that fall outside the longest increasing subsequence of
+/// old indices are moved.
 fn path_children(
-    old_ch: &Vec<Rc<RefCell<Node>>>,
-    new_ch: &Vec<Rc<RefCell<Node>>>,
-    parent: Rc<RefCell<Node>>,
-    parent_node: &DocNode,
+    trees: Trees,
+    parent: NodeId,
+    old_ch: &[NodeId],
+    new_ch: &[NodeId],
+    path: &mut Vec<usize>,
+    new_path: &mut Vec<usize>,
+    patches: &mut Vec<Patch>,
 ) {
-    let window = window().unwrap();
-    let doc = window.document().unwrap();
-    let mut old_start_idx = 0;
-    let mut new_start_idx = 0;
-    let mut old_end_idx = old_ch.len() - 1;
-    let mut new_end_idx = new_ch.len() - 1;
-    while old_start_idx <= old_end_idx && new_start_idx <= new_end_idx {
-        // log(&(old_ch[old_start_idx] == new_ch[new_start_idx]).to_string());
-        if old_ch[old_start_idx] == new_ch[new_start_idx] {
-            update(old_ch[old_start_idx].clone(), new_ch[new_start_idx].clone());
-            old_start_idx += 1;
-            new_start_idx += 1;
-        } else if old_ch[old_end_idx] == new_ch[new_end_idx] {
-            update(old_ch[old_end_idx].clone(), new_ch[new_end_idx].clone());
-            old_end_idx -= 1;
-            new_end_idx -= 1;
-        } else if old_ch[old_start_idx] == new_ch[new_end_idx] {
-            update(old_ch[old_start_idx].clone(), new_ch[new_end_idx].clone());
-            let old_pre = old_ch[old_start_idx].borrow();
-            let element = old_pre.node_mut();
-            match parent_node {
-                DocNode::Element(parent_element) => match element.unwrap() {
-                    DocNode::Element(child_element) => {
-                        let brother_node = old_ch[old_end_idx + 1].borrow();
-                        let brother = brother_node.node_mut();
-                        match brother.unwrap() {
-                            DocNode::Element(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                            DocNode::Text(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                        }
-                    }
-                    DocNode::Text(child_element) => {
-                        let brother_node = old_ch[old_end_idx + 1].borrow();
-                        let brother = brother_node.node_mut();
-                        match brother.unwrap() {
-                            DocNode::Element(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                            DocNode::Text(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                        }
-                    }
-                },
-                DocNode::Text(_) => {}
-            }
-            old_start_idx += 1;
-            new_end_idx -= 1;
-        } else if old_ch[old_end_idx] == new_ch[new_start_idx] {
-            update(old_ch[old_end_idx].clone(), new_ch[new_start_idx].clone());
-            let old_pre = old_ch[old_end_idx].borrow();
-            let element = old_pre.node_mut();
-            match parent_node {
-                DocNode::Element(parent_element) => match element.unwrap() {
-                    DocNode::Element(child_element) => {
-                        let brother_node = old_ch[old_start_idx].borrow();
-                        let brother = brother_node.node_mut();
-                        match brother.unwrap() {
-                            DocNode::Element(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                            DocNode::Text(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                        }
-                    }
-                    DocNode::Text(child_element) => {
-                        let brother_node = old_ch[old_start_idx].borrow();
-                        let brother = brother_node.node_mut();
-                        match brother.unwrap() {
-                            DocNode::Element(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                            DocNode::Text(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                        }
-                    }
-                },
-                DocNode::Text(_) => {}
-            }
-            old_end_idx -= 1;
-            new_start_idx += 1;
-        } else {
-            // TODO
-            break;
+    let arena = trees.arena;
+    let skip = old_ch
+        .iter()
+        .zip(new_ch)
+        .take_while(|(x, y)| x == y)
+        .count();
+    let tail = old_ch[skip..]
+        .iter()
+        .rev()
+        .zip(new_ch[skip..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let old_ch = &old_ch[skip..old_ch.len() - tail];
+    let new_ch = &new_ch[skip..new_ch.len() - tail];
+    let dom_path = |idx: usize| {
+        let mut child = path.clone();
+        child.extend(arena.dom_child_path(parent, skip + idx));
+        child
+    };
+    let old_keys: Vec<Key> = old_ch.iter().map(|child| key(&arena[*child])).collect();
+    let new_keys: Vec<Key> = new_ch.iter().map(|child| key(&arena[*child])).collect();
+
+    let mut old_start = 0;
+    let mut new_start = 0;
+    let mut old_end = old_ch.len();
+    let mut new_end = new_ch.len();
+    while old_start < old_end && new_start < new_end && old_keys[old_start] == new_keys[new_start] {
+        old_start += 1;
+        new_start += 1;
+    }
+    while old_start < old_end
+        && new_start < new_end
+        && old_keys[old_end - 1] == new_keys[new_end - 1]
+    {
+        old_end -= 1;
+        new_end -= 1;
+    }
+
+    // Duplicate keys are matched in document order.
+    let mut key_map: HashMap<Key, VecDeque<usize>> = HashMap::new();
+    for (idx, key) in old_keys.iter().enumerate().take(old_end).skip(old_start) {
+        key_map.entry(*key).or_default().push_back(idx);
+    }
+    let mut sources: Vec<Option<usize>> = new_keys[new_start..new_end]
+        .iter()
+        .map(|key| key_map.get_mut(key).and_then(|indices| indices.pop_front()))
+        .collect();
+    let mut matched = vec![false; old_ch.len()];
+    for idx in sources.iter().flatten() {
+        matched[*idx] = true;
+    }
+
+    let mut leftovers: HashMap<Discriminant<Node>, VecDeque<usize>> = HashMap::new();
+    for idx in old_start..old_end {
+        if !matched[idx] {
+            leftovers.entry(old_keys[idx].0).or_default().push_back(idx);
         }
     }
-    for idx in new_start_idx..new_end_idx + 1 {
-        {
-            let mut child_node = new_ch[idx].borrow_mut();
-            let _ = child_node.create_node(&doc, Some(parent.clone()));
+    for (offset, source) in sources.iter_mut().enumerate() {
+        if source.is_some() {
+            continue;
         }
-        {
-            let child_node = new_ch[idx].borrow();
-            if let Some(children) = child_node.children() {
-                for child in children {
-                    let mut cur_child = child.borrow_mut();
-                    cur_child.create(&doc, new_ch[idx].clone());
-                }
+        let new_id = new_ch[new_start + offset];
+        if let Some(indices) = leftovers.get_mut(&new_keys[new_start + offset].0) {
+            let pos = indices
+                .iter()
+                .position(|idx| patchable(arena, old_ch[*idx], new_id));
+            *source = pos.and_then(|pos| indices.remove(pos));
+            if let Some(idx) = source {
+                matched[*idx] = true;
             }
         }
+    }
 
-        let child_node = new_ch[idx].borrow_mut();
-        let element = child_node.node_mut();
-        match parent_node {
-            DocNode::Element(parent_element) => match element.unwrap() {
-                DocNode::Element(child_element) => {
-                    if old_start_idx < old_ch.len() {
-                        let brother_node = old_ch[old_start_idx].borrow();
-                        let brother = brother_node.node_mut();
-                        match brother.unwrap() {
-                            DocNode::Element(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                            DocNode::Text(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                        }
-                    } else {
-                        let _ = parent_element.append_child(&child_element);
-                    }
-                }
-                DocNode::Text(child_element) => {
-                    if old_start_idx < old_ch.len() {
-                        let brother_node = old_ch[old_start_idx].borrow();
-                        let brother = brother_node.node_mut();
-                        match brother.unwrap() {
-                            DocNode::Element(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                            DocNode::Text(brother_element) => {
-                                let _ = parent_element
-                                    .insert_before(&child_element, Some(&brother_element));
-                            }
-                        }
-                    } else {
-                        let _ = parent_element.append_child(&child_element);
-                    }
-                }
-            },
-            DocNode::Text(_) => {}
+    let mut current: Vec<Slot> = (0..old_ch.len()).map(Slot::Old).collect();
+    for idx in (old_start..old_end).rev() {
+        if !matched[idx] {
+            current.remove(idx);
+            patches.push(Patch::Remove {
+                path: dom_path(idx),
+            });
         }
     }
-    for idx in old_start_idx..old_end_idx + 1 {
-        let child_node = old_ch[idx].borrow_mut();
-        let element = child_node.node_mut();
-        match parent_node {
-            DocNode::Element(parent_element) => match element.unwrap() {
-                DocNode::Element(child_element) => {
-                    let _ = parent_element.remove_child(&child_element);
-                }
-                DocNode::Text(child_element) => {
-                    let _ = parent_element.remove_child(&child_element);
+
+    // Walk backwards so that the next sibling is always in its final place.
+    let stable = longest_increasing_subsequence(&sources);
+    let mut anchor = if new_end < new_ch.len() {
+        Some(Slot::Old(old_end))
+    } else {
+        None
+    };
+    for offset in (0..sources.len()).rev() {
+        let slot = match sources[offset] {
+            Some(idx) => Slot::Old(idx),
+            None => Slot::New(new_start + offset),
+        };
+        if !stable[offset] {
+            let from = match slot {
+                Slot::Old(_) => {
+                    let from = position(&current, slot);
+                    current.remove(from);
+                    Some(from)
                 }
-            },
-            DocNode::Text(_) => {}
+                Slot::New(_) => None,
+            };
+            let to = anchor.map_or(current.len(), |anchor| position(&current, anchor));
+            current.insert(to, slot);
+            patches.push(match from {
+                Some(from) => Patch::Move {
+                    from: dom_path(from),
+                    to: dom_path(to),
+                },
+                None => Patch::Insert {
+                    path: dom_path(to),
+                    source: child_path(new_path, skip + new_start + offset),
+                    html: html::to_html_with_policy(
+                        arena,
+                        new_ch[new_start + offset],
+                        trees.policy,
+                    ),
+                },
+            });
         }
+        anchor = Some(slot);
     }
+
+    // The children are now in their final order, patch the matched ones.
+    for (offset, source) in sources.iter().enumerate() {
+        if let Some(idx) = source {
+            let depth = path.len();
+            path.extend(arena.dom_child_path(parent, skip + new_start + offset));
+            new_path.push(skip + new_start + offset);
+            update(
+                trees,
+                old_ch[*idx],
+                new_ch[new_start + offset],
+                path,
+                new_path,
+                patches,
+            );
+            new_path.pop();
+            path.truncate(depth);
+        }
+    }
+}
+
+fn child_path(path: &[usize], idx: usize) -> Vec<usize> {
+    let mut child = path.to_vec();
+    child.push(idx);
+    child
+}
+
+fn position(current: &[Slot], slot: Slot) -> usize {
+    current.iter().position(|cur| *cur == slot).unwrap()
+}
+
+/// Mark the entries of `sources` that form the longest increasing subsequence
+/// of old indices; those children keep their DOM position.
+fn longest_increasing_subsequence(sources: &[Option<usize>]) -> Vec<bool> {
+    let mut stable = vec![false; sources.len()];
+    let mut predecessors: Vec<Option<usize>> = vec![None; sources.len()];
+    let mut tails: Vec<usize> = Vec::new();
+    for (idx, source) in sources.iter().enumerate() {
+        if let Some(value) = source {
+            let pos = tails.partition_point(|tail| sources[*tail] < Some(*value));
+            if pos > 0 {
+                predecessors[idx] = Some(tails[pos - 1]);
+            }
+            if pos == tails.len() {
+                tails.push(idx);
+            } else {
+                tails[pos] = idx;
+            }
+        }
+    }
+    let mut cur = tails.last().copied();
+    while let Some(idx) = cur {
+        stable[idx] = true;
+        cur = predecessors[idx];
+    }
+    stable
+}
+
+fn key(node: &Node) -> Key {
+    (mem::discriminant(node), node.subtree_hash())
 }
@@ -0,0 +1,160 @@
+use std::{any::Any, fmt, rc::Rc};
+
+/// A document the tree is rendered into.
+///
+/// Implemented for the browser DOM, `web_sys::Document`, with the `dom`
+/// feature, and for the in-memory [`crate::memory::Document`].
+pub trait Dom {
+    /// A node of the document. Clones refer to the same node.
+    type Node: Clone + PartialEq + 'static;
+
+    fn create_element(&self, tag: &str) -> Self::Node;
+    fn create_text_node(&self, text: &str) -> Self::Node;
+    /// The top-level nodes of the HTML fragment `html`, detached.
+    fn parse_html(&self, html: &str) -> Vec<Self::Node>;
+
+    fn is_element(&self, node: &Self::Node) -> bool;
+    /// The text of a text node, `None` for other nodes.
+    fn text(&self, node: &Self::Node) -> Option<String>;
+    fn set_attribute(&self, element: &Self::Node, name: &str, value: &str);
+    fn remove_attribute(&self, element: &Self::Node, name: &str);
+    /// Replace the children of `node` with `text`, or the text of a text
+    /// node.
+    fn set_text_content(&self, node: &Self::Node, text: &str);
+    /// Check or uncheck the checkbox `input`.
+    fn set_checked(&self, input: &Self::Node, checked: bool);
+
+    fn parent(&self, node: &Self::Node) -> Option<Self::Node>;
+    fn child_count(&self, node: &Self::Node) -> usize;
+    fn child(&self, node: &Self::Node, idx: usize) -> Option<Self::Node>;
+    /// Insert `child` in `parent` before `before`, at the end without it,
+    /// detaching it from its parent first.
+    fn insert_before(&self, parent: &Self::Node, child: &Self::Node, before: Option<&Self::Node>);
+    fn remove_child(&self, parent: &Self::Node, child: &Self::Node);
+
+    fn first_child(&self, node: &Self::Node) -> Option<Self::Node> {
+        self.child(node, 0)
+    }
+
+    fn last_child(&self, node: &Self::Node) -> Option<Self::Node> {
+        let count = self.child_count(node);
+        count.checked_sub(1).and_then(|idx| self.child(node, idx))
+    }
+
+    fn append_child(&self, parent: &Self::Node, child: &Self::Node) {
+        self.insert_before(parent, child, None);
+    }
+}
+
+/// The rendered node of a tree node, of whichever [`Dom`] it was rendered
+/// with.
+#[derive(Clone)]
+pub struct DocNode(Rc<dyn Any>);
+
+impl DocNode {
+    pub fn new<N: 'static>(node: N) -> Self {
+        DocNode(Rc::new(node))
+    }
+
+    /// The node, if rendered with a [`Dom`] of nodes `N`.
+    pub fn get<N: 'static>(&self) -> Option<&N> {
+        self.0.downcast_ref()
+    }
+}
+
+impl PartialEq for DocNode {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for DocNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DocNode")
+    }
+}
+
+#[cfg(feature = "dom")]
+mod web {
+    use wasm_bindgen::JsCast;
+    use web_sys::{Document, Element, HtmlInputElement, HtmlTemplateElement, Node};
+
+    use super::Dom;
+
+    impl Dom for Document {
+        type Node = Node;
+
+        fn create_element(&self, tag: &str) -> Node {
+            Document::create_element(self, tag).unwrap().into()
+        }
+
+        fn create_text_node(&self, text: &str) -> Node {
+            Document::create_text_node(self, text).into()
+        }
+
+        fn parse_html(&self, html: &str) -> Vec<Node> {
+            let template = Document::create_element(self, "template")
+                .unwrap()
+                .dyn_into::<HtmlTemplateElement>()
+                .unwrap();
+            template.set_inner_html(html);
+            let nodes = template.content().child_nodes();
+            (0..nodes.length())
+                .filter_map(|idx| nodes.item(idx))
+                .collect()
+        }
+
+        fn is_element(&self, node: &Node) -> bool {
+            node.node_type() == Node::ELEMENT_NODE
+        }
+
+        fn text(&self, node: &Node) -> Option<String> {
+            match node.node_type() {
+                Node::TEXT_NODE => node.node_value(),
+                _ => None,
+            }
+        }
+
+        fn set_attribute(&self, element: &Node, name: &str, value: &str) {
+            if let Some(element) = element.dyn_ref::<Element>() {
+                let _ = element.set_attribute(name, value);
+            }
+        }
+
+        fn remove_attribute(&self, element: &Node, name: &str) {
+            if let Some(element) = element.dyn_ref::<Element>() {
+                let _ = element.remove_attribute(name);
+            }
+        }
+
+        fn set_text_content(&self, node: &Node, text: &str) {
+            node.set_text_content(Some(text));
+        }
+
+        fn set_checked(&self, input: &Node, checked: bool) {
+            if let Some(input) = input.dyn_ref::<HtmlInputElement>() {
+                input.set_checked(checked);
+            }
+        }
+
+        fn parent(&self, node: &Node) -> Option<Node> {
+            node.parent_node()
+        }
+
+        fn child_count(&self, node: &Node) -> usize {
+            node.child_nodes().length() as usize
+        }
+
+        fn child(&self, node: &Node, idx: usize) -> Option<Node> {
+            node.child_nodes().item(idx as u32)
+        }
+
+        fn insert_before(&self, parent: &Node, child: &Node, before: Option<&Node>) {
+            parent.insert_before(child, before).unwrap();
+        }
+
+        fn remove_child(&self, parent: &Node, child: &Node) {
+            let _ = parent.remove_child(child);
+        }
+    }
+}
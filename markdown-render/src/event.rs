@@ -1,22 +1,163 @@
-use std::fmt;
+use std::{fmt, ops::Range};
 
-#[derive(Clone, PartialEq)]
+/// A place in the source: 1-indexed line and column, columns counting
+/// characters, and the byte offset.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+impl Point {
+    /// Move this point by `offset` bytes and `lines` lines.
+    pub fn shift(&mut self, offset: isize, lines: isize) {
+        self.offset = (self.offset as isize + offset) as usize;
+        self.line = (self.line as isize + lines) as usize;
+    }
+
+    /// The point after `text`, read from this point.
+    pub fn after(&self, text: &str) -> Point {
+        match text.rfind('\n') {
+            Some(idx) => Point {
+                line: self.line + text.matches('\n').count(),
+                column: text[idx + 1..].chars().count() + 1,
+                offset: self.offset + text.len(),
+            },
+            None => Point {
+                line: self.line,
+                column: self.column + text.chars().count(),
+                offset: self.offset + text.len(),
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Position {
-    pub start: usize,
-    pub end: usize,
+    pub start: Point,
+    pub end: Point,
 }
 
 impl Position {
-    fn new(&mut self, start: usize, end: usize) -> Option<Position> {
-        Some(Position {
-            start: start,
-            end: end,
-        })
+    pub fn new(start: Point, end: Point) -> Position {
+        Position { start, end }
+    }
+
+    /// Move both ends by `offset` bytes and `lines` lines.
+    pub fn shift(&mut self, offset: isize, lines: isize) {
+        self.start.shift(offset, lines);
+        self.end.shift(offset, lines);
+    }
+
+    /// Whether the byte `offset` falls inside this position.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start.offset <= offset && offset < self.end.offset
     }
 }
 
 impl fmt::Debug for Position {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}-{}", self.start, self.end)
+        write!(f, "{:?}-{:?}", self.start, self.end)
+    }
+}
+
+/// A change to the source: replace the bytes `start..end` with `replacement`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourceEdit {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+impl SourceEdit {
+    /// `source` with this edit applied, or `None` when the replaced range is
+    /// not within it.
+    pub fn apply(&self, source: &str) -> Option<String> {
+        if self.start > self.end
+            || !source.is_char_boundary(self.start)
+            || !source.is_char_boundary(self.end)
+        {
+            return None;
+        }
+        let mut edited =
+            String::with_capacity(source.len() - (self.end - self.start) + self.replacement.len());
+        edited.push_str(&source[..self.start]);
+        edited.push_str(&self.replacement);
+        edited.push_str(&source[self.end..]);
+        Some(edited)
+    }
+}
+
+/// Start offsets of the lines of a source, to turn byte offsets into points.
+#[derive(Clone, Debug, Default)]
+pub struct LineIndex {
+    source: String,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(idx, _)| idx + 1));
+        LineIndex {
+            source: source.to_string(),
+            line_starts,
+        }
+    }
+
+    /// The index of `source`, the source of this one with `edit` applied:
+    /// only the line starts after the edit are moved.
+    pub fn edited(&self, edit: &SourceEdit, source: &str) -> LineIndex {
+        let delta = source.len() as isize - self.source.len() as isize;
+        let before = self
+            .line_starts
+            .partition_point(|start| *start <= edit.start);
+        let after = self.line_starts.partition_point(|start| *start <= edit.end);
+        let mut line_starts = self.line_starts[..before].to_vec();
+        line_starts.extend(
+            edit.replacement
+                .match_indices('\n')
+                .map(|(idx, _)| edit.start + idx + 1),
+        );
+        line_starts.extend(
+            self.line_starts[after..]
+                .iter()
+                .map(|start| (*start as isize + delta) as usize),
+        );
+        LineIndex {
+            source: source.to_string(),
+            line_starts,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The point at `offset`, clamped to the source and moved back to a
+    /// character boundary.
+    pub fn point(&self, offset: usize) -> Point {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        let line_start = self.line_starts[line - 1];
+        Point {
+            line,
+            column: self.source[line_start..offset].chars().count() + 1,
+            offset,
+        }
+    }
+
+    pub fn position(&self, range: Range<usize>) -> Position {
+        Position::new(self.point(range.start), self.point(range.end))
     }
 }
@@ -0,0 +1,238 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+};
+
+use crate::mdast::{Arena, Node, NodeId};
+
+/// Move the footnote definitions of `root` to the end of the document and
+/// number them, and their references, in order of first reference.
+///
+/// Definitions that are never referenced follow, in document order, and so
+/// do the later definitions of a label, which get an id of their own.
+/// Hashes of the whole tree are recomputed when footnotes are found.
+pub fn collect(arena: &mut Arena, root: NodeId) {
+    let mut definitions = Vec::new();
+    take_definitions(arena, root, &mut definitions);
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for definition in &definitions {
+        if let Node::FootnoteDefinition(x, _) = &mut arena[*definition] {
+            let occurrence = occurrences.entry(x.label.clone()).or_default();
+            *occurrence += 1;
+            x.occurrence = *occurrence;
+        }
+    }
+    let mut numbering = Numbering {
+        defined: occurrences.into_keys().collect(),
+        numbers: HashMap::new(),
+        occurrences: HashMap::new(),
+        count: 0,
+        found: !definitions.is_empty(),
+    };
+    number_references(arena, root, &mut numbering);
+
+    // References inside definitions can number further footnotes.
+    let mut ordered = Vec::new();
+    while !definitions.is_empty() {
+        let next = definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| !is_duplicate(&arena[**x]))
+            .filter_map(|(idx, x)| numbering.numbers.get(&label(&arena[*x])).map(|n| (*n, idx)))
+            .min();
+        let definition = definitions.remove(next.map_or(0, |(_, idx)| idx));
+        let number = if is_duplicate(&arena[definition]) {
+            numbering.count += 1;
+            numbering.count
+        } else {
+            numbering.number(&label(&arena[definition]))
+        };
+        if let Node::FootnoteDefinition(x, _) = &mut arena[definition] {
+            x.number = number;
+        }
+        number_references(arena, definition, &mut numbering);
+        ordered.push(definition);
+    }
+    if !numbering.found {
+        return;
+    }
+    arena[root].children_mut().unwrap().extend(ordered);
+    arena.adopt(root);
+    rehash(arena, root);
+}
+
+struct Numbering {
+    defined: HashSet<String>,
+    numbers: HashMap<String, usize>,
+    occurrences: HashMap<String, usize>,
+    /// The last number given.
+    count: usize,
+    /// Whether any footnote construct was seen.
+    found: bool,
+}
+
+impl Numbering {
+    /// The number of the footnote `label`, the next one if it has none yet.
+    fn number(&mut self, label: &str) -> usize {
+        if let Some(number) = self.numbers.get(label) {
+            return *number;
+        }
+        self.count += 1;
+        self.numbers.insert(label.to_string(), self.count);
+        self.count
+    }
+}
+
+fn take_definitions(arena: &mut Arena, id: NodeId, definitions: &mut Vec<NodeId>) {
+    let children = match arena[id].children_mut() {
+        Some(children) => mem::take(children),
+        None => return,
+    };
+    let (taken, kept): (Vec<_>, Vec<_>) = children
+        .into_iter()
+        .partition(|child| matches!(arena[*child], Node::FootnoteDefinition(_, _)));
+    for child in &kept {
+        take_definitions(arena, *child, definitions);
+    }
+    *arena[id].children_mut().unwrap() = kept;
+    for definition in taken {
+        definitions.push(definition);
+        take_definitions(arena, definition, definitions);
+    }
+}
+
+fn number_references(arena: &mut Arena, id: NodeId, numbering: &mut Numbering) {
+    if let Node::FootnoteReference(x, _) = &mut arena[id] {
+        numbering.found = true;
+        if numbering.defined.contains(&x.label) {
+            x.number = numbering.number(&x.label);
+            let occurrence = numbering.occurrences.entry(x.label.clone()).or_default();
+            *occurrence += 1;
+            x.occurrence = *occurrence;
+        }
+    }
+    let mut idx = 0;
+    while let Some(child) = arena.child(id, idx) {
+        number_references(arena, child, numbering);
+        idx += 1;
+    }
+}
+
+fn is_duplicate(node: &Node) -> bool {
+    matches!(node, Node::FootnoteDefinition(x, _) if x.occurrence > 1)
+}
+
+fn label(node: &Node) -> String {
+    match node {
+        Node::FootnoteDefinition(x, _) => x.label.clone(),
+        _ => String::new(),
+    }
+}
+
+fn rehash(arena: &mut Arena, id: NodeId) {
+    let mut idx = 0;
+    while let Some(child) = arena.child(id, idx) {
+        rehash(arena, child);
+        idx += 1;
+    }
+    arena.compute_hash(id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{html::to_html, parse};
+
+    /// Element ids and numbers, in document order.
+    type Ids = Vec<(String, usize)>;
+
+    /// The ids and numbers of the footnote definitions of `markdown`, in the
+    /// order they end up in, and of its references.
+    fn footnotes(markdown: &str) -> (Ids, Ids) {
+        let (arena, root) = parse(markdown);
+        let mut definitions = Vec::new();
+        let mut references = Vec::new();
+        walk(&arena, root, &mut definitions, &mut references);
+        (definitions, references)
+    }
+
+    fn walk(arena: &Arena, id: NodeId, definitions: &mut Ids, references: &mut Ids) {
+        match &arena[id] {
+            Node::FootnoteDefinition(x, _) => definitions.push((x.id(), x.number)),
+            Node::FootnoteReference(x, _) => references.push((x.id(), x.number)),
+            _ => {}
+        }
+        for child in arena[id].children().into_iter().flatten() {
+            walk(arena, *child, definitions, references);
+        }
+    }
+
+    fn ids(ids: &[(&str, usize)]) -> Ids {
+        ids.iter().map(|(id, n)| (id.to_string(), *n)).collect()
+    }
+
+    #[test]
+    fn numbers_footnotes_in_order_of_first_reference() {
+        let (definitions, references) =
+            footnotes("[^b] [^a] [^b]\n\n[^a]: A.\n\n[^c]: C.\n\n[^b]: B.");
+        assert_eq!(definitions, ids(&[("fn-b", 1), ("fn-a", 2), ("fn-c", 3)]));
+        assert_eq!(
+            references,
+            ids(&[("fnref-b", 1), ("fnref-a", 2), ("fnref-b-2", 1)])
+        );
+        // Undefined labels are left unnumbered.
+        assert_eq!(footnotes("[^x]").1, ids(&[("fnref-x", 0)]));
+    }
+
+    #[test]
+    fn numbers_references_inside_definitions_after() {
+        let (definitions, _) = footnotes("[^a]\n\n[^b]: B.\n\n[^a]: A [^b].");
+        assert_eq!(definitions, ids(&[("fn-a", 1), ("fn-b", 2)]));
+    }
+
+    #[test]
+    fn links_references_and_definitions_both_ways() {
+        let (arena, root) = parse("x[^a] y[^a]\n\n[^a]: A.");
+        assert_eq!(
+            to_html(&arena, root),
+            "<p>x<sup class=\"footnote-reference\" id=\"fnref-a\"><a href=\"#fn-a\">1</a></sup> \
+             y<sup class=\"footnote-reference\" id=\"fnref-a-2\"><a href=\"#fn-a\">1</a></sup></p>\n\
+             <div class=\"footnote-definition\" id=\"fn-a\">\
+             <sup class=\"footnote-definition-label\">1</sup>\n<p>A.</p>\n\
+             <a class=\"footnote-backref\" href=\"#fnref-a\">\u{21a9}</a></div>\n"
+        );
+    }
+
+    #[test]
+    fn renumbers_when_footnotes_are_added_or_removed() {
+        let markdown = "[^a] [^b]\n\n[^a]: A.\n\n[^b]: B.";
+        assert_eq!(footnotes(markdown).0, ids(&[("fn-a", 1), ("fn-b", 2)]));
+        let added = "[^c] [^a] [^b]\n\n[^a]: A.\n\n[^b]: B.\n\n[^c]: C.";
+        assert_eq!(
+            footnotes(added).0,
+            ids(&[("fn-c", 1), ("fn-a", 2), ("fn-b", 3)])
+        );
+        let removed = "[^b]\n\n[^b]: B.";
+        assert_eq!(footnotes(removed).0, ids(&[("fn-b", 1)]));
+        // A definition losing its reference moves after the referenced ones.
+        let unreferenced = "[^b]\n\n[^a]: A.\n\n[^b]: B.";
+        assert_eq!(footnotes(unreferenced).0, ids(&[("fn-b", 1), ("fn-a", 2)]));
+    }
+
+    #[test]
+    fn gives_duplicate_definitions_their_own_id() {
+        let (definitions, references) = footnotes("[^a]\n\n[^a]: First.\n\n[^a]: Second.");
+        assert_eq!(definitions, ids(&[("fn-a", 1), ("fn-a-2", 2)]));
+        assert_eq!(references, ids(&[("fnref-a", 1)]));
+        let (arena, root) = parse("[^a]: First.\n\n[^a]: Second.\n\n[^a]: Third.");
+        let html = to_html(&arena, root);
+        for id in ["fn-a", "fn-a-2", "fn-a-3"] {
+            assert_eq!(
+                html.matches(&format!("id=\"{}\"", id)).count(),
+                1,
+                "{}",
+                html
+            );
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use serde_json::{Map, Value};
+
+use crate::{
+    event::LineIndex,
+    mdast::{ElementNode, Node, Toml, Yaml},
+};
+
+/// Split a leading front matter block off `source`: YAML between `---`
+/// fences, closed by `---` or `...`, or TOML between `+++` fences.
+///
+/// Returns the `Yaml` or `Toml` node and the offset where the markdown
+/// starts.
+pub fn split(source: &str, lines: &LineIndex) -> Option<(Node, usize)> {
+    let mut rest = source.split_inclusive('\n');
+    let fence = rest.next()?.trim_end();
+    if fence != "---" && fence != "+++" {
+        return None;
+    }
+    let start = source.find('\n')? + 1;
+    let mut offset = start;
+    for line in rest {
+        let trimmed = line.trim_end();
+        let closed = trimmed == fence || (fence == "---" && trimmed == "...");
+        if closed {
+            let value = source[start..offset]
+                .trim_end_matches('\n')
+                .trim_end_matches('\r')
+                .to_string();
+            let position = Some(lines.position(0..offset + trimmed.len()));
+            let node = match fence {
+                "---" => Node::Yaml(
+                    Yaml {
+                        value: Some(value),
+                        position,
+                    },
+                    ElementNode::default(),
+                ),
+                _ => Node::Toml(
+                    Toml {
+                        value: Some(value),
+                        position,
+                    },
+                    ElementNode::default(),
+                ),
+            };
+            return Some((node, offset + line.len()));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Parse the value of a front matter node into JSON.
+///
+/// TOML dates become strings, as they are in YAML. An empty block gives an
+/// empty object.
+pub fn to_json(node: &Node) -> Result<Value, String> {
+    let value = node.value().map(String::as_str).unwrap_or_default();
+    if value.trim().is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+    match node {
+        Node::Yaml(_, _) => serde_yaml::from_str(value).map_err(|err| err.to_string()),
+        Node::Toml(_, _) => value
+            .parse::<toml::Value>()
+            .map(toml_to_json)
+            .map_err(|err| err.to_string()),
+        _ => Err("not a front matter node".to_string()),
+    }
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(x) => Value::from(x),
+        toml::Value::Integer(x) => Value::from(x),
+        toml::Value::Float(x) => Value::from(x),
+        toml::Value::Boolean(x) => Value::from(x),
+        toml::Value::Datetime(x) => Value::from(x.to_string()),
+        toml::Value::Array(x) => Value::from(x.into_iter().map(toml_to_json).collect::<Vec<_>>()),
+        toml::Value::Table(x) => Value::Object(
+            x.into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::CompileContext;
+
+    /// The front matter of `source` as JSON, and where the markdown starts.
+    fn front_matter(source: &str) -> Option<(Result<Value, String>, usize)> {
+        let (node, offset) = split(source, &LineIndex::new(source))?;
+        Some((to_json(&node), offset))
+    }
+
+    #[test]
+    fn splits_yaml_closed_by_dashes_or_dots() {
+        for source in ["---\na: 1\n---\n# x", "---\na: 1\n...\n# x"] {
+            let (value, offset) = front_matter(source).unwrap();
+            assert_eq!(value.unwrap(), json!({"a": 1}));
+            assert_eq!(&source[offset..], "# x");
+        }
+        // Only YAML closes with dots.
+        assert!(front_matter("+++\na = 1\n...\n").is_none());
+    }
+
+    #[test]
+    fn splits_toml_with_dates_as_strings() {
+        let source = "+++\ntitle = \"t\"\ndate = 1979-05-27T07:32:00Z\ntags = [\"a\"]\n+++\nx";
+        let (value, offset) = front_matter(source).unwrap();
+        assert_eq!(
+            value.unwrap(),
+            json!({"title": "t", "date": "1979-05-27T07:32:00Z", "tags": ["a"]})
+        );
+        assert_eq!(&source[offset..], "x");
+    }
+
+    #[test]
+    fn splits_crlf_lines() {
+        let source = "---\r\na: 1\r\nb: [x]\r\n---\r\n# x";
+        let (node, offset) = split(source, &LineIndex::new(source)).unwrap();
+        assert_eq!(node.value().unwrap(), "a: 1\r\nb: [x]");
+        assert_eq!(to_json(&node).unwrap(), json!({"a": 1, "b": ["x"]}));
+        assert_eq!(&source[offset..], "# x");
+    }
+
+    #[test]
+    fn leaves_unclosed_fences_to_the_markdown() {
+        assert!(front_matter("---\na: 1\n").is_none());
+        assert!(front_matter("---\na: 1\n+++\n").is_none());
+        assert!(front_matter("---").is_none());
+        assert!(front_matter("# x\n---\na: 1\n---\n").is_none());
+    }
+
+    #[test]
+    fn gives_an_empty_object_for_an_empty_block() {
+        let (value, offset) = front_matter("---\n---\nx").unwrap();
+        assert_eq!(value.unwrap(), json!({}));
+        assert_eq!(offset, 8);
+        let (value, _) = front_matter("+++\n  \n+++\n").unwrap();
+        assert_eq!(value.unwrap(), json!({}));
+    }
+
+    #[test]
+    fn reports_invalid_front_matter() {
+        let (value, _) = front_matter("---\na: [1\n---\n").unwrap();
+        assert!(value.is_err());
+        let (value, _) = front_matter("+++\na = \n+++\n").unwrap();
+        assert!(value.is_err());
+    }
+
+    #[test]
+    fn gives_the_front_matter_of_the_document() {
+        let mut context = CompileContext::new();
+        context.run("---\na: [1, 2]\n---\n# x");
+        assert_eq!(
+            context.frontmatter_json().unwrap().as_deref(),
+            Some("{\"a\":[1,2]}")
+        );
+        context.run("---\na: [1\n---\n# x");
+        assert!(context.frontmatter_json().is_err());
+        context.run("# x");
+        assert_eq!(context.frontmatter_json(), Ok(None));
+    }
+}
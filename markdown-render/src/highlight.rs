@@ -0,0 +1,904 @@
+/// A run of code and the class it is highlighted with, `None` for plain text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token<'a> {
+    pub class: Option<&'static str>,
+    pub text: &'a str,
+}
+
+/// Split `code` into highlighted tokens with the grammar of `lang`.
+///
+/// Code in a language without a grammar is a single plain token; empty code
+/// has no tokens.
+pub fn highlight<'a>(lang: Option<&str>, code: &'a str) -> Vec<Token<'a>> {
+    match lang.and_then(grammar) {
+        Some(grammar) => Tokenizer::new(grammar, code).run(),
+        None if code.is_empty() => Vec::new(),
+        None => vec![Token {
+            class: None,
+            text: code,
+        }],
+    }
+}
+
+/// The grammar of the language named by the info string `lang`, e.g. `rust`
+/// or `rs,ignore`.
+pub fn grammar(lang: &str) -> Option<&'static Grammar> {
+    let name = lang.split(',').next().unwrap_or_default().to_lowercase();
+    GRAMMARS
+        .iter()
+        .find(|grammar| grammar.names.contains(&name.as_str()))
+}
+
+/// Lexical rules of a language, interpreted by the tokenizer.
+pub struct Grammar {
+    /// Info string names of the language.
+    pub names: &'static [&'static str],
+    pub line_comments: &'static [&'static str],
+    /// Line comments only start at the beginning of a word, as in shells.
+    pub comment_after_space: bool,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    /// String delimiters, longest first.
+    pub strings: &'static [Quote],
+    /// Identifiers that may prefix a string, e.g. `r` in `r"raw"`.
+    pub string_prefixes: &'static [&'static str],
+    pub keywords: &'static [&'static str],
+    /// Keywords compare case-insensitively.
+    pub ignore_case: bool,
+    pub types: &'static [&'static str],
+    /// Capitalized identifiers are types.
+    pub capitalized_types: bool,
+    pub literals: &'static [&'static str],
+    pub builtins: &'static [&'static str],
+    /// Keywords naming the function that follows them.
+    pub function_keywords: &'static [&'static str],
+    /// Keywords naming the type that follows them.
+    pub type_keywords: &'static [&'static str],
+    /// Identifiers followed by `(` are function calls.
+    pub calls: bool,
+    pub attributes: Attributes,
+    pub keys: Keys,
+    /// Rust-style char literals, lifetimes, raw strings and macros.
+    pub rust: bool,
+    /// `$name` and `${...}` are variables.
+    pub variables: bool,
+    /// Numbers may contain `-` and `:`, for TOML dates and times.
+    pub dates: bool,
+    /// `[section]` lines are headers.
+    pub sections: bool,
+    pub operators: &'static str,
+}
+
+pub struct Quote {
+    pub open: &'static str,
+    pub close: &'static str,
+    /// Backslash escapes the next character.
+    pub escapes: bool,
+    pub multiline: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Attributes {
+    None,
+    /// `#[...]`, balanced.
+    Bracketed,
+    /// `@name.name`.
+    Decorators,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Keys {
+    None,
+    /// Strings followed by `:`.
+    Colon,
+    /// Bare or quoted keys at the start of a line, followed by `=` or `.`.
+    Assign,
+}
+
+const fn quote(open: &'static str, close: &'static str, escapes: bool, multiline: bool) -> Quote {
+    Quote {
+        open,
+        close,
+        escapes,
+        multiline,
+    }
+}
+
+const BASE: Grammar = Grammar {
+    names: &[],
+    line_comments: &[],
+    comment_after_space: false,
+    block_comment: None,
+    strings: &[],
+    string_prefixes: &[],
+    keywords: &[],
+    ignore_case: false,
+    types: &[],
+    capitalized_types: false,
+    literals: &[],
+    builtins: &[],
+    function_keywords: &[],
+    type_keywords: &[],
+    calls: false,
+    attributes: Attributes::None,
+    keys: Keys::None,
+    rust: false,
+    variables: false,
+    dates: false,
+    sections: false,
+    operators: "",
+};
+
+static GRAMMARS: &[Grammar] = &[
+    Grammar {
+        names: &["rust", "rs"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        strings: &[quote("\"", "\"", true, true)],
+        string_prefixes: &["b", "r", "br"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+            "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+            "type", "unsafe", "use", "where", "while",
+        ],
+        types: &[
+            "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+            "i64", "i128", "isize", "f32", "f64",
+        ],
+        capitalized_types: true,
+        literals: &["true", "false"],
+        function_keywords: &["fn"],
+        type_keywords: &["struct", "enum", "trait", "type", "union"],
+        calls: true,
+        attributes: Attributes::Bracketed,
+        rust: true,
+        operators: "+-*/%=<>!&|^?",
+        ..BASE
+    },
+    Grammar {
+        names: &[
+            "typescript",
+            "ts",
+            "tsx",
+            "javascript",
+            "js",
+            "jsx",
+            "mjs",
+            "cjs",
+        ],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        strings: &[
+            quote("\"", "\"", true, false),
+            quote("'", "'", true, false),
+            quote("`", "`", true, true),
+        ],
+        keywords: &[
+            "abstract",
+            "as",
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "debugger",
+            "declare",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "enum",
+            "export",
+            "extends",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "get",
+            "if",
+            "implements",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "keyof",
+            "let",
+            "namespace",
+            "new",
+            "of",
+            "private",
+            "protected",
+            "public",
+            "readonly",
+            "return",
+            "set",
+            "static",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "try",
+            "type",
+            "typeof",
+            "var",
+            "void",
+            "while",
+            "with",
+            "yield",
+        ],
+        types: &[
+            "any", "bigint", "boolean", "never", "number", "object", "string", "symbol", "unknown",
+        ],
+        capitalized_types: true,
+        literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+        builtins: &[
+            "console",
+            "document",
+            "window",
+            "globalThis",
+            "require",
+            "module",
+        ],
+        function_keywords: &["function"],
+        type_keywords: &[
+            "class",
+            "interface",
+            "type",
+            "enum",
+            "extends",
+            "implements",
+        ],
+        calls: true,
+        attributes: Attributes::Decorators,
+        operators: "+-*/%=<>!&|^~?",
+        ..BASE
+    },
+    Grammar {
+        names: &["python", "py", "python3"],
+        line_comments: &["#"],
+        strings: &[
+            quote("\"\"\"", "\"\"\"", true, true),
+            quote("'''", "'''", true, true),
+            quote("\"", "\"", true, false),
+            quote("'", "'", true, false),
+        ],
+        string_prefixes: &[
+            "r", "u", "b", "f", "br", "rb", "fr", "rf", "R", "U", "B", "F", "BR", "RB", "FR", "RF",
+        ],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+            "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in",
+            "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+            "with", "yield", "match", "case", "self",
+        ],
+        types: &[
+            "int",
+            "float",
+            "complex",
+            "str",
+            "bytes",
+            "bool",
+            "list",
+            "dict",
+            "set",
+            "frozenset",
+            "tuple",
+            "object",
+            "type",
+        ],
+        capitalized_types: true,
+        literals: &["True", "False", "None"],
+        builtins: &[
+            "abs",
+            "all",
+            "any",
+            "enumerate",
+            "filter",
+            "getattr",
+            "hasattr",
+            "isinstance",
+            "iter",
+            "len",
+            "map",
+            "max",
+            "min",
+            "next",
+            "open",
+            "print",
+            "range",
+            "repr",
+            "reversed",
+            "round",
+            "setattr",
+            "sorted",
+            "sum",
+            "super",
+            "zip",
+        ],
+        function_keywords: &["def"],
+        type_keywords: &["class"],
+        calls: true,
+        attributes: Attributes::Decorators,
+        operators: "+-*/%=<>!&|^~",
+        ..BASE
+    },
+    Grammar {
+        names: &["json", "jsonc", "json5"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        strings: &[quote("\"", "\"", true, false)],
+        literals: &["true", "false", "null"],
+        keys: Keys::Colon,
+        ..BASE
+    },
+    Grammar {
+        names: &["toml"],
+        line_comments: &["#"],
+        strings: &[
+            quote("\"\"\"", "\"\"\"", true, true),
+            quote("'''", "'''", false, true),
+            quote("\"", "\"", true, false),
+            quote("'", "'", false, false),
+        ],
+        literals: &["true", "false", "inf", "nan"],
+        keys: Keys::Assign,
+        dates: true,
+        sections: true,
+        operators: "=",
+        ..BASE
+    },
+    Grammar {
+        names: &["bash", "sh", "shell", "zsh", "console"],
+        line_comments: &["#"],
+        comment_after_space: true,
+        strings: &[quote("\"", "\"", true, true), quote("'", "'", false, true)],
+        keywords: &[
+            "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case",
+            "esac", "in", "function", "select", "return", "break", "continue", "local", "export",
+            "readonly", "declare",
+        ],
+        literals: &["true", "false"],
+        builtins: &[
+            "alias", "cd", "echo", "eval", "exec", "exit", "printf", "pwd", "read", "set", "shift",
+            "source", "test", "trap", "unset",
+        ],
+        function_keywords: &["function"],
+        variables: true,
+        operators: "|&;<>=",
+        ..BASE
+    },
+    Grammar {
+        names: &["sql", "mysql", "postgresql", "postgres", "sqlite", "pgsql"],
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        strings: &[
+            quote("'", "'", false, true),
+            quote("\"", "\"", false, false),
+            quote("`", "`", false, false),
+        ],
+        keywords: &[
+            "add",
+            "all",
+            "alter",
+            "and",
+            "as",
+            "asc",
+            "begin",
+            "between",
+            "by",
+            "case",
+            "check",
+            "column",
+            "commit",
+            "constraint",
+            "create",
+            "cross",
+            "database",
+            "default",
+            "delete",
+            "desc",
+            "distinct",
+            "drop",
+            "else",
+            "end",
+            "exists",
+            "foreign",
+            "from",
+            "full",
+            "group",
+            "having",
+            "if",
+            "in",
+            "index",
+            "inner",
+            "insert",
+            "into",
+            "is",
+            "join",
+            "key",
+            "left",
+            "like",
+            "limit",
+            "not",
+            "offset",
+            "on",
+            "or",
+            "order",
+            "outer",
+            "primary",
+            "references",
+            "returning",
+            "right",
+            "rollback",
+            "select",
+            "set",
+            "table",
+            "then",
+            "transaction",
+            "union",
+            "unique",
+            "update",
+            "values",
+            "view",
+            "when",
+            "where",
+            "with",
+        ],
+        ignore_case: true,
+        types: &[
+            "bigint",
+            "blob",
+            "boolean",
+            "char",
+            "date",
+            "decimal",
+            "double",
+            "float",
+            "int",
+            "integer",
+            "json",
+            "numeric",
+            "real",
+            "serial",
+            "smallint",
+            "text",
+            "time",
+            "timestamp",
+            "uuid",
+            "varchar",
+        ],
+        literals: &["null", "true", "false"],
+        builtins: &[
+            "avg", "coalesce", "count", "lower", "max", "min", "now", "round", "sum", "upper",
+        ],
+        calls: true,
+        operators: "+-*/%=<>!|",
+        ..BASE
+    },
+];
+
+struct Tokenizer<'a> {
+    grammar: &'static Grammar,
+    code: &'a str,
+    pos: usize,
+    /// Start of the pending plain text.
+    plain: usize,
+    /// Last identifier, while only whitespace follows it.
+    last_word: Option<&'a str>,
+    tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(grammar: &'static Grammar, code: &'a str) -> Tokenizer<'a> {
+        Tokenizer {
+            grammar,
+            code,
+            pos: 0,
+            plain: 0,
+            last_word: None,
+            tokens: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Vec<Token<'a>> {
+        while self.pos < self.code.len() {
+            match self.next_token() {
+                Some((class, end)) => {
+                    self.push(class, end);
+                }
+                None => {
+                    let c = self.rest().chars().next().unwrap();
+                    if !c.is_whitespace() {
+                        self.last_word = None;
+                    }
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+        self.flush();
+        self.tokens
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.code[self.pos..]
+    }
+
+    fn flush(&mut self) {
+        if self.plain < self.pos {
+            self.tokens.push(Token {
+                class: None,
+                text: &self.code[self.plain..self.pos],
+            });
+        }
+    }
+
+    fn push(&mut self, class: Option<&'static str>, end: usize) {
+        if class.is_some() {
+            self.flush();
+            self.tokens.push(Token {
+                class,
+                text: &self.code[self.pos..end],
+            });
+            self.plain = end;
+        }
+        self.pos = end;
+    }
+
+    /// The class and end of the token starting at the current position, if
+    /// any. Identifiers without a class are returned as plain tokens so that
+    /// they are skipped whole.
+    fn next_token(&mut self) -> Option<(Option<&'static str>, usize)> {
+        let grammar = self.grammar;
+        let rest = self.rest();
+        let c = rest.chars().next()?;
+        let previous = self.code[..self.pos].chars().next_back();
+
+        let comment_allowed =
+            !grammar.comment_after_space || previous.is_none_or(char::is_whitespace);
+        if comment_allowed && grammar.line_comments.iter().any(|x| rest.starts_with(x)) {
+            return Some((Some("hl-comment"), self.line_end(self.pos)));
+        }
+        if let Some((open, close)) = grammar.block_comment {
+            if rest.starts_with(open) {
+                let end = self.find(close, self.pos + open.len(), true, false);
+                return Some((Some("hl-comment"), end));
+            }
+        }
+        if grammar.sections && c == '[' && self.at_line_start(self.pos) {
+            let line_end = self.line_end(self.pos);
+            let end = self.code[self.pos..line_end]
+                .rfind(']')
+                .map_or(line_end, |idx| self.pos + idx + 1);
+            return Some((Some("hl-section"), end));
+        }
+        match grammar.attributes {
+            Attributes::Bracketed if rest.starts_with("#[") || rest.starts_with("#![") => {
+                return Some((Some("hl-attribute"), self.balanced(self.pos + 1)));
+            }
+            Attributes::Decorators if c == '@' => {
+                let end = self.pos
+                    + 1
+                    + rest[1..]
+                        .find(|c: char| !is_ident(c) && c != '.')
+                        .unwrap_or(rest.len() - 1);
+                if end > self.pos + 1 {
+                    return Some((Some("hl-attribute"), end));
+                }
+            }
+            _ => {}
+        }
+        if let Some(end) = self.string(self.pos) {
+            let class = if self.is_key(end) {
+                "hl-property"
+            } else {
+                "hl-string"
+            };
+            return Some((Some(class), end));
+        }
+        if grammar.rust && c == '\'' {
+            return Some(self.quote_or_lifetime());
+        }
+        if grammar.variables && c == '$' {
+            return Some((Some("hl-variable"), self.variable()));
+        }
+        if c.is_ascii_digit() {
+            return Some((Some("hl-number"), self.number()));
+        }
+        if is_ident_start(c) {
+            let end = self.pos + rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+            let word = &self.code[self.pos..end];
+            if grammar.string_prefixes.contains(&word) {
+                if let Some(end) = self.string(end) {
+                    return Some((Some("hl-string"), end));
+                }
+            }
+            let class = self.classify(word, end);
+            self.last_word = Some(word);
+            return Some(match class {
+                Some("hl-macro") => (class, end + 1),
+                _ => (class, end),
+            });
+        }
+        if grammar.operators.contains(c) {
+            return Some((Some("hl-operator"), self.pos + c.len_utf8()));
+        }
+        None
+    }
+
+    fn classify(&self, word: &str, end: usize) -> Option<&'static str> {
+        let grammar = self.grammar;
+        let contains = |words: &[&str]| match grammar.ignore_case {
+            true => words.iter().any(|x| x.eq_ignore_ascii_case(word)),
+            false => words.contains(&word),
+        };
+        if grammar.keys == Keys::Assign && self.is_key(end) {
+            Some("hl-property")
+        } else if contains(grammar.keywords) {
+            Some("hl-keyword")
+        } else if contains(grammar.literals) {
+            Some("hl-literal")
+        } else if grammar.rust
+            && self.code[end..].starts_with('!')
+            && !self.code[end..].starts_with("!=")
+        {
+            Some("hl-macro")
+        } else if self
+            .last_word
+            .is_some_and(|x| grammar.function_keywords.contains(&x))
+        {
+            Some("hl-function")
+        } else if self
+            .last_word
+            .is_some_and(|x| grammar.type_keywords.contains(&x))
+            || contains(grammar.types)
+            || (grammar.capitalized_types && word.starts_with(|c: char| c.is_ascii_uppercase()))
+        {
+            Some("hl-type")
+        } else if contains(grammar.builtins) {
+            Some("hl-builtin")
+        } else if grammar.calls && self.code[end..].starts_with('(') {
+            Some("hl-function")
+        } else {
+            None
+        }
+    }
+
+    /// The end of the string starting at `start`, if one does.
+    fn string(&self, start: usize) -> Option<usize> {
+        let rest = &self.code[start..];
+        if self.grammar.rust && start > self.pos {
+            // Raw strings: `r"..."`, `r#"..."#`.
+            let prefix = &self.code[self.pos..start];
+            if prefix.contains('r') {
+                let hashes = rest.len() - rest.trim_start_matches('#').len();
+                if !rest[hashes..].starts_with('"') {
+                    return None;
+                }
+                let close = format!("\"{}", "#".repeat(hashes));
+                return Some(self.find(&close, start + hashes + 1, true, false));
+            }
+        }
+        let quote = self
+            .grammar
+            .strings
+            .iter()
+            .find(|x| rest.starts_with(x.open))?;
+        Some(self.find(
+            quote.close,
+            start + quote.open.len(),
+            quote.multiline,
+            quote.escapes,
+        ))
+    }
+
+    /// The end of `close` searched from `from`, or of the line or code when
+    /// it is not found.
+    fn find(&self, close: &str, from: usize, multiline: bool, escapes: bool) -> usize {
+        let limit = match multiline {
+            true => self.code.len(),
+            false => self.line_end(from),
+        };
+        let mut idx = from;
+        while idx < limit {
+            let rest = &self.code[idx..limit];
+            if rest.starts_with(close) {
+                return idx + close.len();
+            }
+            let mut chars = rest.chars();
+            let c = chars.next().unwrap();
+            idx += c.len_utf8();
+            if escapes && c == '\\' {
+                idx += chars.next().map_or(0, char::len_utf8);
+            }
+        }
+        limit
+    }
+
+    /// A Rust char literal, or a lifetime or label.
+    fn quote_or_lifetime(&self) -> (Option<&'static str>, usize) {
+        let rest = &self.rest()[1..];
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some('\\'), _) => (Some("hl-string"), self.find("'", self.pos + 2, false, true)),
+            (Some(c), Some('\'')) => (Some("hl-string"), self.pos + 1 + c.len_utf8() + 1),
+            (Some(c), _) if is_ident_start(c) => {
+                let len = rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+                (Some("hl-label"), self.pos + 1 + len)
+            }
+            _ => (None, self.pos + 1),
+        }
+    }
+
+    /// A shell variable: `$name`, `${...}`, `$1` or a special parameter.
+    fn variable(&self) -> usize {
+        let rest = &self.rest()[1..];
+        match rest.chars().next() {
+            Some('{') => self.balanced(self.pos + 1),
+            Some(c) if is_ident(c) => {
+                self.pos + 1 + rest.find(|c| !is_ident(c)).unwrap_or(rest.len())
+            }
+            Some(c) if "@#?$!*-".contains(c) => self.pos + 2,
+            _ => self.pos + 1,
+        }
+    }
+
+    fn number(&self) -> usize {
+        let bytes = self.code.as_bytes();
+        let hex = self.rest().starts_with("0x") || self.rest().starts_with("0X");
+        let mut idx = self.pos;
+        while idx < bytes.len() {
+            let b = bytes[idx];
+            let next_digit = bytes.get(idx + 1).is_some_and(u8::is_ascii_digit);
+            let continues = b.is_ascii_alphanumeric()
+                || b == b'_'
+                || (b == b'.' && next_digit)
+                || ((b == b'+' || b == b'-')
+                    && !hex
+                    && next_digit
+                    && matches!(bytes[idx - 1], b'e' | b'E'))
+                || (self.grammar.dates && (b == b'-' || b == b':') && next_digit);
+            if !continues {
+                break;
+            }
+            idx += 1;
+        }
+        idx
+    }
+
+    /// The end of the bracketed group opening at `open`, or of the line.
+    fn balanced(&self, open: usize) -> usize {
+        let line_end = self.line_end(open);
+        let mut depth = 0;
+        for (idx, c) in self.code[open..line_end].char_indices() {
+            match c {
+                '[' | '{' | '(' => depth += 1,
+                ']' | '}' | ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return open + idx + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        line_end
+    }
+
+    /// Whether the string or identifier ending at `end` is a key.
+    fn is_key(&self, end: usize) -> bool {
+        let next = self.code[end..]
+            .trim_start_matches([' ', '\t'])
+            .chars()
+            .next();
+        match self.grammar.keys {
+            Keys::None => false,
+            Keys::Colon => next == Some(':'),
+            Keys::Assign => {
+                let before = self.code[self.line_start(self.pos)..self.pos].trim();
+                (before.is_empty() || before.ends_with('.'))
+                    && matches!(next, Some('=') | Some('.'))
+            }
+        }
+    }
+
+    fn line_start(&self, idx: usize) -> usize {
+        self.code[..idx].rfind('\n').map_or(0, |x| x + 1)
+    }
+
+    fn line_end(&self, idx: usize) -> usize {
+        self.code[idx..]
+            .find('\n')
+            .map_or(self.code.len(), |x| idx + x)
+    }
+
+    fn at_line_start(&self, idx: usize) -> bool {
+        self.code[self.line_start(idx)..idx].trim().is_empty()
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::highlight;
+
+    /// The classes and texts of the tokens of `code`, which must cover it.
+    fn tokens<'a>(lang: &str, code: &'a str) -> Vec<(Option<&'static str>, &'a str)> {
+        let tokens = highlight(Some(lang), code);
+        let text: String = tokens.iter().map(|token| token.text).collect();
+        assert_eq!(text, code, "{}", lang);
+        tokens
+            .into_iter()
+            .map(|token| (token.class, token.text))
+            .collect()
+    }
+
+    #[test]
+    fn unterminated_strings_run_to_the_end() {
+        let cases = [
+            ("rust", "x = \"abc\\\nx", "\"abc\\\nx"),
+            ("rust", "x = r#\"raw\"", "r#\"raw\""),
+            ("ts", "x = `tpl ${a", "`tpl ${a"),
+            ("python", "x = '''abc\nx", "'''abc\nx"),
+            ("json", "{\"a", "\"a"),
+            ("toml", "a = \"\"\"x\ny", "\"\"\"x\ny"),
+            ("bash", "echo \"abc", "\"abc"),
+            ("sql", "SELECT 'abc", "'abc"),
+            ("rust", "x = \"é", "\"é"),
+        ];
+        for (lang, code, string) in cases {
+            let tokens = tokens(lang, code);
+            assert_eq!(
+                tokens.last(),
+                Some(&(Some("hl-string"), string)),
+                "{}",
+                lang
+            );
+        }
+    }
+
+    #[test]
+    fn single_line_strings_end_at_the_line_ending() {
+        for lang in ["ts", "python"] {
+            let tokens = tokens(lang, "'abc\nnext");
+            assert_eq!(tokens[0], (Some("hl-string"), "'abc"), "{}", lang);
+        }
+    }
+
+    #[test]
+    fn unterminated_comments_run_to_the_end() {
+        let cases = [
+            ("rust", "/* open\nstill"),
+            ("ts", "/* open\nstill"),
+            ("sql", "/* open\nstill"),
+            ("python", "# open"),
+            ("bash", "# open"),
+            ("toml", "# open"),
+        ];
+        for (lang, comment) in cases {
+            let code = format!("x {}", comment);
+            let tokens = tokens(lang, &code);
+            assert_eq!(
+                tokens.last(),
+                Some(&(Some("hl-comment"), comment)),
+                "{}",
+                lang
+            );
+        }
+    }
+
+    #[test]
+    fn code_without_a_grammar_is_plain() {
+        assert_eq!(tokens("brainfuck", "+[-]"), [(None, "+[-]")]);
+        assert!(highlight(None, "").is_empty());
+    }
+}
@@ -0,0 +1,380 @@
+use crate::{
+    highlight::highlight,
+    markdown::is_block,
+    math::to_mathml,
+    mdast::{reference_close, Arena, Node, NodeId},
+    sanitize::HtmlPolicy,
+};
+
+/// Render a document tree to an HTML string, without a DOM. Raw HTML is
+/// sanitized with the default [`HtmlPolicy`].
+pub fn to_html(arena: &Arena, id: NodeId) -> String {
+    to_html_with_policy(arena, id, &HtmlPolicy::default())
+}
+
+/// Render a document tree to an HTML string, with raw HTML rendered under
+/// `policy`.
+pub fn to_html_with_policy(arena: &Arena, id: NodeId, policy: &HtmlPolicy) -> String {
+    let mut out = String::new();
+    render(arena, &arena[id], policy, &mut out);
+    out
+}
+
+fn render(arena: &Arena, node: &Node, policy: &HtmlPolicy, out: &mut String) {
+    match node {
+        Node::Root(_, _) => render_children(arena, node, policy, out),
+        Node::BlockQuote(_, _) => {
+            out.push_str("<blockquote>\n");
+            render_children(arena, node, policy, out);
+            out.push_str("</blockquote>\n");
+        }
+        Node::FootnoteDefinition(x, _) => {
+            out.push_str("<div class=\"footnote-definition\" id=\"");
+            escape_html(&x.id(), out);
+            out.push_str("\"><sup class=\"footnote-definition-label\">");
+            out.push_str(&x.number.to_string());
+            out.push_str("</sup>\n");
+            render_children(arena, node, policy, out);
+            out.push_str("<a class=\"footnote-backref\" href=\"#fnref-");
+            escape_html(&x.label, out);
+            out.push_str("\">\u{21a9}</a></div>\n");
+        }
+        Node::FootnoteReference(x, _) if x.number == 0 => {
+            out.push_str("<sup class=\"footnote-reference\">[^");
+            escape_html(&x.label, out);
+            out.push_str("]</sup>");
+        }
+        Node::FootnoteReference(x, _) => {
+            out.push_str("<sup class=\"footnote-reference\" id=\"");
+            escape_html(&x.id(), out);
+            out.push_str("\"><a href=\"#fn-");
+            escape_html(&x.label, out);
+            out.push_str("\">");
+            out.push_str(&x.number.to_string());
+            out.push_str("</a></sup>");
+        }
+        Node::List(x, _) => {
+            match x.start {
+                Some(1) => out.push_str("<ol>\n"),
+                Some(start) => {
+                    out.push_str("<ol start=\"");
+                    out.push_str(&start.to_string());
+                    out.push_str("\">\n");
+                }
+                None => out.push_str("<ul>\n"),
+            }
+            render_children(arena, node, policy, out);
+            out.push_str(if x.start.is_some() {
+                "</ol>\n"
+            } else {
+                "</ul>\n"
+            });
+        }
+        Node::ListItem(x, _) => {
+            match x.checked {
+                Some(checked) => {
+                    out.push_str("<li class=\"task-list-item\"><input type=\"checkbox\"");
+                    if checked {
+                        out.push_str(" checked=\"\"");
+                    }
+                    out.push_str(" disabled=\"\" />");
+                }
+                None => out.push_str("<li>"),
+            }
+            // Blocks start on their own line, the content of tight
+            // paragraphs follows the marker.
+            for child in node.children().unwrap() {
+                let child = &arena[*child];
+                if is_block(child) && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                render(arena, child, policy, out);
+            }
+            out.push_str("</li>");
+            out.push('\n');
+        }
+        Node::SoftBreak(_, _) => out.push('\n'),
+        Node::HardBreak(_, _) => out.push_str("<br />\n"),
+        Node::ThematicBreak(_, _) => out.push_str("<hr />\n"),
+        Node::InlineCode(x, _) => {
+            out.push_str("<code>");
+            escape_html(x.value.as_deref().unwrap_or_default(), out);
+            out.push_str("</code>");
+        }
+        Node::InlineMath(x, _) => {
+            out.push_str("<span class=\"math math-inline\">");
+            out.push_str(&to_mathml(x.value.as_deref().unwrap_or_default(), false));
+            out.push_str("</span>");
+        }
+        Node::Math(x, _) => {
+            out.push_str("<div class=\"math math-display\">");
+            out.push_str(&to_mathml(x.value.as_deref().unwrap_or_default(), true));
+            out.push_str("</div>\n");
+        }
+        // Front matter is metadata, not content.
+        Node::Toml(_, _) | Node::Yaml(_, _) => {}
+        Node::Delete(_, _) => wrap(arena, node, "del", policy, out),
+        Node::Emphasis(_, _) => wrap(arena, node, "em", policy, out),
+        Node::Strong(_, _) => wrap(arena, node, "strong", policy, out),
+        Node::Html(x, _) => {
+            out.push_str(&policy.render(x.value.as_deref().unwrap_or_default()));
+            out.push('\n');
+            render_children(arena, node, policy, out);
+            if let Some(close) = &x.close {
+                out.push_str(&policy.render(close));
+                out.push('\n');
+            }
+        }
+        Node::InlineHtml(x, _) => {
+            out.push_str(&policy.render(x.value.as_deref().unwrap_or_default()));
+            render_children(arena, node, policy, out);
+            if let Some(close) = &x.close {
+                out.push_str(&policy.render(close));
+            }
+        }
+        Node::Image(x, _) => {
+            let url = x.url.as_deref().unwrap_or_default();
+            push_image(
+                policy.link_url(url).as_deref(),
+                &x.alt,
+                x.title.as_deref(),
+                out,
+            );
+        }
+        Node::Link(x, _) => {
+            let url = x.url.as_deref().unwrap_or_default();
+            push_link(policy.link_url(url).as_deref(), x.title.as_deref(), out);
+            render_children(arena, node, policy, out);
+            out.push_str("</a>");
+        }
+        Node::LinkReference(x, _) => match &x.url {
+            Some(url) => {
+                push_link(policy.link_url(url).as_deref(), x.title.as_deref(), out);
+                render_children(arena, node, policy, out);
+                out.push_str("</a>");
+            }
+            // Undefined: the reference is text.
+            None => {
+                out.push('[');
+                render_children(arena, node, policy, out);
+                escape_html(&reference_close(x.link_type, &x.label), out);
+            }
+        },
+        Node::ImageReference(x, _) => match &x.url {
+            Some(url) => push_image(
+                policy.link_url(url).as_deref(),
+                &x.alt,
+                x.title.as_deref(),
+                out,
+            ),
+            None => {
+                out.push_str("![");
+                escape_html(&x.alt, out);
+                escape_html(&reference_close(x.link_type, &x.label), out);
+            }
+        },
+        // Definitions are not rendered.
+        Node::Definition(_, _) => {}
+        Node::Text(x, _) => escape_html(x.value.as_deref().unwrap_or_default(), out),
+        Node::Code(x, _) => {
+            out.push_str("<pre><code");
+            if let Some(lang) = x.lang.as_deref().filter(|lang| !lang.is_empty()) {
+                out.push_str(" class=\"language-");
+                escape_html(lang, out);
+                out.push('"');
+            }
+            out.push('>');
+            for token in highlight(x.lang.as_deref(), x.value.as_deref().unwrap_or_default()) {
+                match token.class {
+                    Some(class) => {
+                        out.push_str("<span class=\"");
+                        out.push_str(class);
+                        out.push_str("\">");
+                        escape_html(token.text, out);
+                        out.push_str("</span>");
+                    }
+                    None => escape_html(token.text, out),
+                }
+            }
+            out.push_str("</code></pre>\n");
+        }
+        Node::Heading(x, _) => {
+            out.push_str(&format!("<h{}", x.depth));
+            if !x.slug.is_empty() {
+                out.push_str(" id=\"");
+                escape_html(&x.slug, out);
+                out.push('"');
+            }
+            if !x.classes.is_empty() {
+                out.push_str(" class=\"");
+                escape_html(&x.classes.join(" "), out);
+                out.push('"');
+            }
+            out.push('>');
+            render_children(arena, node, policy, out);
+            out.push_str(&format!("</h{}>\n", x.depth));
+        }
+        Node::Table(_, _) => {
+            out.push_str("<table>\n");
+            let children = node.children().unwrap();
+            let mut rows = children.iter().peekable();
+            if let Some(head) = rows.next_if(|row| matches!(arena[**row], Node::TableHead(_, _))) {
+                out.push_str("<thead>\n");
+                render_row(arena, &arena[*head], "th", policy, out);
+                out.push_str("</thead>\n");
+            }
+            if rows.peek().is_some() {
+                out.push_str("<tbody>\n");
+                for row in rows {
+                    render_row(arena, &arena[*row], "td", policy, out);
+                }
+                out.push_str("</tbody>\n");
+            }
+            out.push_str("</table>\n");
+        }
+        // Rows and cells outside of a table.
+        Node::TableHead(_, _) | Node::TableRow(_, _) => {
+            render_row(arena, node, "td", policy, out);
+        }
+        Node::TableCell(_, _) => wrap(arena, node, "td", policy, out),
+        Node::Paragraph(_, _) => {
+            wrap(arena, node, "p", policy, out);
+            out.push('\n');
+        }
+    }
+}
+
+fn render_children(arena: &Arena, node: &Node, policy: &HtmlPolicy, out: &mut String) {
+    if let Some(children) = node.children() {
+        for child in children {
+            render(arena, &arena[*child], policy, out);
+        }
+    }
+}
+
+fn wrap(arena: &Arena, node: &Node, tag: &str, policy: &HtmlPolicy, out: &mut String) {
+    out.push('<');
+    out.push_str(tag);
+    out.push('>');
+    render_children(arena, node, policy, out);
+    out.push_str("</");
+    out.push_str(tag);
+    out.push('>');
+}
+
+fn render_row(arena: &Arena, row: &Node, cell_tag: &str, policy: &HtmlPolicy, out: &mut String) {
+    out.push_str("<tr>\n");
+    for cell in row.children().unwrap() {
+        out.push('<');
+        out.push_str(cell_tag);
+        let cell = &arena[*cell];
+        if let Node::TableCell(x, _) = cell {
+            if let Some(style) = x.style() {
+                out.push_str(" style=\"");
+                out.push_str(style);
+                out.push('"');
+            }
+        }
+        out.push('>');
+        render_children(arena, cell, policy, out);
+        out.push_str("</");
+        out.push_str(cell_tag);
+        out.push_str(">\n");
+    }
+    out.push_str("</tr>\n");
+}
+
+/// The opening tag of a link, without `href` when its URL is not allowed.
+fn push_link(url: Option<&str>, title: Option<&str>, out: &mut String) {
+    out.push_str("<a");
+    if let Some(url) = url {
+        out.push_str(" href=\"");
+        escape_html(url, out);
+        out.push('"');
+    }
+    push_title(title, out);
+    out.push('>');
+}
+
+/// An image, without `src` when its URL is not allowed.
+fn push_image(url: Option<&str>, alt: &str, title: Option<&str>, out: &mut String) {
+    out.push_str("<img");
+    if let Some(url) = url {
+        out.push_str(" src=\"");
+        escape_html(url, out);
+        out.push('"');
+    }
+    out.push_str(" alt=\"");
+    escape_html(alt, out);
+    out.push('"');
+    push_title(title, out);
+    out.push_str(" />");
+}
+
+fn push_title(title: Option<&str>, out: &mut String) {
+    if let Some(title) = title.filter(|title| !title.is_empty()) {
+        out.push_str(" title=\"");
+        escape_html(title, out);
+        out.push('"');
+    }
+}
+
+/// Escape `text` for use in HTML text and double-quoted attribute values.
+pub fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn html(markdown: &str) -> String {
+        let (arena, root) = parse(markdown);
+        to_html(&arena, root)
+    }
+
+    #[test]
+    fn writes_start_only_when_not_one() {
+        assert_eq!(html("1. a"), "<ol>\n<li>a</li>\n</ol>\n");
+        assert_eq!(html("3. a"), "<ol start=\"3\">\n<li>a</li>\n</ol>\n");
+        assert_eq!(html("0. a"), "<ol start=\"0\">\n<li>a</li>\n</ol>\n");
+    }
+
+    #[test]
+    fn leaves_paragraphs_of_tight_lists_unwrapped() {
+        assert_eq!(html("- a\n- b"), "<ul>\n<li>a</li>\n<li>b</li>\n</ul>\n");
+    }
+
+    #[test]
+    fn wraps_paragraphs_of_loose_lists() {
+        assert_eq!(
+            html("- a\n\n- b"),
+            "<ul>\n<li>\n<p>a</p>\n</li>\n<li>\n<p>b</p>\n</li>\n</ul>\n"
+        );
+        assert_eq!(
+            html("1. a\n\n   b"),
+            "<ol>\n<li>\n<p>a</p>\n<p>b</p>\n</li>\n</ol>\n"
+        );
+    }
+
+    #[test]
+    fn nests_lists_inside_their_item() {
+        assert_eq!(
+            html("- a\n  1. b\n  2. c\n- d"),
+            "<ul>\n<li>a\n<ol>\n<li>b</li>\n<li>c</li>\n</ol>\n</li>\n<li>d</li>\n</ul>\n"
+        );
+        assert_eq!(
+            html("- a\n\n  - b\n  - c"),
+            "<ul>\n<li>\n<p>a</p>\n<ul>\n<li>b</li>\n<li>c</li>\n</ul>\n</li>\n</ul>\n"
+        );
+    }
+}
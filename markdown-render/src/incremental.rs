@@ -0,0 +1,41 @@
+use crate::mdast::{Arena, Node, NodeId};
+
+/// Whether the byte `offset` of `source` starts a line that follows a blank
+/// line: top-level blocks starting there do not depend on the blocks before.
+pub fn after_blank_line(source: &str, offset: usize) -> bool {
+    let before = match source[..offset].strip_suffix('\n') {
+        Some(before) => before,
+        None => return false,
+    };
+    let line = &before[before.rfind('\n').map_or(0, |idx| idx + 1)..];
+    line.trim().is_empty()
+}
+
+/// Whether the top-level `blocks` parsed up to the byte `end` of `source`
+/// are closed there, so that `next`, the block found at `end` before the
+/// edit, still starts a new block after them.
+pub fn resyncs(source: &str, end: usize, arena: &Arena, blocks: &[NodeId], next: &Node) -> bool {
+    // Indented, it could continue a list item or a code block.
+    if source[end..].starts_with(char::is_whitespace) {
+        return false;
+    }
+    let last = match blocks.last() {
+        Some(last) => &arena[*last],
+        None => return true,
+    };
+    // The end of the last line with content, blank lines follow.
+    let content = source[..end].trim_end().len();
+    let content_end = source[content..end]
+        .find('\n')
+        .map_or(end, |idx| content + idx + 1);
+    match last {
+        // Items of the same kind would join the list.
+        Node::List(_, _) => !matches!(next, Node::List(_, _)),
+        // An unclosed HTML block takes the blank lines in.
+        Node::Html(x, _) if x.value.as_deref().unwrap_or_default().ends_with('\n') => false,
+        // So does an unclosed code fence.
+        block => block
+            .position()
+            .is_some_and(|position| position.end.offset <= content_end),
+    }
+}
@@ -0,0 +1,885 @@
+use std::slice;
+
+use pulldown_cmark::{Alignment, LinkType};
+use serde_json::{Map, Value};
+
+use crate::{event::Position, footnote, mdast::*, reference, slug};
+
+/// Export a document tree as mdast.
+///
+/// Tight list items get their phrasing content wrapped in paragraphs, soft
+/// breaks are folded into the surrounding text and the table head becomes
+/// the first `tableRow`, as remark does.
+pub fn to_mdast(arena: &Arena, id: NodeId) -> Value {
+    export(arena, &arena[id], arena.position(id).as_ref(), false)
+}
+
+fn export(arena: &Arena, node: &Node, position: Option<&Position>, tight: bool) -> Value {
+    let mut object = Map::new();
+    object.insert("type".to_string(), Value::from(type_name(node)));
+    match node {
+        Node::List(x, _) => {
+            object.insert("ordered".to_string(), Value::from(x.start.is_some()));
+            object.insert("start".to_string(), Value::from(x.start));
+            object.insert("spread".to_string(), Value::from(x.spread));
+        }
+        Node::ListItem(x, _) => {
+            object.insert("spread".to_string(), Value::from(!tight));
+            object.insert("checked".to_string(), Value::from(x.checked));
+        }
+        Node::Heading(x, _) => {
+            object.insert("depth".to_string(), Value::from(x.depth));
+            // As remark-heading-id and rehype-slug set them.
+            let mut properties = Map::new();
+            properties.insert("id".to_string(), Value::from(x.slug.clone()));
+            if !x.classes.is_empty() {
+                properties.insert("className".to_string(), Value::from(x.classes.clone()));
+            }
+            let mut data = Map::new();
+            if let Some(id) = &x.id {
+                data.insert("id".to_string(), Value::from(id.clone()));
+            }
+            data.insert("hProperties".to_string(), Value::Object(properties));
+            object.insert("data".to_string(), Value::Object(data));
+        }
+        Node::FootnoteDefinition(x, _) => {
+            object.insert(
+                "identifier".to_string(),
+                Value::from(x.label.to_lowercase()),
+            );
+            object.insert("label".to_string(), Value::from(x.label.clone()));
+        }
+        Node::FootnoteReference(x, _) => {
+            object.insert(
+                "identifier".to_string(),
+                Value::from(x.label.to_lowercase()),
+            );
+            object.insert("label".to_string(), Value::from(x.label.clone()));
+        }
+        Node::Code(x, _) => {
+            let lang = x.lang.clone().filter(|lang| !lang.is_empty());
+            let value = x.value.as_deref().unwrap_or_default();
+            object.insert("lang".to_string(), Value::from(lang));
+            object.insert("meta".to_string(), Value::Null);
+            object.insert(
+                "value".to_string(),
+                Value::from(value.strip_suffix('\n').unwrap_or(value)),
+            );
+        }
+        Node::Text(_, _)
+        | Node::InlineCode(_, _)
+        | Node::InlineMath(_, _)
+        | Node::Html(_, _)
+        | Node::InlineHtml(_, _)
+        | Node::Toml(_, _)
+        | Node::Yaml(_, _) => {
+            object.insert("value".to_string(), Value::from(node.value().cloned()));
+        }
+        Node::SoftBreak(_, _) => {
+            object.insert("value".to_string(), Value::from("\n"));
+        }
+        Node::Math(x, _) => {
+            let value = x.value.as_deref().unwrap_or_default();
+            object.insert("meta".to_string(), Value::Null);
+            object.insert(
+                "value".to_string(),
+                Value::from(value.strip_suffix('\n').unwrap_or(value)),
+            );
+        }
+        Node::Link(x, _) => {
+            object.insert("url".to_string(), Value::from(x.url.clone()));
+            object.insert("title".to_string(), title(&x.title));
+        }
+        Node::Image(x, _) => {
+            object.insert("url".to_string(), Value::from(x.url.clone()));
+            object.insert("title".to_string(), title(&x.title));
+            object.insert("alt".to_string(), Value::from(x.alt.clone()));
+        }
+        Node::Definition(x, _) => {
+            insert_label(&mut object, &x.label);
+            object.insert("url".to_string(), Value::from(x.url.clone()));
+            object.insert("title".to_string(), title(&x.title));
+        }
+        Node::LinkReference(x, _) => {
+            insert_label(&mut object, &x.label);
+            object.insert(
+                "referenceType".to_string(),
+                Value::from(reference::reference_type(x.link_type)),
+            );
+        }
+        Node::ImageReference(x, _) => {
+            insert_label(&mut object, &x.label);
+            object.insert(
+                "referenceType".to_string(),
+                Value::from(reference::reference_type(x.link_type)),
+            );
+            object.insert("alt".to_string(), Value::from(x.alt.clone()));
+        }
+        Node::Table(x, _) => {
+            let align: Vec<Value> = x
+                .alignment
+                .iter()
+                .map(|alignment| match alignment {
+                    Alignment::Left => Value::from("left"),
+                    Alignment::Right => Value::from("right"),
+                    Alignment::Center => Value::from("center"),
+                    Alignment::None => Value::Null,
+                })
+                .collect();
+            object.insert("align".to_string(), Value::from(align));
+        }
+        _ => {}
+    }
+    // Html is a literal, paired tags are exported by `export_children`.
+    let children = match node {
+        Node::Html(_, _) | Node::InlineHtml(_, _) => None,
+        _ => node.children(),
+    };
+    if let Some(children) = children {
+        let children = match node {
+            Node::ListItem(_, _) if tight => wrap_phrasing(arena, children),
+            Node::List(x, _) => {
+                let tight = !x.spread;
+                children
+                    .iter()
+                    .map(|child| {
+                        let position = arena.position(*child);
+                        export(arena, &arena[*child], position.as_ref(), tight)
+                    })
+                    .collect()
+            }
+            Node::Root(x, _) => {
+                let mut values = Vec::new();
+                if let Some(frontmatter) = x.frontmatter {
+                    let position = arena.position(frontmatter);
+                    values.push(export(arena, &arena[frontmatter], position.as_ref(), false));
+                }
+                values.extend(export_children(arena, &x.flow(arena)));
+                values
+            }
+            _ => export_children(arena, children),
+        };
+        object.insert("children".to_string(), Value::from(children));
+    }
+    if let Some(position) = position.and_then(export_position) {
+        object.insert("position".to_string(), position);
+    }
+    Value::Object(object)
+}
+
+fn export_children(arena: &Arena, children: &[NodeId]) -> Vec<Value> {
+    let mut values: Vec<Value> = Vec::new();
+    // Text node being extended with the following text and soft breaks.
+    let mut text: Option<(String, Option<Position>)> = None;
+    for id in children {
+        let child = &arena[*id];
+        match child {
+            Node::Text(_, _) | Node::SoftBreak(_, _) => {
+                let value = match child {
+                    Node::SoftBreak(_, _) => "\n",
+                    _ => child.value().map(String::as_str).unwrap_or_default(),
+                };
+                match &mut text {
+                    Some((text, position)) => {
+                        text.push_str(value);
+                        *position = match (position.take(), arena.position(*id)) {
+                            (Some(start), Some(end)) => Some(Position::new(start.start, end.end)),
+                            _ => None,
+                        };
+                    }
+                    None => text = Some((value.to_string(), arena.position(*id))),
+                }
+            }
+            _ => {
+                if let Some((value, position)) = text.take() {
+                    values.push(text_value(value, position.as_ref()));
+                }
+                // Paired tags are exported as the nodes they were parsed from.
+                let paired = match child {
+                    Node::InlineHtml(x, _) if x.close.is_some() => {
+                        Some((&x.value, &x.children, &x.close, &x.close_position))
+                    }
+                    Node::Html(x, _) if !x.content.is_empty() => {
+                        Some((&x.value, &x.children, &x.close, &x.close_position))
+                    }
+                    _ => None,
+                };
+                match paired {
+                    Some((open, children, close, close_position)) => {
+                        let open = open.as_deref().unwrap_or_default();
+                        let position = arena.position(*id).map(|position| {
+                            Position::new(position.start.clone(), position.start.after(open))
+                        });
+                        values.push(html_value(open, position.as_ref()));
+                        values.extend(export_children(arena, children));
+                        if let Some(close) = close {
+                            let close_position = arena.shifted(*id, close_position.as_ref());
+                            values.push(html_value(close, close_position.as_ref()));
+                        }
+                    }
+                    None => {
+                        let position = arena.position(*id);
+                        values.push(export(arena, child, position.as_ref(), false))
+                    }
+                }
+            }
+        }
+    }
+    if let Some((value, position)) = text {
+        values.push(text_value(value, position.as_ref()));
+    }
+    values
+}
+
+/// Children of a tight list item, with each run of phrasing content wrapped
+/// in a paragraph.
+fn wrap_phrasing(arena: &Arena, children: &[NodeId]) -> Vec<Value> {
+    let mut values = Vec::new();
+    let mut run: Vec<NodeId> = Vec::new();
+    let flush = |run: &mut Vec<NodeId>, values: &mut Vec<Value>| {
+        if run.is_empty() {
+            return;
+        }
+        let start = arena.position(run[0]).map(|x| x.start);
+        let end = arena.position(run[run.len() - 1]).map(|x| x.end);
+        let mut object = Map::new();
+        object.insert("type".to_string(), Value::from("paragraph"));
+        object.insert(
+            "children".to_string(),
+            Value::from(export_children(arena, run)),
+        );
+        if let (Some(start), Some(end)) = (start, end) {
+            if let Some(position) = export_position(&Position::new(start, end)) {
+                object.insert("position".to_string(), position);
+            }
+        }
+        values.push(Value::Object(object));
+        run.clear();
+    };
+    for child in children {
+        if is_phrasing(&arena[*child]) {
+            run.push(*child);
+        } else {
+            flush(&mut run, &mut values);
+            values.extend(export_children(arena, slice::from_ref(child)));
+        }
+    }
+    flush(&mut run, &mut values);
+    values
+}
+
+fn text_value(value: String, position: Option<&Position>) -> Value {
+    let mut object = Map::new();
+    object.insert("type".to_string(), Value::from("text"));
+    object.insert("value".to_string(), Value::from(value));
+    if let Some(position) = position.and_then(export_position) {
+        object.insert("position".to_string(), position);
+    }
+    Value::Object(object)
+}
+
+/// An mdast `html` node: inline elements are exported as their opening
+/// tag, their content and their closing tag, as remark parses them.
+fn html_value(value: &str, position: Option<&Position>) -> Value {
+    let mut object = Map::new();
+    object.insert("type".to_string(), Value::from("html"));
+    object.insert("value".to_string(), Value::from(value));
+    if let Some(position) = position.and_then(export_position) {
+        object.insert("position".to_string(), position);
+    }
+    Value::Object(object)
+}
+
+/// The mdast `identifier` and `label` of a reference or definition.
+fn insert_label(object: &mut Map<String, Value>, label: &str) {
+    object.insert(
+        "identifier".to_string(),
+        Value::from(reference::normalize(label)),
+    );
+    object.insert("label".to_string(), Value::from(label));
+}
+
+fn export_position(position: &Position) -> Option<Value> {
+    serde_json::to_value(position).ok()
+}
+
+fn title(title: &Option<String>) -> Value {
+    Value::from(title.clone().filter(|title| !title.is_empty()))
+}
+
+fn type_name(node: &Node) -> &'static str {
+    match node {
+        Node::Root(_, _) => "root",
+        Node::BlockQuote(_, _) => "blockquote",
+        Node::FootnoteDefinition(_, _) => "footnoteDefinition",
+        Node::FootnoteReference(_, _) => "footnoteReference",
+        Node::List(_, _) => "list",
+        Node::Toml(_, _) => "toml",
+        Node::Yaml(_, _) => "yaml",
+        Node::SoftBreak(_, _) | Node::Text(_, _) => "text",
+        Node::HardBreak(_, _) => "break",
+        Node::ThematicBreak(_, _) => "thematicBreak",
+        Node::InlineCode(_, _) => "inlineCode",
+        Node::InlineMath(_, _) => "inlineMath",
+        Node::Math(_, _) => "math",
+        Node::Delete(_, _) => "delete",
+        Node::Emphasis(_, _) => "emphasis",
+        Node::Html(_, _) | Node::InlineHtml(_, _) => "html",
+        Node::Image(_, _) => "image",
+        Node::ImageReference(_, _) => "imageReference",
+        Node::Link(_, _) => "link",
+        Node::LinkReference(_, _) => "linkReference",
+        Node::Definition(_, _) => "definition",
+        Node::Strong(_, _) => "strong",
+        Node::Code(_, _) => "code",
+        Node::Heading(_, _) => "heading",
+        Node::Table(_, _) => "table",
+        Node::TableHead(_, _) | Node::TableRow(_, _) => "tableRow",
+        Node::TableCell(_, _) => "tableCell",
+        Node::ListItem(_, _) => "listItem",
+        Node::Paragraph(_, _) => "paragraph",
+    }
+}
+
+fn is_phrasing(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::SoftBreak(_, _)
+            | Node::HardBreak(_, _)
+            | Node::InlineCode(_, _)
+            | Node::InlineMath(_, _)
+            | Node::Delete(_, _)
+            | Node::Emphasis(_, _)
+            | Node::FootnoteReference(_, _)
+            | Node::Image(_, _)
+            | Node::ImageReference(_, _)
+            | Node::Link(_, _)
+            | Node::LinkReference(_, _)
+            | Node::InlineHtml(_, _)
+            | Node::Strong(_, _)
+            | Node::Text(_, _)
+    )
+}
+
+/// Import an mdast tree, as produced by [`to_mdast`] or by remark, into
+/// `arena`. Returns the id of its root.
+pub fn from_mdast(arena: &mut Arena, value: &Value) -> Result<NodeId, String> {
+    let nodes = import(arena, value, Parent::Other)?;
+    if nodes.len() != 1 || !matches!(arena[nodes[0]], Node::Root(_, _)) {
+        return Err("expected an mdast `root` node".to_string());
+    }
+    let root = nodes[0];
+    // Definitions may come anywhere in the tree, references resolve to them.
+    reference::take_definitions(arena, root);
+    let definitions = match &arena[root] {
+        Node::Root(x, _) => x.definitions.clone(),
+        _ => Vec::new(),
+    };
+    reference::resolve(arena, root, &definitions);
+    footnote::collect(arena, root);
+    slug::assign(arena, root);
+    Ok(root)
+}
+
+/// Where a node is imported, for the mdast constructs without a direct
+/// counterpart in the tree.
+#[derive(Clone, Copy, PartialEq)]
+enum Parent {
+    /// An item of a tight list: its paragraphs are unwrapped.
+    TightItem,
+    /// The first row of a table: it becomes the table head.
+    TableHead,
+    /// Phrasing content: `html` nodes are inline.
+    Phrasing,
+    Other,
+}
+
+fn import(arena: &mut Arena, value: &Value, parent: Parent) -> Result<Vec<NodeId>, String> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| "expected an mdast node".to_string())?;
+    let kind = object
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| "mdast node without a `type`".to_string())?;
+    let position = import_position(object.get("position"));
+    let data = object.get("data");
+    let string = |key: &str| object.get(key).and_then(Value::as_str).map(str::to_string);
+    let label = || {
+        string("label")
+            .or_else(|| string("identifier"))
+            .ok_or_else(|| format!("mdast `{}` without a `label`", kind))
+    };
+
+    let children_of = |arena: &mut Arena, parent: Parent| -> Result<Vec<NodeId>, String> {
+        let mut children = Vec::new();
+        let values = match object.get("children") {
+            Some(Value::Array(values)) => values.as_slice(),
+            _ => &[],
+        };
+        for (idx, child) in values.iter().enumerate() {
+            let parent = match parent {
+                Parent::TableHead if idx > 0 => Parent::Other,
+                parent => parent,
+            };
+            children.extend(import(arena, child, parent)?);
+        }
+        Ok(children)
+    };
+
+    let node = match kind {
+        "root" => {
+            let mut children = children_of(arena, Parent::Other)?;
+            let frontmatter = match children.first() {
+                Some(first) if matches!(arena[*first], Node::Toml(_, _) | Node::Yaml(_, _)) => {
+                    Some(children.remove(0))
+                }
+                _ => None,
+            };
+            Node::Root(
+                Root {
+                    children,
+                    position: None,
+                    frontmatter,
+                    definitions: Vec::new(),
+                },
+                ElementNode::default(),
+            )
+        }
+        "paragraph" if parent == Parent::TightItem => {
+            return children_of(arena, Parent::Phrasing);
+        }
+        "paragraph" => Node::Paragraph(
+            Paragraph {
+                children: children_of(arena, Parent::Phrasing)?,
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "heading" => Node::Heading(
+            Heading {
+                children: children_of(arena, Parent::Phrasing)?,
+                position,
+                depth: object.get("depth").and_then(Value::as_u64).unwrap_or(1) as u8,
+                id: data
+                    .and_then(|data| data.get("id"))
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                classes: match data
+                    .and_then(|data| data.get("hProperties"))
+                    .and_then(|properties| properties.get("className"))
+                {
+                    Some(Value::Array(classes)) => classes
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect(),
+                    _ => Vec::new(),
+                },
+                slug: String::new(),
+            },
+            ElementNode::default(),
+        ),
+        "blockquote" => Node::BlockQuote(
+            BlockQuote {
+                children: children_of(arena, Parent::Other)?,
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "list" => {
+            let ordered = object.get("ordered").and_then(Value::as_bool) == Some(true);
+            let spread = object.get("spread").and_then(Value::as_bool) == Some(true);
+            let items = match object.get("children") {
+                Some(Value::Array(items)) => items.as_slice(),
+                _ => &[],
+            };
+            let spread = spread
+                || items
+                    .iter()
+                    .any(|item| item.get("spread").and_then(Value::as_bool) == Some(true));
+            Node::List(
+                List {
+                    start: if ordered {
+                        Some(object.get("start").and_then(Value::as_u64).unwrap_or(1))
+                    } else {
+                        None
+                    },
+                    children: children_of(
+                        arena,
+                        if spread {
+                            Parent::Other
+                        } else {
+                            Parent::TightItem
+                        },
+                    )?,
+                    position,
+                    spread,
+                },
+                ElementNode::default(),
+            )
+        }
+        "listItem" => {
+            let item_parent = match parent {
+                Parent::TightItem => Parent::TightItem,
+                _ => Parent::Other,
+            };
+            Node::ListItem(
+                ListItem {
+                    children: children_of(arena, item_parent)?,
+                    position,
+                    checked: object.get("checked").and_then(Value::as_bool),
+                    checked_position: None,
+                },
+                ElementNode::default(),
+            )
+        }
+        "code" => {
+            let value = string("value").unwrap_or_default();
+            Node::Code(
+                Code {
+                    lang: Some(string("lang").unwrap_or_default()),
+                    value: if value.is_empty() {
+                        None
+                    } else {
+                        Some(value + "\n")
+                    },
+                    position,
+                    value_position: None,
+                },
+                ElementNode::default(),
+            )
+        }
+        "footnoteDefinition" => Node::FootnoteDefinition(
+            FootnoteDefinition {
+                children: children_of(arena, Parent::Other)?,
+                position,
+                label: label()?,
+                number: 0,
+                occurrence: 0,
+            },
+            ElementNode::default(),
+        ),
+        "footnoteReference" => Node::FootnoteReference(
+            FootnoteReference {
+                position,
+                label: label()?,
+                number: 0,
+                occurrence: 0,
+            },
+            ElementNode::default(),
+        ),
+        "inlineMath" => Node::InlineMath(
+            InlineMath {
+                value: string("value"),
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "math" => Node::Math(
+            Math {
+                value: string("value"),
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "toml" => Node::Toml(
+            Toml {
+                value: string("value"),
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "yaml" => Node::Yaml(
+            Yaml {
+                value: string("value"),
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "html" if parent == Parent::Phrasing => Node::InlineHtml(
+            InlineHtml {
+                value: string("value"),
+                close: None,
+                children: Vec::new(),
+                position,
+                close_position: None,
+            },
+            ElementNode::default(),
+        ),
+        "html" => Node::Html(
+            Html {
+                value: string("value"),
+                close: None,
+                children: Vec::new(),
+                content: Vec::new(),
+                position,
+                close_position: None,
+            },
+            ElementNode::default(),
+        ),
+        "table" => {
+            let alignment = match object.get("align") {
+                Some(Value::Array(align)) => align
+                    .iter()
+                    .map(|align| match align.as_str() {
+                        Some("left") => Alignment::Left,
+                        Some("right") => Alignment::Right,
+                        Some("center") => Alignment::Center,
+                        _ => Alignment::None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            let table = Table {
+                children: children_of(arena, Parent::TableHead)?,
+                position,
+                alignment,
+            };
+            table.align_cells(arena);
+            Node::Table(table, ElementNode::default())
+        }
+        "tableRow" if parent == Parent::TableHead => Node::TableHead(
+            TableHead {
+                children: children_of(arena, Parent::Other)?,
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "tableRow" => Node::TableRow(
+            TableRow {
+                children: children_of(arena, Parent::Other)?,
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "tableCell" => Node::TableCell(
+            TableCell {
+                children: children_of(arena, Parent::Phrasing)?,
+                position,
+                align: Alignment::None,
+            },
+            ElementNode::default(),
+        ),
+        "text" => {
+            return Ok(import_text(
+                arena,
+                &string("value").unwrap_or_default(),
+                position,
+            ))
+        }
+        "break" => Node::HardBreak(HardBreak { position }, ElementNode::default()),
+        "thematicBreak" => Node::ThematicBreak(ThematicBreak { position }, ElementNode::default()),
+        "inlineCode" => Node::InlineCode(
+            InlineCode {
+                value: string("value"),
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "emphasis" => Node::Emphasis(
+            Emphasis {
+                children: children_of(arena, Parent::Phrasing)?,
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "strong" => Node::Strong(
+            Strong {
+                children: children_of(arena, Parent::Phrasing)?,
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "delete" => Node::Delete(
+            Delete {
+                children: children_of(arena, Parent::Phrasing)?,
+                position,
+            },
+            ElementNode::default(),
+        ),
+        "link" => Node::Link(
+            Link {
+                children: children_of(arena, Parent::Phrasing)?,
+                position,
+                link_type: LinkType::Inline,
+                url: Some(string("url").unwrap_or_default()),
+                title: Some(string("title").unwrap_or_default()),
+            },
+            ElementNode::default(),
+        ),
+        "image" => Node::Image(
+            Image {
+                position,
+                alt: string("alt").unwrap_or_default(),
+                link_type: LinkType::Inline,
+                url: Some(string("url").unwrap_or_default()),
+                title: Some(string("title").unwrap_or_default()),
+            },
+            ElementNode::default(),
+        ),
+        "definition" => Node::Definition(
+            Definition {
+                position,
+                label: label()?,
+                url: Some(string("url").unwrap_or_default()),
+                title: string("title"),
+            },
+            ElementNode::default(),
+        ),
+        "linkReference" => Node::LinkReference(
+            LinkReference {
+                children: children_of(arena, Parent::Phrasing)?,
+                position,
+                label: label()?,
+                link_type: reference::link_type(&string("referenceType").unwrap_or_default()),
+                url: None,
+                title: None,
+            },
+            ElementNode::default(),
+        ),
+        "imageReference" => Node::ImageReference(
+            ImageReference {
+                position,
+                alt: string("alt").unwrap_or_default(),
+                label: label()?,
+                link_type: reference::link_type(&string("referenceType").unwrap_or_default()),
+                url: None,
+                title: None,
+            },
+            ElementNode::default(),
+        ),
+        _ => return Err(format!("unsupported mdast node type `{}`", kind)),
+    };
+    let id = arena.push(node);
+    arena.compute_hash(id);
+    Ok(vec![id])
+}
+
+/// Split a text value on its line endings, which are soft breaks in the tree.
+fn import_text(arena: &mut Arena, value: &str, position: Option<Position>) -> Vec<NodeId> {
+    let lines: Vec<&str> = value.split('\n').collect();
+    // Without line endings the position still applies to the whole text.
+    let position = if lines.len() == 1 { position } else { None };
+    let mut nodes = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        if idx > 0 {
+            let soft_break = arena.push(Node::SoftBreak(
+                SoftBreak { position: None },
+                ElementNode::default(),
+            ));
+            arena.compute_hash(soft_break);
+            nodes.push(soft_break);
+        }
+        if !line.is_empty() {
+            let text = arena.push(Node::Text(
+                Text {
+                    value: Some(line.to_string()),
+                    position: position.clone(),
+                },
+                TextNode::default(),
+            ));
+            arena.compute_hash(text);
+            nodes.push(text);
+        }
+    }
+    nodes
+}
+
+fn import_position(value: Option<&Value>) -> Option<Position> {
+    serde_json::from_value(value?.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::html::to_html;
+
+    fn import_html(value: Value) -> String {
+        let mut arena = Arena::default();
+        let root = from_mdast(&mut arena, &value).unwrap();
+        to_html(&arena, root)
+    }
+
+    fn reference(label: &str) -> Value {
+        json!({
+            "type": "paragraph",
+            "children": [{
+                "type": "linkReference",
+                "label": label,
+                "referenceType": "shortcut",
+                "children": [{"type": "text", "value": label}]
+            }]
+        })
+    }
+
+    fn definition(label: &str) -> Value {
+        json!({"type": "definition", "label": label, "url": "/url"})
+    }
+
+    #[test]
+    fn resolves_references_by_normalized_label() {
+        for (reference_label, definition_label) in [
+            ("ẞ", "SS"),
+            ("Foo  bar", " foo\tBAR "),
+            ("ref\\[", "ref\\["),
+        ] {
+            let html = import_html(json!({
+                "type": "root",
+                "children": [reference(reference_label), definition(definition_label)]
+            }));
+            assert!(
+                html.contains("href=\"/url\""),
+                "{:?}: {}",
+                reference_label,
+                html
+            );
+        }
+        let html = import_html(json!({
+            "type": "root",
+            "children": [reference("foo\\!"), definition("foo!")]
+        }));
+        assert!(!html.contains("href"), "{}", html);
+    }
+
+    #[test]
+    fn round_trips_references_definitions_and_autolinks() {
+        for markdown in [
+            "[full][Ref] [collapsed][] [ref] ![img][ref] ![ref][]\n\n[ref]: /url \"Title\"",
+            "[undefined] and [also][undefined]",
+            "> [nested]\n>\n> [nested]: </a b> 'T'",
+            "<https://example.com> <me@example.com> and www.example.com",
+            "1. [a](/a)\n2. [b]\n\n[b]: /b",
+        ] {
+            let (arena, root) = crate::parse(markdown);
+            let value = to_mdast(&arena, root);
+            let mut imported = Arena::default();
+            let id = from_mdast(&mut imported, &value).unwrap();
+            // The root of an imported tree has no position, as it has no
+            // source to re-parse.
+            assert_eq!(
+                to_mdast(&imported, id)["children"],
+                value["children"],
+                "{:?}",
+                markdown
+            );
+            assert_eq!(
+                to_html(&imported, id),
+                to_html(&arena, root),
+                "{:?}",
+                markdown
+            );
+        }
+    }
+
+    #[test]
+    fn imports_thematic_breaks() {
+        let html = import_html(json!({
+            "type": "root",
+            "children": [{"type": "thematicBreak"}]
+        }));
+        assert_eq!(html, "<hr />\n");
+    }
+}
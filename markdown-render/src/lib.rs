@@ -1,29 +1,51 @@
+mod autolink;
 mod diff;
-mod event;
-mod mdast;
+pub mod dom;
+pub mod event;
+mod footnote;
+mod frontmatter;
+pub mod highlight;
+pub mod html;
+mod incremental;
+pub mod json;
+pub mod markdown;
+pub mod math;
+pub mod mdast;
+pub mod memory;
+mod patch;
+mod raw_html;
+pub mod reference;
+pub mod sanitize;
+pub mod slug;
 mod utils;
+pub mod validate;
 
-use std::{cell::RefCell, rc::Rc};
+use std::collections::HashMap;
 use utils::set_panic_hook;
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "dom")]
+use wasm_bindgen::JsCast;
 
 // use crate::diff::update;
 use crate::diff::*;
-use crate::event::Position;
+use crate::dom::{DocNode, Dom};
+use crate::event::{LineIndex, Position, SourceEdit};
+use crate::math::ParseEvent;
 use crate::mdast::*;
+use crate::patch::{apply, dom_at, dom_path, Patch};
+use crate::reference::Unresolved;
+use crate::sanitize::{HtmlMode, HtmlPolicy};
 use pulldown_cmark::Event;
-use pulldown_cmark::{CodeBlockKind, Tag};
+use pulldown_cmark::{Alignment, BrokenLink, CodeBlockKind, LinkType, Tag};
 use pulldown_cmark::{Options, Parser};
 use std::io::{self};
-use web_sys::window;
-
-#[macro_use]
-extern crate lazy_static;
+use std::mem;
+use std::ops::Range;
+#[cfg(feature = "dom")]
+use web_sys::{window, Document, Element};
 
 #[macro_use]
 extern crate serde_derive;
-extern crate console_error_panic_hook;
-use std::panic;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -39,59 +61,228 @@ extern "C" {
     //   pub fn __random() -> u8;
 }
 
+/// A rendered DOM node: the tree nodes leading to it and its remaining path.
+type Located = (Vec<NodeId>, Vec<usize>);
+
+/// What [`CompileContext::parse_blocks`] finds besides the blocks.
+struct Blocks {
+    definitions: Vec<NodeId>,
+    unresolved: Vec<Unresolved>,
+}
+
+/// The parser extensions rendered.
+fn options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    options
+}
+
 #[derive(Debug, Clone)]
 #[wasm_bindgen]
 pub struct CompileContext {
-    old_root: Option<Rc<RefCell<Node>>>,
-    root: Rc<RefCell<Node>>,
+    /// The nodes of the tree rendered last and of the one rendered next.
+    arena: Arena,
+    old_root: Option<NodeId>,
+    root: NodeId,
+    /// The number of nodes in the tree of `root`, to tell when the arena is
+    /// worth compacting.
+    live: usize,
     stack: Vec<Node>,
+    patches: Vec<Patch>,
+    lines: LineIndex,
+    /// The node rendered into, `#render` when not given.
+    #[cfg(feature = "dom")]
+    target: Option<web_sys::Node>,
+    /// How raw HTML is rendered.
+    html_policy: HtmlPolicy,
+    /// The references of the last parsed document the parser left as text,
+    /// their label being undefined.
+    unresolved: Vec<Unresolved>,
+}
+
+impl Default for CompileContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
 impl CompileContext {
     pub fn new() -> CompileContext {
-        let root = Node::Root(
+        let mut arena = Arena::new();
+        let root = arena.push(Node::Root(
             Root {
                 children: Vec::new(),
                 position: None,
+                frontmatter: None,
+                definitions: Vec::new(),
             },
             ElementNode::default(),
-        );
+        ));
         CompileContext {
+            arena,
             old_root: None,
-            root: Rc::new(RefCell::new(root)),
+            root,
+            live: 1,
             stack: vec![],
+            patches: vec![],
+            lines: LineIndex::default(),
+            #[cfg(feature = "dom")]
+            target: None,
+            html_policy: HtmlPolicy::default(),
+            unresolved: Vec::new(),
         }
         // self.stack.push((*root).clone());
     }
+
     fn run(&mut self, markdown_input: &str) {
-        panic::set_hook(Box::new(console_error_panic_hook::hook));
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_TABLES);
-        let parser = Parser::new_ext(markdown_input, options);
-        let offset_parser = parser.into_offset_iter();
-        if self.root.borrow().children().unwrap().len() != 0 {
-            self.old_root = Some(self.root.clone());
-            self.root = Rc::new(RefCell::new(Node::Root(
-                Root {
-                    children: Vec::new(),
-                    position: None,
-                },
-                ElementNode::default(),
-            )));
+        set_panic_hook();
+        self.lines = LineIndex::new(markdown_input);
+        let frontmatter = frontmatter::split(markdown_input, &self.lines);
+        let body_start = frontmatter.as_ref().map_or(0, |(_, start)| *start);
+        // Nothing is rendered from the previous tree, if any.
+        if self.old_root.is_none() {
+            self.arena.clear();
+        }
+        let frontmatter = frontmatter.map(|(node, _)| {
+            let id = self.arena.push(node);
+            self.arena.compute_hash(id);
+            id
+        });
+        self.root = self.arena.push(Node::Root(
+            Root {
+                children: Vec::new(),
+                position: Some(self.lines.position(0..markdown_input.len())),
+                frontmatter,
+                definitions: Vec::new(),
+            },
+            ElementNode::default(),
+        ));
+        let blocks = self.parse_blocks(
+            markdown_input,
+            body_start..markdown_input.len(),
+            &HashMap::new(),
+        );
+        if let Node::Root(x, _) = &mut self.arena[self.root] {
+            x.definitions = blocks.definitions;
         }
-        for (event, range) in offset_parser {
+        self.arena.adopt(self.root);
+        self.unresolved = blocks.unresolved;
+        self.finish();
+        self.live = self.arena.count(self.root);
+    }
+
+    /// Parse the blocks of `source[range]` into the children of `root`.
+    ///
+    /// References to labels the range does not define resolve to `known`,
+    /// the destination and title of the definitions elsewhere in the
+    /// source, by normalized label: the parser collapses the whitespace of
+    /// labels but does not trim them or fold their case.
+    fn parse_blocks(
+        &mut self,
+        source: &str,
+        range: Range<usize>,
+        known: &HashMap<String, (String, String)>,
+    ) -> Blocks {
+        let body_start = range.start;
+        let body = &source[range.clone()];
+        // Undefined references are left as text by the parser, note them.
+        let mut broken = Vec::new();
+        let mut callback =
+            |link: BrokenLink<'_>| match known.get(&reference::normalize(&link.reference)) {
+                Some((url, title)) => Some((url.clone().into(), title.clone().into())),
+                None => {
+                    broken.push((link.span, link.link_type, link.reference.to_string()));
+                    None
+                }
+            };
+        let mut iter = Parser::new_with_broken_link_callback(body, options(), Some(&mut callback))
+            .into_offset_iter();
+        let events: Vec<_> = iter.by_ref().collect();
+        let shift = |span: &Range<usize>| span.start + body_start..span.end + body_start;
+        let parsed: Vec<_> = iter
+            .reference_definitions()
+            .iter()
+            .map(|(label, definition)| reference::Parsed {
+                label: label.to_string(),
+                url: definition.dest.to_string(),
+                title: definition.title.as_ref().map(|title| title.to_string()),
+                span: shift(&definition.span),
+            })
+            .collect();
+        drop(iter);
+        // Tight list items hold their text without paragraphs: everything
+        // but the containers is content.
+        let covered: Vec<_> = events
+            .iter()
+            .filter(|(event, _)| {
+                !matches!(
+                    event,
+                    Event::Start(Tag::List(_) | Tag::Item | Tag::BlockQuote) | Event::End(_)
+                )
+            })
+            .map(|(_, range)| shift(range))
+            .collect();
+        let mut unresolved: Vec<_> = broken
+            .into_iter()
+            .filter(|(_, _, label)| !label.starts_with('^'))
+            .map(|(span, link_type, label)| Unresolved {
+                label,
+                reference_type: reference::reference_type(link_type),
+                image: body[span.clone()].starts_with('!'),
+                position: Some(self.lines.position(shift(&span))),
+            })
+            .collect();
+        unresolved.dedup();
+        let definitions =
+            reference::definitions(&mut self.arena, &parsed, &covered, &self.lines, range);
+        let events = math::split_math(body, events);
+        // Each event but the ends makes at most one node.
+        self.arena.reserve(
+            events
+                .iter()
+                .filter(|(event, _)| !matches!(event, ParseEvent::Markdown(Event::End(_))))
+                .count(),
+        );
+        for (event, range) in events {
+            let range = shift(&range);
             // log("222");
+            let position = self.lines.position(range.clone());
+            let event = match event {
+                ParseEvent::Markdown(event) => event,
+                ParseEvent::InlineMath(value) => {
+                    let parent = self.stack.pop();
+                    let math_node = Node::InlineMath(
+                        InlineMath {
+                            value: Some(value),
+                            position: Some(position),
+                        },
+                        ElementNode::default(),
+                    );
+                    self.push_node_to_parent(math_node, parent);
+                    continue;
+                }
+                ParseEvent::Math(value) => {
+                    let parent = self.stack.pop();
+                    let math_node = Node::Math(
+                        Math {
+                            value: Some(value),
+                            position: Some(position),
+                        },
+                        ElementNode::default(),
+                    );
+                    self.push_node_to_parent(math_node, parent);
+                    continue;
+                }
+            };
             match event {
                 Event::Start(tag) => {
-                    let _ = self.start_tag(
-                        tag,
-                        Some(Position {
-                            start: range.start,
-                            end: range.end,
-                        }),
-                    );
+                    let source = &source[range];
+                    let _ = self.start_tag(tag, source, Some(position.clone()));
                 }
                 Event::End(tag) => {
                     let _ = self.end_tag(tag);
@@ -100,34 +291,35 @@ impl CompileContext {
                     let parent = self.stack.pop().unwrap();
                     match parent {
                         Node::Code(mut code, _) => {
-                            code.value = Some(text.to_string());
+                            // Long code blocks come in several text events.
+                            code.value.get_or_insert_with(String::new).push_str(&text);
+                            code.value_position = Some(match code.value_position.take() {
+                                Some(start) => Position::new(start.start, position.end),
+                                None => position,
+                            });
                             self.stack.push(Node::Code(code, ElementNode::default()));
                         }
+                        Node::Math(mut math, _) => {
+                            math.value.get_or_insert_with(String::new).push_str(&text);
+                            self.stack.push(Node::Math(math, ElementNode::default()));
+                        }
                         _ => {
                             let text_node = Node::Text(
                                 Text {
                                     value: Some(text.to_string()),
-                                    position: Some(Position {
-                                        start: range.start,
-                                        end: range.end,
-                                    }),
+                                    position: Some(position.clone()),
                                 },
                                 TextNode::default(),
                             );
                             self.push_node_to_parent(text_node, Some(parent))
                         }
                     }
-                    let end_newline = text.ends_with('\n');
-                    println!("{}", end_newline);
                 }
                 Event::SoftBreak => {
                     let parent = self.stack.pop();
                     let break_node = Node::SoftBreak(
                         SoftBreak {
-                            position: Some(Position {
-                                start: range.start,
-                                end: range.end,
-                            }),
+                            position: Some(position.clone()),
                         },
                         ElementNode::default(),
                     );
@@ -137,10 +329,17 @@ impl CompileContext {
                     let parent = self.stack.pop();
                     let break_node = Node::HardBreak(
                         HardBreak {
-                            position: Some(Position {
-                                start: range.start,
-                                end: range.end,
-                            }),
+                            position: Some(position.clone()),
+                        },
+                        ElementNode::default(),
+                    );
+                    self.push_node_to_parent(break_node, parent)
+                }
+                Event::Rule => {
+                    let parent = self.stack.pop();
+                    let break_node = Node::ThematicBreak(
+                        ThematicBreak {
+                            position: Some(position.clone()),
                         },
                         ElementNode::default(),
                     );
@@ -150,55 +349,334 @@ impl CompileContext {
                     let parent = self.stack.pop();
                     let code_node = Node::InlineCode(
                         InlineCode {
-                            position: Some(Position {
-                                start: range.start,
-                                end: range.end,
-                            }),
+                            position: Some(position.clone()),
                             value: Some(value.to_string()),
                         },
                         ElementNode::default(),
                     );
                     self.push_node_to_parent(code_node, parent)
                 }
+                Event::FootnoteReference(label) => {
+                    let parent = self.stack.pop();
+                    let reference = Node::FootnoteReference(
+                        FootnoteReference {
+                            position: Some(position.clone()),
+                            label: label.to_string(),
+                            number: 0,
+                            occurrence: 0,
+                        },
+                        ElementNode::default(),
+                    );
+                    self.push_node_to_parent(reference, parent)
+                }
+                Event::TaskListMarker(checked) => {
+                    let item = self.stack.iter_mut().rev().find_map(|node| match node {
+                        Node::ListItem(x, _) => Some(x),
+                        _ => None,
+                    });
+                    if let Some(item) = item {
+                        item.checked = Some(checked);
+                        item.checked_position = Some(position.clone());
+                    }
+                }
                 Event::Html(value) => {
                     let parent = self.stack.pop();
-                    let html_node = Node::Html(
-                        Html {
-                            position: Some(Position {
-                                start: range.start,
-                                end: range.end,
-                            }),
+                    // Only tight list items mix flow and phrasing content;
+                    // the lines of HTML blocks keep their line ending.
+                    let flow = match &parent {
+                        None
+                        | Some(Node::BlockQuote(_, _))
+                        | Some(Node::FootnoteDefinition(_, _)) => true,
+                        Some(Node::ListItem(_, _)) => value.ends_with('\n'),
+                        _ => false,
+                    };
+                    if flow {
+                        self.push_html_line(&value, position, parent);
+                        continue;
+                    }
+                    let html_node = Node::InlineHtml(
+                        InlineHtml {
                             value: Some(value.to_string()),
+                            close: None,
+                            children: Vec::new(),
+                            position: Some(position.clone()),
+                            close_position: None,
                         },
                         ElementNode::default(),
                     );
                     self.push_node_to_parent(html_node, parent)
                 }
-                _ => {
-                    // print!("no");
-                }
             }
             // println!("{}", self.stack.len());
         }
+        if self.html_policy.mode != HtmlMode::Escape {
+            raw_html::nest(&mut self.arena, self.root, &self.html_policy);
+        }
+        autolink::literals(&mut self.arena, self.root, &self.lines);
+        Blocks {
+            definitions,
+            unresolved,
+        }
+    }
+
+    /// Apply `edit` to the source of the last parse and update the tree,
+    /// re-parsing only the top-level blocks around the edit when possible.
+    fn edit(&mut self, edit: &SourceEdit) -> Result<(), String> {
+        let source = edit
+            .apply(self.lines.source())
+            .ok_or_else(|| format!("edit {}..{} out of the source", edit.start, edit.end))?;
+        if !self.reparse(edit, &source) {
+            self.run(&source);
+        }
+        Ok(())
     }
-    fn start_tag(&mut self, tag: Tag, positon: Option<Position>) -> io::Result<()> {
-        println!("{}", self.stack.len());
+
+    /// Re-parse the top-level blocks around `edit`, which gave `source`, and
+    /// splice them into a new root, the blocks after them shifted.
+    ///
+    /// The blocks re-parsed start and end after blank lines, and are
+    /// extended until the following block starts anew after them. Returns
+    /// `false` when the edit may change the document beyond its blocks:
+    /// around front matter, footnotes or link reference definitions, or
+    /// after a tree not parsed from source. The context then needs a full
+    /// `run`.
+    fn reparse(&mut self, edit: &SourceEdit, source: &str) -> bool {
+        let old_root = self.root;
+        let old = match &self.arena[old_root] {
+            Node::Root(x, _) if x.position.is_some() => x.clone(),
+            _ => return false,
+        };
+        // Footnote definitions are moved to the end of the document, out of
+        // the order of the blocks.
+        let footnotes = old
+            .children
+            .last()
+            .is_some_and(|last| matches!(self.arena[*last], Node::FootnoteDefinition(_, _)));
+        if footnotes {
+            return false;
+        }
+        let old_len = self.lines.source().len();
+        let delta = source.len() as isize - old_len as isize;
+        let line_delta = edit.replacement.matches('\n').count() as isize
+            - self.lines.source()[edit.start..edit.end]
+                .matches('\n')
+                .count() as isize;
+        let lines = self.lines.edited(edit, source);
+        let old_lines = mem::replace(&mut self.lines, lines);
+        let body_start = match (&old.frontmatter, frontmatter::split(source, &self.lines)) {
+            (None, None) => 0,
+            (Some(old), Some((new, start)))
+                if edit.start >= start && self.arena[*old].value() == new.value() =>
+            {
+                start
+            }
+            _ => return false,
+        };
+        let spans: Option<Vec<_>> = old
+            .children
+            .iter()
+            .map(|child| {
+                self.arena[*child]
+                    .position()
+                    .map(|x| x.start.offset..x.end.offset)
+            })
+            .collect();
+        let spans = match spans {
+            Some(spans) => spans,
+            None => return false,
+        };
+        let offsets: Vec<_> = old
+            .definitions
+            .iter()
+            .map(|definition| self.arena[*definition].position().map(|x| x.start.offset))
+            .collect();
+        let known: HashMap<_, _> = old
+            .definitions
+            .iter()
+            .rev()
+            .filter_map(|definition| match &self.arena[*definition] {
+                Node::Definition(x, _) => Some((
+                    reference::normalize(&x.label),
+                    (
+                        x.url.clone().unwrap_or_default(),
+                        x.title.clone().unwrap_or_default(),
+                    ),
+                )),
+                _ => None,
+            })
+            .collect();
+
+        // The blocks touched by the edit, and one more on each side.
+        let count = spans.len();
+        let first = spans
+            .iter()
+            .position(|span| span.end >= edit.start)
+            .unwrap_or(count);
+        let last = spans.iter().rposition(|span| span.start <= edit.end);
+        let mut blocks_start = first.saturating_sub(1);
+        let mut blocks_end = (last.map_or(0, |last| last + 1) + 1)
+            .min(count)
+            .max(blocks_start);
+        let (range, old_end, parsed, children) = loop {
+            let start = match blocks_start {
+                0 => body_start,
+                idx => spans[idx].start,
+            };
+            if blocks_start > 0 && !incremental::after_blank_line(source, start) {
+                blocks_start -= 1;
+                continue;
+            }
+            let old_end = spans.get(blocks_end).map_or(old_len, |span| span.start);
+            let end = (old_end as isize + delta) as usize;
+            if blocks_end < count && !incremental::after_blank_line(source, end) {
+                blocks_end += 1;
+                continue;
+            }
+            let defined = offsets
+                .iter()
+                .any(|offset| offset.is_some_and(|offset| start <= offset && offset < old_end));
+            if defined {
+                return false;
+            }
+            self.root = self.arena.push(Node::Root(
+                Root {
+                    children: Vec::new(),
+                    position: None,
+                    frontmatter: None,
+                    definitions: Vec::new(),
+                },
+                ElementNode::default(),
+            ));
+            let parsed = self.parse_blocks(source, start..end, &known);
+            if !parsed.definitions.is_empty() {
+                return false;
+            }
+            let children = match &mut self.arena[self.root] {
+                Node::Root(x, _) => mem::take(&mut x.children),
+                _ => Vec::new(),
+            };
+            // An HTML block left open holds the blocks after it.
+            let unclosed = children
+                .last()
+                .is_some_and(|last| raw_html::unclosed(&self.arena[*last]));
+            if blocks_end < count && unclosed {
+                blocks_end = count;
+                continue;
+            }
+            if blocks_end < count
+                && !incremental::resyncs(
+                    source,
+                    end,
+                    &self.arena,
+                    &children,
+                    &self.arena[old.children[blocks_end]],
+                )
+            {
+                blocks_end += 1;
+                continue;
+            }
+            break (start..end, old_end, parsed, children);
+        };
+        // Footnotes are numbered across the document.
+        if old_lines.source()[range.start..old_end].contains("[^")
+            || source[range.clone()].contains("[^")
+        {
+            return false;
+        }
+        let removed: usize = old.children[blocks_start..blocks_end]
+            .iter()
+            .map(|child| self.arena.count(*child))
+            .sum();
+        let added: usize = children.iter().map(|child| self.arena.count(*child)).sum();
+        self.live = self.live + added - removed;
+        // Unless the headings re-parsed read differently, no slug changes.
+        let carried = slug::carry(
+            &mut self.arena,
+            &old.children[blocks_start..blocks_end],
+            &children,
+        );
+
+        let mut new_children = old.children[..blocks_start].to_vec();
+        new_children.extend(children);
+        for child in &old.children[blocks_end..] {
+            self.arena.shift(*child, delta, line_delta);
+            new_children.push(*child);
+        }
+        // The blocks kept are shared with the old tree the DOM is diffed
+        // against: those whose slugs change are copied, not changed there.
+        if !carried {
+            let changes = slug::changes(&self.arena, &new_children);
+            for (child, changes) in new_children.iter_mut().zip(changes) {
+                if changes {
+                    *child = self.arena.deep_clone(*child);
+                }
+            }
+        }
+        for (definition, offset) in old.definitions.iter().zip(offsets) {
+            if offset.is_some_and(|offset| offset >= old_end) {
+                self.arena.shift(*definition, delta, line_delta);
+            }
+        }
+        // In parse order, which is that of the blocks.
+        let offset = |x: &Unresolved| x.position.as_ref().map_or(0, |x| x.start.offset);
+        let (before, after): (Vec<_>, Vec<_>) = mem::take(&mut self.unresolved)
+            .into_iter()
+            .filter(|x| offset(x) < range.start || offset(x) >= old_end)
+            .partition(|x| offset(x) < range.start);
+        let mut unresolved = before;
+        unresolved.extend(parsed.unresolved);
+        unresolved.extend(after.into_iter().map(|mut x| {
+            if let Some(position) = &mut x.position {
+                position.shift(delta, line_delta);
+            }
+            x
+        }));
+        self.unresolved = unresolved;
+        self.root = self.arena.push(Node::Root(
+            Root {
+                children: new_children,
+                position: Some(self.lines.position(0..source.len())),
+                frontmatter: old.frontmatter,
+                definitions: old.definitions,
+            },
+            ElementNode::default(),
+        ));
+        // The footnotes elsewhere keep their numbers: only the slugs can change.
+        if !carried {
+            slug::assign(&mut self.arena, self.root);
+        }
+        self.arena.compute_hash(self.root);
+        true
+    }
+
+    /// Number the footnotes and slug the headings of the whole tree.
+    fn finish(&mut self) {
+        footnote::collect(&mut self.arena, self.root);
+        slug::assign(&mut self.arena, self.root);
+        self.arena.compute_hash(self.root);
+    }
+    /// Open the node of `tag`, written as `source`.
+    fn start_tag(&mut self, tag: Tag, source: &str, positon: Option<Position>) -> io::Result<()> {
         match tag {
-            Tag::Heading(level, _id, _classes) => {
+            Tag::Heading(level, id, classes) => {
                 let heading = Node::Heading(
                     Heading {
                         children: Vec::new(),
                         position: positon,
                         depth: level as u8,
+                        id: id.map(str::to_string),
+                        classes: classes.into_iter().map(str::to_string).collect(),
+                        slug: String::new(),
                     },
                     ElementNode::default(),
                 );
                 self.stack.push(heading);
             }
-            Tag::List(index) => {
+            Tag::List(start) => {
                 let list = Node::List(
                     List {
-                        index: index,
+                        start,
+                        spread: false,
                         children: Vec::new(),
                         position: positon,
                     },
@@ -212,6 +690,8 @@ impl CompileContext {
                     ListItem {
                         children: Vec::new(),
                         position: positon,
+                        checked: None,
+                        checked_position: None,
                     },
                     ElementNode::default(),
                 );
@@ -220,6 +700,16 @@ impl CompileContext {
             }
             Tag::CodeBlock(info) => {
                 match info {
+                    CodeBlockKind::Fenced(info) if info.split(' ').next() == Some("math") => {
+                        let item = Node::Math(
+                            Math {
+                                value: None,
+                                position: positon,
+                            },
+                            ElementNode::default(),
+                        );
+                        self.stack.push(item);
+                    }
                     CodeBlockKind::Fenced(info) => {
                         let lang = info.split(' ').next().unwrap();
                         let item = Node::Code(
@@ -227,6 +717,7 @@ impl CompileContext {
                                 position: positon,
                                 lang: Some(lang.to_owned()),
                                 value: None,
+                                value_position: None,
                             },
                             ElementNode::default(),
                         );
@@ -238,13 +729,13 @@ impl CompileContext {
                                 position: positon,
                                 lang: None,
                                 value: None,
+                                value_position: None,
                             },
                             ElementNode::default(),
                         );
                         self.stack.push(item);
                     }
                 }
-                println!("{}", self.stack.len());
                 // println!("item");
             }
             Tag::Paragraph => {
@@ -282,7 +773,7 @@ impl CompileContext {
                     Table {
                         children: Vec::new(),
                         position: positon,
-                        alignment: alignment,
+                        alignment,
                     },
                     ElementNode::default(),
                 );
@@ -313,6 +804,20 @@ impl CompileContext {
                     TableCell {
                         children: Vec::new(),
                         position: positon,
+                        align: Alignment::None,
+                    },
+                    ElementNode::default(),
+                );
+                self.stack.push(item);
+            }
+            Tag::FootnoteDefinition(label) => {
+                let item = Node::FootnoteDefinition(
+                    FootnoteDefinition {
+                        children: Vec::new(),
+                        position: positon,
+                        label: label.to_string(),
+                        number: 0,
+                        occurrence: 0,
                     },
                     ElementNode::default(),
                 );
@@ -338,13 +843,66 @@ impl CompileContext {
                 );
                 self.stack.push(item);
             }
+            Tag::Link(
+                link_type @ (LinkType::Reference
+                | LinkType::Collapsed
+                | LinkType::Shortcut
+                | LinkType::ReferenceUnknown
+                | LinkType::CollapsedUnknown
+                | LinkType::ShortcutUnknown),
+                url,
+                title,
+            ) => {
+                let link_type = reference::known(link_type);
+                let item = Node::LinkReference(
+                    LinkReference {
+                        children: Vec::new(),
+                        position: positon,
+                        label: reference::label(source, link_type),
+                        link_type,
+                        url: Some(url.to_string()),
+                        title: Some(title.to_string()),
+                    },
+                    ElementNode::default(),
+                );
+                self.stack.push(item);
+            }
+            Tag::Image(
+                link_type @ (LinkType::Reference
+                | LinkType::Collapsed
+                | LinkType::Shortcut
+                | LinkType::ReferenceUnknown
+                | LinkType::CollapsedUnknown
+                | LinkType::ShortcutUnknown),
+                url,
+                title,
+            ) => {
+                let link_type = reference::known(link_type);
+                let item = Node::ImageReference(
+                    ImageReference {
+                        position: positon,
+                        alt: String::new(),
+                        label: reference::label(source, link_type),
+                        link_type,
+                        url: Some(url.to_string()),
+                        title: Some(title.to_string()),
+                    },
+                    ElementNode::default(),
+                );
+                self.stack.push(item);
+            }
             Tag::Link(link_type, url, title) => {
+                // As in mdast, and the email addresses of `autolink`.
+                let url = match link_type {
+                    LinkType::Email => format!("mailto:{}", url),
+                    _ => url.to_string(),
+                };
                 let item = Node::Link(
                     Link {
                         children: Vec::new(),
                         position: positon,
-                        link_type: link_type,
-                        url: Some(url.to_string()),
+                        link_type,
+                        url: Some(url),
                         title: Some(title.to_string()),
                     },
                     ElementNode::default(),
@@ -355,7 +913,8 @@ impl CompileContext {
                 let item = Node::Image(
                     Image {
                         position: positon,
-                        link_type: link_type,
+                        alt: String::new(),
+                        link_type,
                         url: Some(url.to_string()),
                         title: Some(title.to_string()),
                     },
@@ -363,90 +922,472 @@ impl CompileContext {
                 );
                 self.stack.push(item);
             }
-            _ => {
-                // println!("ok");
-            }
         }
         Ok(())
     }
     fn end_tag(&mut self, _tag: Tag) -> io::Result<()> {
-        println!("{}", self.stack.len());
-        let node = self.stack.pop().unwrap();
+        let mut node = self.stack.pop().unwrap();
+        match &mut node {
+            Node::Table(x, _) => x.align_cells(&mut self.arena),
+            Node::List(x, _) => x.spread = x.has_paragraphs(&self.arena),
+            _ => {}
+        }
         let parent = self.stack.pop();
         self.push_node_to_parent(node, parent);
         Ok(())
     }
 
+    /// Add a line of an HTML block to `parent`, extending the block of the
+    /// previous line if any.
+    fn push_html_line(&mut self, line: &str, position: Position, parent: Option<Node>) {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let position = Position::new(position.start.clone(), position.start.after(line));
+        let last = match &parent {
+            Some(parent) => parent.children(),
+            None => self.arena[self.root].children(),
+        }
+        .and_then(|x| x.last().copied());
+        if let Some(last) = last {
+            if let Node::Html(x, _) = &mut self.arena[last] {
+                if let Some(previous) = x
+                    .position
+                    .as_mut()
+                    .filter(|x| x.end.line + 1 == position.start.line)
+                {
+                    let value = x.value.get_or_insert_with(String::new);
+                    value.push('\n');
+                    value.push_str(line);
+                    previous.end = position.end;
+                    self.arena.compute_hash(last);
+                    if let Some(parent) = parent {
+                        self.stack.push(parent);
+                    }
+                    return;
+                }
+            }
+        }
+        let html_node = Node::Html(
+            Html {
+                value: Some(line.to_string()),
+                close: None,
+                children: Vec::new(),
+                content: Vec::new(),
+                position: Some(position),
+                close_position: None,
+            },
+            ElementNode::default(),
+        );
+        self.push_node_to_parent(html_node, parent)
+    }
+
     fn push_node_to_parent(&mut self, node: Node, parent: Option<Node>) {
+        let id = self.arena.push(node);
+        self.arena.compute_hash(id);
         match parent {
             Some(mut p) => {
+                // Image descriptions only keep their text, as `alt`.
+                match &mut p {
+                    Node::Image(image, _) => image.alt.push_str(&self.arena.text_content(id)),
+                    Node::ImageReference(image, _) => {
+                        image.alt.push_str(&self.arena.text_content(id))
+                    }
+                    _ => {}
+                }
                 if let Some(children) = p.children_mut() {
-                    children.push(Rc::new(RefCell::new(node)));
+                    children.push(id);
                     // println!("{}", self.stack.len());
                 }
                 self.stack.push(p);
             }
-            _ => {
-                let mut p = self.root.borrow_mut();
-                if let Some(children) = p.children_mut() {
-                    children.push(Rc::new(RefCell::new(node)));
-                }
-            }
+            _ => self.arena.append_child(self.root, id),
         }
     }
 
-    fn init(&self) {
-        let window = window().unwrap();
-        let doc = window.document().unwrap();
-        let root_rc = Rc::clone(&self.root);
-        {
-            let mut cur_root = root_rc.borrow_mut();
-            let node = cur_root.create_node(&doc, None).unwrap();
-            match node {
-                DocNode::Element(element) => {
-                    let render_element = doc.get_element_by_id("render").unwrap();
-                    let current_child = render_element.first_child();
-                    match current_child {
-                        Some(node) => {
-                            render_element.remove_child(&node).unwrap();
-                        }
-                        _ => {}
-                    }
-                    render_element.append_child(&element).unwrap();
-                }
-                _ => {}
-            }
+    /// The document tree of the last `render`, as mdast JSON.
+    pub fn to_mdast_json(&self) -> String {
+        serde_json::to_string(&json::to_mdast(&self.arena, self.root)).unwrap()
+    }
+
+    /// The key/values of the front matter of the last parsed document,
+    /// serialized as JSON, or `None` when it has none.
+    pub fn frontmatter(&self) -> Result<Option<String>, JsValue> {
+        self.frontmatter_json()
+            .map_err(|err| JsValue::from_str(&err))
+    }
+
+    /// The outline of the last parsed document, serialized as JSON: the
+    /// headings with their `depth`, `text`, `slug` and source `position`,
+    /// nested in `children` by depth.
+    pub fn toc(&self) -> String {
+        serde_json::to_string(&slug::toc(&self.arena, self.root)).unwrap()
+    }
+
+    /// The link reference definitions of the last parsed document and its
+    /// references to undefined labels, serialized as JSON:
+    /// `{"definitions": [{label, url, title, duplicate, position}], "unresolved": [{label, reference_type, image, position}]}`.
+    pub fn references(&self) -> String {
+        serde_json::to_string(&reference::list(&self.arena, self.root, &self.unresolved)).unwrap()
+    }
+
+    /// The problems found in the links, references and images of the last
+    /// parsed document, serialized as JSON: a list of
+    /// `{rule, severity, message, position}` sorted by position.
+    ///
+    /// `files` is the JSON list of the paths, relative to the document, of
+    /// the existing files; relative links are not checked without it, and
+    /// root-absolute ones never are.
+    pub fn diagnostics(&self, files: Option<String>) -> Result<String, JsValue> {
+        self.diagnostics_json(files.as_deref())
+            .map_err(|err| JsValue::from_str(&err))
+    }
+
+    /// How raw HTML is rendered: escaped, sanitized (the default) or trusted.
+    /// Takes effect at the next render, which rebuilds the preview.
+    pub fn set_html_mode(&mut self, mode: HtmlMode) {
+        self.html_policy.mode = mode;
+        self.old_root = None;
+    }
+
+    /// Set the whole raw HTML policy from JSON, e.g.
+    /// `{"mode": "sanitize", "tags": ["b", "i"], "attributes": [], "url_schemes": ["https"]}`,
+    /// or `{"mode": "trusted", "tag_filter": true}`.
+    /// Missing fields keep their default.
+    pub fn set_html_policy(&mut self, input: &str) -> Result<(), JsValue> {
+        self.html_policy =
+            serde_json::from_str(input).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.old_root = None;
+        Ok(())
+    }
+
+    /// The patches applied by the last `render`, serialized as JSON.
+    pub fn patches(&self) -> String {
+        serde_json::to_string(&self.patches).unwrap()
+    }
+}
+
+/// Rendering into the browser DOM.
+#[cfg(feature = "dom")]
+#[wasm_bindgen]
+impl CompileContext {
+    /// A context rendering into `element`, replacing its content.
+    pub fn new_with_target(element: &Element) -> CompileContext {
+        CompileContext::new_with_node(element)
+    }
+
+    /// A context rendering into any node, such as a `DocumentFragment` or a
+    /// `ShadowRoot`.
+    pub fn new_with_node(target: &web_sys::Node) -> CompileContext {
+        CompileContext {
+            target: Some(target.clone()),
+            ..CompileContext::new()
         }
-        let cur_root1 = root_rc.borrow();
-        if let Some(children) = cur_root1.children() {
-            for child in children {
-                let mut cur_child = child.borrow_mut();
-                cur_child.create(&doc, root_rc.clone());
-            }
+    }
+
+    /// A context rendering into a detached fragment, see `target`.
+    pub fn new_detached() -> CompileContext {
+        let doc = window().unwrap().document().unwrap();
+        CompileContext::new_with_node(&doc.create_document_fragment())
+    }
+
+    /// The node rendered into.
+    pub fn target(&self) -> web_sys::Node {
+        match &self.target {
+            Some(target) => target.clone(),
+            None => window()
+                .unwrap()
+                .document()
+                .unwrap()
+                .get_element_by_id("render")
+                .unwrap()
+                .into(),
         }
     }
 
+    fn document(&self) -> Document {
+        self.target
+            .as_ref()
+            .and_then(|target| target.owner_document())
+            .unwrap_or_else(|| window().unwrap().document().unwrap())
+    }
+
+    /// Render `markdown_input` into the target, patching what the previous
+    /// render left in it.
     pub fn render(&mut self, markdown_input: &str) {
-        if markdown_input == "" {
+        if markdown_input.is_empty() {
             return;
         }
 
+        let doc = self.document();
+        let target = self.target();
+        self.render_into(&doc, &target, markdown_input);
+    }
+
+    /// Apply the edit replacing the bytes `start..end` of the source of the
+    /// last render with `replacement`, and render the result.
+    ///
+    /// Only the top-level blocks around the edit are parsed again; the
+    /// others are kept, and left untouched in the DOM.
+    pub fn render_edit(
+        &mut self,
+        start: usize,
+        end: usize,
+        replacement: &str,
+    ) -> Result<(), JsValue> {
+        let edit = SourceEdit {
+            start,
+            end,
+            replacement: replacement.to_string(),
+        };
+        let doc = self.document();
+        let target = self.target();
+        self.render_edit_into(&doc, &target, &edit)
+            .map_err(|err| JsValue::from_str(&err))
+    }
+
+    /// The rendered element of the deepest node containing the byte `offset`
+    /// of the source, to scroll the preview along with the editor.
+    pub fn node_at(&self, offset: usize) -> Option<web_sys::Element> {
+        let node = self.rendered_node_at(&self.document(), offset)?;
+        // Text is rendered as text nodes, hand back their element.
+        match node.clone().dyn_into::<web_sys::Element>() {
+            Ok(element) => Some(element),
+            Err(_) => node.parent_element(),
+        }
+    }
+
+    /// The source position of the rendered node `element`, or of the nearest
+    /// node it is part of, as JSON.
+    pub fn source_range_of(&self, element: &web_sys::Node) -> Option<String> {
+        self.source_position(&self.document(), element)
+            .map(|position| serde_json::to_string(&position).unwrap())
+    }
+
+    /// The source edit toggling the task list checkbox `element`, as JSON,
+    /// for the editor to apply when the checkbox changes.
+    pub fn task_list_edit(&self, element: &web_sys::Node) -> Option<String> {
+        self.task_list_source_edit(&self.document(), element)
+            .map(|edit| serde_json::to_string(&edit).unwrap())
+    }
+
+    /// Replace the document tree with the mdast JSON `input` and render it.
+    pub fn load_mdast_json(&mut self, input: &str) -> Result<(), JsValue> {
+        let value: serde_json::Value =
+            serde_json::from_str(input).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.root =
+            json::from_mdast(&mut self.arena, &value).map_err(|err| JsValue::from_str(&err))?;
+        if self.html_policy.mode != HtmlMode::Escape {
+            raw_html::nest(&mut self.arena, self.root, &self.html_policy);
+        }
+        self.arena.compute_hash(self.root);
+        self.live = self.arena.count(self.root);
+        self.lines = LineIndex::default();
+        self.unresolved = Vec::new();
+        let doc = self.document();
+        let target = self.target();
+        self.commit(&doc, &target);
+        Ok(())
+    }
+}
+
+/// Rendering into any [`Dom`], such as the in-memory [`memory::Document`]:
+/// the methods of the browser API above, for a given DOM and target node.
+impl CompileContext {
+    /// Render `markdown_input` into `target`, replacing its content at the
+    /// first render and patching it after.
+    pub fn render_into<D: Dom>(&mut self, dom: &D, target: &D::Node, markdown_input: &str) {
         self.run(markdown_input);
-        let new_root = self.root.clone();
-        match &self.old_root {
+        self.commit(dom, target);
+    }
+
+    /// Apply `edit` to the source of the last render and render the result
+    /// into `target`, re-parsing only the top-level blocks around it when
+    /// possible.
+    pub fn render_edit_into<D: Dom>(
+        &mut self,
+        dom: &D,
+        target: &D::Node,
+        edit: &SourceEdit,
+    ) -> Result<(), String> {
+        self.edit(edit)?;
+        self.commit(dom, target);
+        Ok(())
+    }
+
+    /// [`CompileContext::frontmatter`], with the error as a string.
+    pub fn frontmatter_json(&self) -> Result<Option<String>, String> {
+        match &self.arena[self.root] {
+            Node::Root(
+                Root {
+                    frontmatter: Some(node),
+                    ..
+                },
+                _,
+            ) => frontmatter::to_json(&self.arena[*node]).map(|value| Some(value.to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    /// [`CompileContext::diagnostics`], with the error as a string.
+    pub fn diagnostics_json(&self, files: Option<&str>) -> Result<String, String> {
+        let files: Option<Vec<String>> = match files {
+            Some(files) => Some(serde_json::from_str(files).map_err(|err| err.to_string())?),
+            None => None,
+        };
+        let diagnostics =
+            validate::validate(&self.arena, self.root, &self.unresolved, files.as_deref());
+        Ok(serde_json::to_string(&diagnostics).unwrap())
+    }
+
+    fn init<D: Dom>(&mut self, dom: &D, target: &D::Node) {
+        let node = self
+            .arena
+            .create_node(self.root, dom, None, &self.html_policy)
+            .and_then(DocNode::get::<D::Node>)
+            .unwrap();
+        while let Some(child) = dom.first_child(target) {
+            dom.remove_child(target, &child);
+        }
+        dom.append_child(target, node);
+        let mut idx = 0;
+        while let Some(child) = self.arena.child(self.root, idx) {
+            self.arena.create(child, dom, self.root, &self.html_policy);
+            idx += 1;
+        }
+    }
+
+    /// Bring the DOM up to date with `root`, patching the mounted tree if any.
+    fn commit<D: Dom>(&mut self, dom: &D, target: &D::Node) {
+        match self.old_root {
             None => {
-                self.init();
+                self.init(dom, target);
             }
-            Some(_root) => {
-                {
-                    let old = _root.borrow();
-                    let mut new = (&new_root).borrow_mut();
-                    new.set_node(old.node_mut());
+            Some(old_root) => {
+                let node = self.arena[old_root].node_mut().cloned();
+                self.arena[self.root].set_node(node.as_ref());
+
+                self.patches = diff(&self.arena, old_root, self.root, &self.html_policy);
+                let root_node = self.arena[self.root]
+                    .node_mut()
+                    .and_then(DocNode::get::<D::Node>)
+                    .unwrap()
+                    .clone();
+                let applied = apply(
+                    dom,
+                    &root_node,
+                    &mut self.arena,
+                    self.root,
+                    &self.patches,
+                    &self.html_policy,
+                );
+                // The DOM was changed behind our back: render it again.
+                if applied.is_err() {
+                    self.init(dom, target);
                 }
+            }
+        }
+        // The old tree is not needed anymore: drop its nodes once they
+        // outnumber those of the new one.
+        if self.arena.len() >= 2 * self.live {
+            self.root = self.arena.compact(self.root);
+        }
+        self.old_root = Some(self.root);
+    }
 
-                update(_root.clone(), Rc::clone(&self.root));
+    /// The rendered node of the deepest node containing the byte `offset` of
+    /// the source.
+    pub fn rendered_node_at<D: Dom>(&self, dom: &D, offset: usize) -> Option<D::Node> {
+        let root_node = self.arena[self.root].node_mut()?.get::<D::Node>()?.clone();
+        let mut path = Vec::new();
+        let mut cur = self.root;
+        for idx in self.arena.path_at(self.root, offset) {
+            path.extend(self.arena.dom_child_path(cur, idx));
+            cur = self.arena.child(cur, idx).unwrap();
+        }
+        dom_at(dom, &root_node, &path)
+    }
+
+    /// The source position of the rendered node `node`, or of the nearest
+    /// node it is part of.
+    pub fn source_position<D: Dom>(&self, dom: &D, node: &D::Node) -> Option<Position> {
+        let (nodes, rest) = self.locate(dom, node)?;
+        let last = *nodes.last().unwrap();
+        let position = match &self.arena[last] {
+            // Inside the `<code>` of a code block: point at its text.
+            Node::Code(x, _) if !rest.is_empty() => {
+                self.arena.shifted(last, x.value_position.as_ref())
             }
+            _ => None,
+        };
+        position.or_else(|| {
+            nodes
+                .iter()
+                .rev()
+                .find_map(|node| self.arena.position(*node))
+        })
+    }
+
+    /// The source edit toggling the rendered task list checkbox `node`.
+    pub fn task_list_source_edit<D: Dom>(&self, dom: &D, node: &D::Node) -> Option<SourceEdit> {
+        let (nodes, rest) = self.locate(dom, node)?;
+        let last = *nodes.last().unwrap();
+        match &self.arena[last] {
+            Node::ListItem(x, _) if rest == [0] => {
+                let position = self.arena.shifted(last, x.checked_position.as_ref())?;
+                Some(SourceEdit {
+                    start: position.start.offset,
+                    end: position.end.offset,
+                    replacement: if x.checked? { "[ ]" } else { "[x]" }.to_string(),
+                })
+            }
+            _ => None,
         }
-        self.old_root = Some(Rc::clone(&self.root));
     }
+
+    /// The nodes from the root down to the deepest one rendering `element`,
+    /// and the path of `element` inside the DOM node of the latter.
+    fn locate<D: Dom>(&self, dom: &D, element: &D::Node) -> Option<Located> {
+        let root_node = self.arena[self.root].node_mut()?.get::<D::Node>()?.clone();
+        let path = dom_path(dom, &root_node, element)?;
+        let mut rest = &path[..];
+        let mut nodes = vec![self.root];
+        while !rest.is_empty() {
+            let id = *nodes.last().unwrap();
+            let child = self.arena[id].children().and_then(|children| {
+                (0..children.len())
+                    .map(|idx| (idx, self.arena.dom_child_path(id, idx)))
+                    .find(|(_, child_path)| rest.starts_with(child_path))
+                    .map(|(idx, child_path)| (children[idx], child_path.len()))
+            });
+            match child {
+                Some((child, len)) => {
+                    nodes.push(child);
+                    rest = &rest[len..];
+                }
+                None => break,
+            }
+        }
+        Some((nodes, rest.to_vec()))
+    }
+}
+
+/// Parse `markdown_input` into a document tree: the arena holding its
+/// nodes and the id of its root.
+pub fn parse(markdown_input: &str) -> (Arena, NodeId) {
+    let mut context = CompileContext::new();
+    context.run(markdown_input);
+    (context.arena, context.root)
+}
+
+/// Render `markdown_input` to an HTML string, without touching the DOM.
+#[wasm_bindgen]
+pub fn to_html(markdown_input: &str) -> String {
+    let (arena, root) = parse(markdown_input);
+    html::to_html(&arena, root)
+}
+
+/// Reformat `markdown_input` by serializing its document tree back to source.
+#[wasm_bindgen]
+pub fn format_markdown(markdown_input: &str) -> String {
+    let (arena, root) = parse(markdown_input);
+    markdown::to_markdown(&arena, root, &markdown::SerializeOptions::default())
 }
@@ -0,0 +1,813 @@
+use std::{borrow::Cow, cell::Cell, ops::Range};
+
+use pulldown_cmark::{Alignment, LinkType};
+
+use crate::{
+    event::Position,
+    mdast::{reference_close, Arena, Node, NodeId},
+};
+
+/// How headings of depth 1 and 2 are written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeadingStyle {
+    /// `# Heading`
+    Atx,
+    /// `Heading` underlined with `===` or `---`.
+    Setext,
+}
+
+/// Options of [`to_markdown`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerializeOptions {
+    /// Marker of bullet list items: `-`, `*` or `+`.
+    pub bullet: char,
+    /// Marker of emphasis: `*` or `_`.
+    pub emphasis: char,
+    /// Marker of strong emphasis: `*` or `_`.
+    pub strong: char,
+    /// Character of code fences: `` ` `` or `~`.
+    pub fence: char,
+    /// Pad table cells so that the columns line up.
+    pub table_padding: bool,
+    pub heading_style: HeadingStyle,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> Self {
+        SerializeOptions {
+            bullet: '-',
+            emphasis: '*',
+            strong: '*',
+            fence: '`',
+            table_padding: true,
+            heading_style: HeadingStyle::Atx,
+        }
+    }
+}
+
+/// Serialize a document tree back to CommonMark/GFM source.
+pub fn to_markdown(arena: &Arena, id: NodeId, options: &SerializeOptions) -> String {
+    let node = &arena[id];
+    let definitions = match node {
+        Node::Root(x, _) => &x.definitions[..],
+        _ => &[],
+    };
+    let serializer = Serializer {
+        arena,
+        options,
+        definitions,
+        cell: false,
+        enclosing: Cell::new(None),
+    };
+    let mut out = match node {
+        Node::Root(x, _) => {
+            let blocks = serializer.contents(node, false);
+            match &x.frontmatter {
+                Some(frontmatter) if blocks.is_empty() => serializer.block(&arena[*frontmatter]),
+                Some(frontmatter) => {
+                    format!("{}\n\n{}", serializer.block(&arena[*frontmatter]), blocks)
+                }
+                None => blocks,
+            }
+        }
+        Node::BlockQuote(_, _) | Node::ListItem(_, _) => serializer.contents(node, false),
+        _ if is_block(node) => serializer.block(node),
+        _ => {
+            let mut out = String::new();
+            serializer.inline(node, &mut out);
+            out
+        }
+    };
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+struct Serializer<'a> {
+    arena: &'a Arena,
+    options: &'a SerializeOptions,
+    /// The definitions of the document, written back in the containers
+    /// they were found in.
+    definitions: &'a [NodeId],
+    /// Whether a table cell is being written, where a pipe ends the cell
+    /// unless escaped.
+    cell: bool,
+    /// The delimiter of the emphasis whose content is being written, and
+    /// whether it is inside a word, until something is written after it.
+    enclosing: Cell<Option<(char, bool)>>,
+}
+
+impl Serializer<'_> {
+    /// Serialize the blocks of a container node, with the definitions
+    /// written in it.
+    fn contents(&self, node: &Node, tight: bool) -> String {
+        let children = node.children().map(Vec::as_slice).unwrap_or_default();
+        let range = match node {
+            Node::Root(_, _) => None,
+            _ => Some(node.position().map_or(0..0, span)),
+        };
+        self.blocks(&self.flow(range, children), tight)
+    }
+
+    /// `children` with the definitions found in `range`, but not in one of
+    /// them, put back among them in source order. Without a range, the
+    /// children are those of the document and the definitions without a
+    /// position come last.
+    fn flow(&self, range: Option<Range<usize>>, children: &[NodeId]) -> Vec<NodeId> {
+        let offset = |id: NodeId| self.arena[id].position().map(|x| x.start.offset);
+        let nested = |offset: usize| {
+            children.iter().any(|child| {
+                let child = &self.arena[*child];
+                is_container(child)
+                    && child
+                        .position()
+                        .map(span)
+                        .is_some_and(|x| x.contains(&offset))
+            })
+        };
+        let mut definitions = self
+            .definitions
+            .iter()
+            .copied()
+            .filter(|id| match (offset(*id), &range) {
+                (Some(offset), Some(range)) => range.contains(&offset) && !nested(offset),
+                (Some(offset), None) => !nested(offset),
+                (None, range) => range.is_none(),
+            })
+            .peekable();
+        let mut flow = Vec::new();
+        for child in children {
+            let start = offset(*child).unwrap_or(usize::MAX);
+            while let Some(definition) =
+                definitions.next_if(|x| offset(*x).unwrap_or(usize::MAX) < start)
+            {
+                flow.push(definition);
+            }
+            flow.push(*child);
+        }
+        flow.extend(definitions);
+        flow
+    }
+
+    /// Serialize a sequence of blocks. Runs of phrasing content, found in the
+    /// items of tight lists, are written as a single line of text.
+    fn blocks(&self, children: &[NodeId], tight: bool) -> String {
+        let mut blocks: Vec<String> = Vec::new();
+        let mut phrasing: Option<String> = None;
+        let mut previous_list: Option<bool> = None;
+        for child in children {
+            let child = &self.arena[*child];
+            if !is_block(child) {
+                self.inline(child, phrasing.get_or_insert_with(String::new));
+                continue;
+            }
+            if let Some(text) = phrasing.take() {
+                blocks.push(text);
+            }
+            match child {
+                // Two adjacent lists of the same kind would be parsed back as one.
+                Node::List(x, _) if previous_list == Some(x.start.is_some()) => {
+                    blocks.push(self.list(child, true));
+                }
+                _ => blocks.push(self.block(child)),
+            }
+            previous_list = match child {
+                Node::List(x, _) => Some(x.start.is_some()),
+                _ => None,
+            };
+        }
+        if let Some(text) = phrasing {
+            blocks.push(text);
+        }
+        blocks.join(if tight { "\n" } else { "\n\n" })
+    }
+
+    fn block(&self, node: &Node) -> String {
+        match node {
+            Node::Paragraph(_, _) => self.phrasing(node),
+            Node::Heading(x, _) => {
+                let mut text = self.phrasing(node);
+                let attributes: Vec<String> =
+                    x.id.iter()
+                        .map(|id| format!("#{}", id))
+                        .chain(x.classes.iter().map(|class| format!(".{}", class)))
+                        .collect();
+                if !attributes.is_empty() {
+                    text = format!("{} {{{}}}", text, attributes.join(" "));
+                }
+                // Only setext headings span several lines.
+                let setext =
+                    self.options.heading_style == HeadingStyle::Setext || text.contains('\n');
+                if setext && x.depth <= 2 && !text.is_empty() {
+                    let underline = if x.depth == 1 { "=" } else { "-" };
+                    let width = text.lines().map(|line| line.chars().count()).max();
+                    format!("{}\n{}", text, underline.repeat(width.unwrap_or(3).max(3)))
+                } else {
+                    let mut text = text.replace('\n', " ");
+                    // A closing sequence would be left out of the text.
+                    if text.ends_with('#') {
+                        let run = text.trim_end_matches('#').len();
+                        text.insert(run, '\\');
+                    }
+                    format!("{} {}", "#".repeat(x.depth as usize), text)
+                        .trim_end()
+                        .to_string()
+                }
+            }
+            Node::BlockQuote(_, _) => {
+                let content = self.contents(node, false);
+                if content.is_empty() {
+                    return ">".to_string();
+                }
+                content
+                    .lines()
+                    .map(|line| {
+                        if line.is_empty() {
+                            ">".to_string()
+                        } else {
+                            format!("> {}", line)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            Node::FootnoteDefinition(x, _) => {
+                let content = self.contents(node, false);
+                let mut out = format!("[^{}]:", x.label);
+                for (line_idx, line) in content.lines().enumerate() {
+                    if line_idx == 0 {
+                        out.push(' ');
+                    } else {
+                        out.push('\n');
+                        if !line.is_empty() {
+                            out.push_str("    ");
+                        }
+                    }
+                    out.push_str(line);
+                }
+                out
+            }
+            Node::List(_, _) => self.list(node, false),
+            Node::Definition(x, _) => {
+                let mut out = format!("[{}]: ", x.label);
+                push_target(
+                    x.url.as_deref().unwrap_or_default(),
+                    x.title.as_deref(),
+                    &mut out,
+                );
+                out
+            }
+            Node::Code(x, _) => {
+                let value = x.value.as_deref().unwrap_or_default();
+                let longest = longest_run(value, self.options.fence);
+                let fence = self.options.fence.to_string().repeat((longest + 1).max(3));
+                let mut out = fence.clone();
+                out.push_str(x.lang.as_deref().unwrap_or_default());
+                out.push('\n');
+                out.push_str(value);
+                if !value.is_empty() && !value.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(&fence);
+                out
+            }
+            Node::Html(x, _) => {
+                let mut out = x.value.clone().unwrap_or_default();
+                // The blocks between paired tags.
+                let content = self.contents(node, false);
+                if !content.is_empty() {
+                    out.push_str("\n\n");
+                    out.push_str(&content);
+                }
+                if let Some(close) = &x.close {
+                    out.push_str("\n\n");
+                    out.push_str(close);
+                }
+                out
+            }
+            Node::ThematicBreak(_, _) => "***".to_string(),
+            Node::Math(x, _) => format!(
+                "$$\n{}\n$$",
+                x.value
+                    .as_deref()
+                    .unwrap_or_default()
+                    .trim_end_matches('\n')
+            ),
+            Node::Yaml(x, _) => fenced("---", x.value.as_deref().unwrap_or_default()),
+            Node::Toml(x, _) => fenced("+++", x.value.as_deref().unwrap_or_default()),
+            Node::Table(x, _) => self.table(node, &x.alignment),
+            _ => self.phrasing(node),
+        }
+    }
+
+    /// Serialize a list, renumbering ordered items from the list start.
+    /// `alternate` switches to the other bullet or delimiter.
+    fn list(&self, node: &Node, alternate: bool) -> String {
+        let (start, loose, children) = match node {
+            Node::List(x, _) => (x.start, x.spread, &x.children),
+            _ => unreachable!(),
+        };
+        let bullet = match (self.options.bullet, alternate) {
+            (bullet, false) => bullet,
+            ('-', true) => '*',
+            (_, true) => '-',
+        };
+        let delimiter = if alternate { ')' } else { '.' };
+        let items: Vec<String> = children
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| {
+                let marker = match start {
+                    Some(start) => format!("{}{}", start + idx as u64, delimiter),
+                    None => bullet.to_string(),
+                };
+                let item = &self.arena[*item];
+                let mut content = self.contents(item, !loose);
+                if let Node::ListItem(x, _) = item {
+                    match x.checked {
+                        Some(true) => content.insert_str(0, "[x] "),
+                        Some(false) => content.insert_str(0, "[ ] "),
+                        None => {}
+                    }
+                }
+                let indent = " ".repeat(marker.len() + 1);
+                let mut out = marker;
+                for (line_idx, line) in content.lines().enumerate() {
+                    if line_idx == 0 {
+                        out.push(' ');
+                    } else {
+                        out.push('\n');
+                        if !line.is_empty() {
+                            out.push_str(&indent);
+                        }
+                    }
+                    out.push_str(line);
+                }
+                out
+            })
+            .collect();
+        items.join(if loose { "\n\n" } else { "\n" })
+    }
+
+    fn table(&self, node: &Node, alignment: &[Alignment]) -> String {
+        let cells = Serializer {
+            arena: self.arena,
+            options: self.options,
+            definitions: self.definitions,
+            cell: true,
+            enclosing: Cell::new(None),
+        };
+        let rows: Vec<Vec<String>> = node
+            .children()
+            .unwrap()
+            .iter()
+            .map(|row| {
+                self.arena[*row]
+                    .children()
+                    .unwrap()
+                    .iter()
+                    .map(|cell| cells.phrasing(&self.arena[*cell]))
+                    .collect()
+            })
+            .collect();
+        let columns = rows
+            .iter()
+            .map(|row| row.len())
+            .max()
+            .unwrap_or(0)
+            .max(alignment.len());
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                if !self.options.table_padding {
+                    return 0;
+                }
+                rows.iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(3)
+            })
+            .collect();
+        let align = |column: usize| alignment.get(column).copied().unwrap_or(Alignment::None);
+
+        let mut lines = Vec::new();
+        for (row_idx, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = (0..columns)
+                .map(|column| {
+                    let cell = row.get(column).map(String::as_str).unwrap_or_default();
+                    pad(cell, widths[column], align(column))
+                })
+                .collect();
+            lines.push(format!("| {} |", cells.join(" | ")));
+            if row_idx == 0 {
+                let delimiters: Vec<String> = (0..columns)
+                    .map(|column| {
+                        let width = widths[column].max(3);
+                        match align(column) {
+                            Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+                            Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+                            Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+                            Alignment::None => "-".repeat(width),
+                        }
+                    })
+                    .collect();
+                lines.push(format!("| {} |", delimiters.join(" | ")));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn phrasing(&self, node: &Node) -> String {
+        let enclosing = self.enclosing.take();
+        let mut out = String::new();
+        // The parser splits text around escapes and character references:
+        // adjacent text is escaped as a whole.
+        let mut text = String::new();
+        for child in node.children().map(Vec::as_slice).unwrap_or_default() {
+            match &self.arena[*child] {
+                Node::Text(x, _) => text.push_str(x.value.as_deref().unwrap_or_default()),
+                child => {
+                    escape_text(&text, self.cell, &mut out);
+                    text.clear();
+                    if out.is_empty() && matches!(child, Node::Emphasis(_, _) | Node::Strong(_, _))
+                    {
+                        self.enclosing.set(enclosing);
+                    }
+                    self.inline(child, &mut out);
+                }
+            }
+        }
+        escape_text(&text, self.cell, &mut out);
+        out
+    }
+
+    /// The content of the emphasis `node`, written between `marker`
+    /// delimiters.
+    fn emphasized(&self, node: &Node, marker: char, in_word: bool) -> String {
+        self.enclosing.set(Some((marker, in_word)));
+        self.phrasing(node)
+    }
+
+    /// `text` written as is, but for the pipes of a table cell.
+    fn raw<'b>(&self, text: &'b str) -> Cow<'b, str> {
+        if self.cell {
+            escape_pipes(text)
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    fn inline(&self, node: &Node, out: &mut String) {
+        match node {
+            Node::Text(x, _) => escape_text(x.value.as_deref().unwrap_or_default(), self.cell, out),
+            Node::SoftBreak(_, _) => out.push('\n'),
+            Node::HardBreak(_, _) => out.push_str("\\\n"),
+            Node::InlineCode(x, _) => {
+                let value = &*self.raw(x.value.as_deref().unwrap_or_default());
+                let ticks = "`".repeat(longest_run(value, '`') + 1);
+                let padded = value.starts_with('`')
+                    || value.ends_with('`')
+                    || (value.starts_with(' ') && value.ends_with(' ') && value.trim() != "");
+                out.push_str(&ticks);
+                if padded {
+                    out.push(' ');
+                }
+                out.push_str(value);
+                if padded {
+                    out.push(' ');
+                }
+                out.push_str(&ticks);
+            }
+            Node::Emphasis(_, _) | Node::Strong(_, _) => {
+                let (marker, count) = match node {
+                    Node::Emphasis(_, _) => (self.options.emphasis, 1),
+                    _ => (self.options.strong, 2),
+                };
+                // First in the content of another emphasis, its delimiter
+                // comes before, and its place in a word carries over.
+                let (before, in_word) = match (out.chars().next_back(), self.enclosing.take()) {
+                    (None, Some((marker, in_word))) => (Some(marker), in_word),
+                    (before, _) => (before, before.is_some_and(char::is_alphanumeric)),
+                };
+                let mut marker = delimiter(marker, before, in_word);
+                let mut content = self.emphasized(node, marker, in_word);
+                // Delimiters of the last emphasis in the content, or escaped
+                // ones, would run into the closing delimiter.
+                if !in_word && (content.starts_with(marker) || content.ends_with(marker)) {
+                    marker = other_delimiter(marker);
+                    content = self.emphasized(node, marker, in_word);
+                }
+                let marker = marker.to_string().repeat(count);
+                out.push_str(&marker);
+                out.push_str(&content);
+                out.push_str(&marker);
+            }
+            Node::Delete(_, _) => {
+                out.push_str("~~");
+                out.push_str(&self.phrasing(node));
+                out.push_str("~~");
+            }
+            Node::InlineHtml(x, _) => {
+                out.push_str(&self.raw(x.value.as_deref().unwrap_or_default()));
+                out.push_str(&self.phrasing(node));
+                out.push_str(&self.raw(x.close.as_deref().unwrap_or_default()));
+            }
+            Node::InlineMath(x, _) => {
+                out.push('$');
+                out.push_str(&self.raw(x.value.as_deref().unwrap_or_default()));
+                out.push('$');
+            }
+            Node::FootnoteReference(x, _) => {
+                out.push_str("[^");
+                out.push_str(&x.label);
+                out.push(']');
+            }
+            Node::Link(x, _) => {
+                let url = x.url.as_deref().unwrap_or_default();
+                let text: String = node
+                    .children()
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|child| self.arena.text_content(*child))
+                    .collect();
+                match x.link_type {
+                    // Literal autolinks other than URLs, e.g. `www.` ones,
+                    // are found again in their text.
+                    LinkType::Autolink | LinkType::Email
+                        if url != text && url.strip_prefix("mailto:") != Some(&text) =>
+                    {
+                        out.push_str(&text);
+                    }
+                    LinkType::Autolink | LinkType::Email => {
+                        out.push('<');
+                        out.push_str(&text);
+                        out.push('>');
+                    }
+                    _ => {
+                        escape_bang(out);
+                        out.push('[');
+                        out.push_str(&self.phrasing(node));
+                        out.push(']');
+                        let title = x.title.as_deref().map(|title| self.raw(title));
+                        push_destination(&self.raw(url), title.as_deref(), out);
+                    }
+                }
+            }
+            // The text of collapsed and shortcut references is their label,
+            // written as is to match the definition.
+            Node::LinkReference(x, _) => {
+                escape_bang(out);
+                out.push('[');
+                match x.link_type {
+                    LinkType::Reference | LinkType::ReferenceUnknown => {
+                        out.push_str(&self.phrasing(node))
+                    }
+                    _ => out.push_str(&x.label),
+                }
+                out.push_str(&reference_close(x.link_type, &x.label));
+            }
+            Node::ImageReference(x, _) => {
+                out.push_str("![");
+                match x.link_type {
+                    LinkType::Reference | LinkType::ReferenceUnknown => {
+                        escape_text(&x.alt, self.cell, out)
+                    }
+                    _ => out.push_str(&x.label),
+                }
+                out.push_str(&reference_close(x.link_type, &x.label));
+            }
+            Node::Image(x, _) => {
+                out.push_str("![");
+                escape_text(&x.alt, self.cell, out);
+                out.push(']');
+                let title = x.title.as_deref().map(|title| self.raw(title));
+                push_destination(
+                    &self.raw(x.url.as_deref().unwrap_or_default()),
+                    title.as_deref(),
+                    out,
+                );
+            }
+            // Blocks inside phrasing content, e.g. in a tight list item.
+            _ => out.push_str(&self.block(node)),
+        }
+    }
+}
+
+pub(crate) fn is_block(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::Root(_, _)
+            | Node::BlockQuote(_, _)
+            | Node::FootnoteDefinition(_, _)
+            | Node::List(_, _)
+            | Node::ListItem(_, _)
+            | Node::Code(_, _)
+            | Node::Math(_, _)
+            | Node::Toml(_, _)
+            | Node::Yaml(_, _)
+            | Node::Heading(_, _)
+            | Node::ThematicBreak(_, _)
+            | Node::Table(_, _)
+            | Node::TableHead(_, _)
+            | Node::TableRow(_, _)
+            | Node::TableCell(_, _)
+            | Node::Html(_, _)
+            | Node::Paragraph(_, _)
+            | Node::Definition(_, _)
+    )
+}
+
+/// Whether the definitions nested in `node` belong to it rather than to the
+/// container around it.
+fn is_container(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::BlockQuote(_, _)
+            | Node::FootnoteDefinition(_, _)
+            | Node::List(_, _)
+            | Node::ListItem(_, _)
+            | Node::Html(_, _)
+    )
+}
+
+fn span(position: &Position) -> Range<usize> {
+    position.start.offset..position.end.offset
+}
+
+/// The emphasis delimiter to write after `before`: `marker`, or the other
+/// one where it would run into the delimiter before and be read as a single
+/// run. `_` cannot delimit emphasis inside a word, where runs of `*` are
+/// left to merge instead.
+fn delimiter(marker: char, before: Option<char>, in_word: bool) -> char {
+    if in_word {
+        '*'
+    } else if before == Some(marker) {
+        other_delimiter(marker)
+    } else {
+        marker
+    }
+}
+
+fn other_delimiter(marker: char) -> char {
+    match marker {
+        '*' => '_',
+        _ => '*',
+    }
+}
+
+/// Escape a `!` just written before the `[` of a link, which would make it
+/// an image.
+fn escape_bang(out: &mut String) {
+    if out.ends_with('!') {
+        out.insert(out.len() - 1, '\\');
+    }
+}
+
+/// A front matter block between `fence` lines.
+fn fenced(fence: &str, value: &str) -> String {
+    match value {
+        "" => format!("{}\n{}", fence, fence),
+        _ => format!("{}\n{}\n{}", fence, value, fence),
+    }
+}
+
+fn push_destination(url: &str, title: Option<&str>, out: &mut String) {
+    out.push('(');
+    push_target(url, title, out);
+    out.push(')');
+}
+
+/// The destination and title of a link, as written in its parentheses or in
+/// its definition.
+fn push_target(url: &str, title: Option<&str>, out: &mut String) {
+    if url.is_empty() || url.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        out.push('<');
+        out.push_str(&url.replace('<', "\\<").replace('>', "\\>"));
+        out.push('>');
+    } else {
+        out.push_str(url);
+    }
+    if let Some(title) = title.filter(|title| !title.is_empty()) {
+        out.push_str(" \"");
+        out.push_str(&title.replace('"', "\\\""));
+        out.push('"');
+    }
+}
+
+/// Escape the characters of `text` that would otherwise be read as markup,
+/// and pipes in a table `cell`. Line breaks, tabs and leading spaces, which
+/// the text only holds when they were written as character references, are
+/// written as such again.
+fn escape_text(text: &str, cell: bool, out: &mut String) {
+    let chars: Vec<char> = text.chars().collect();
+    for (idx, c) in chars.iter().enumerate() {
+        let line_start = out.is_empty() || out.ends_with('\n');
+        match c {
+            '\n' => {
+                out.push_str("&#10;");
+                continue;
+            }
+            '\t' => {
+                out.push_str("&#9;");
+                continue;
+            }
+            ' ' if line_start => {
+                out.push_str("&#32;");
+                continue;
+            }
+            _ => {}
+        }
+        let escape = match c {
+            '\\' | '*' | '`' | '[' | ']' | '<' | '~' | '$' => true,
+            // Only before what would be read as a character reference.
+            '&' => {
+                let name: String = chars[idx + 1..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphanumeric() || **c == '#')
+                    .collect();
+                !name.is_empty() && chars.get(idx + 1 + name.len()) == Some(&';')
+            }
+            '_' => {
+                let before = idx.checked_sub(1).and_then(|idx| chars.get(idx));
+                let after = chars.get(idx + 1);
+                !before.is_some_and(|c| c.is_alphanumeric())
+                    || !after.is_some_and(|c| c.is_alphanumeric())
+            }
+            '|' => cell,
+            '#' | '>' | '-' | '+' | '=' => line_start,
+            // After the digits of a would-be ordered list marker.
+            '.' | ')' => {
+                ends_with_number(out) && chars.get(idx + 1).is_none_or(|c| *c == ' ' || *c == '\t')
+            }
+            _ => false,
+        };
+        if escape {
+            out.push('\\');
+        }
+        out.push(*c);
+    }
+}
+
+/// `text` with the pipes not escaped yet escaped. Table cells are split on
+/// the other pipes before anything else is parsed, even in code spans,
+/// where the backslash is kept.
+fn escape_pipes(text: &str) -> Cow<'_, str> {
+    if !text.contains('|') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len() + 1);
+    let mut backslashes = 0;
+    for c in text.chars() {
+        if c == '|' && backslashes % 2 == 0 {
+            out.push('\\');
+        }
+        backslashes = if c == '\\' { backslashes + 1 } else { 0 };
+        out.push(c);
+    }
+    Cow::Owned(out)
+}
+
+/// Whether the line written last in `out` is the 1 to 9 digits of an
+/// ordered list marker. Only the end of the line is looked at.
+fn ends_with_number(out: &str) -> bool {
+    let bytes = out.as_bytes();
+    let digits = bytes
+        .iter()
+        .rev()
+        .take(10)
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    let start = bytes.len() - digits;
+    (1..=9).contains(&digits) && (start == 0 || bytes[start - 1] == b'\n')
+}
+
+fn longest_run(text: &str, marker: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in text.chars() {
+        if c == marker {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+fn pad(cell: &str, width: usize, alignment: Alignment) -> String {
+    let fill = width.saturating_sub(cell.chars().count());
+    match alignment {
+        Alignment::Right => format!("{}{}", " ".repeat(fill), cell),
+        Alignment::Center => format!(
+            "{}{}{}",
+            " ".repeat(fill / 2),
+            cell,
+            " ".repeat(fill - fill / 2)
+        ),
+        _ => format!("{}{}", cell, " ".repeat(fill)),
+    }
+}
@@ -0,0 +1,935 @@
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Tag};
+
+use crate::html::escape_html;
+
+/// A parser event, including the math constructs pulldown-cmark does not
+/// know about.
+pub enum ParseEvent<'a> {
+    Markdown(Event<'a>),
+    /// `$...$`, or `$$...$$` sharing its paragraph with other content.
+    InlineMath(String),
+    /// `$$...$$` alone in its paragraph.
+    Math(String),
+}
+
+/// Find the `$` math spans in the text of `events`.
+///
+/// Delimiters follow pandoc: an opening `$` is not followed by whitespace, a
+/// closing `$` is not preceded by whitespace nor followed by a digit, and
+/// `\$` is a literal dollar. Math is taken from the source, so that markdown
+/// escapes and emphasis markers in TeX are kept.
+pub fn split_math<'a>(
+    source: &str,
+    events: Vec<(Event<'a>, Range<usize>)>,
+) -> Vec<(ParseEvent<'a>, Range<usize>)> {
+    let mut out: Vec<(ParseEvent<'a>, Range<usize>)> = Vec::new();
+    let mut events: Vec<(Option<Event<'a>>, Range<usize>)> = events
+        .into_iter()
+        .map(|(event, range)| (Some(event), range))
+        .collect();
+    let mut idx = 0;
+    // Offset from which the text event at `idx` is still to be scanned.
+    let mut from: Option<usize> = None;
+    while idx < events.len() {
+        let range = events[idx].1.clone();
+        let start = from.take().unwrap_or(range.start);
+        let span = match &events[idx].0 {
+            Some(Event::Text(_)) => find_math(source, &events, idx, start),
+            _ => None,
+        };
+        let span = match span {
+            Some(span) => span,
+            None => {
+                let event = events[idx].0.take().unwrap();
+                let event = if start > range.start {
+                    Event::Text(source[start..range.end].to_string().into())
+                } else {
+                    event
+                };
+                out.push((ParseEvent::Markdown(event), start..range.end));
+                idx += 1;
+                continue;
+            }
+        };
+        if span.open > start {
+            let text = source[start..span.open].to_string();
+            out.push((
+                ParseEvent::Markdown(Event::Text(text.into())),
+                start..span.open,
+            ));
+        }
+        let value = math_source(source, &events[idx..=span.last], &span);
+        let event = if span.delimiter == 2 {
+            ParseEvent::Math(value)
+        } else {
+            ParseEvent::InlineMath(value)
+        };
+        out.push((event, span.open..span.close));
+        idx = span.last;
+        let end = events[idx].1.end;
+        if span.close < end {
+            from = Some(span.close);
+        } else {
+            idx += 1;
+        }
+    }
+    display_paragraphs(out)
+}
+
+/// A math span: from the opening delimiter at `open` to the end of the
+/// closing one at `close`, found in the text event `last`.
+struct Span {
+    open: usize,
+    close: usize,
+    delimiter: usize,
+    last: usize,
+}
+
+fn find_math(
+    source: &str,
+    events: &[(Option<Event>, Range<usize>)],
+    first: usize,
+    from: usize,
+) -> Option<Span> {
+    let bytes = source.as_bytes();
+    let range = &events[first].1;
+    let mut open = from;
+    while open < range.end {
+        if bytes[open] != b'$' || escaped(bytes, open) {
+            open += 1;
+            continue;
+        }
+        let delimiter = if bytes.get(open + 1) == Some(&b'$') {
+            2
+        } else {
+            1
+        };
+        let content = open + delimiter;
+        if delimiter == 2 || bytes.get(content).is_some_and(|b| !b.is_ascii_whitespace()) {
+            if let Some(span) = find_close(source, events, first, open, delimiter) {
+                return Some(span);
+            }
+        }
+        open += delimiter;
+    }
+    None
+}
+
+fn find_close(
+    source: &str,
+    events: &[(Option<Event>, Range<usize>)],
+    first: usize,
+    open: usize,
+    delimiter: usize,
+) -> Option<Span> {
+    let bytes = source.as_bytes();
+    let mut depth = 0usize;
+    for (idx, (event, range)) in events.iter().enumerate().skip(first) {
+        match event {
+            Some(Event::Text(_)) => {
+                let mut close = if idx == first {
+                    open + delimiter
+                } else {
+                    range.start
+                };
+                while close + delimiter <= range.end {
+                    let candidate = &bytes[close..close + delimiter];
+                    if candidate.iter().all(|b| *b == b'$')
+                        && !escaped(bytes, close)
+                        && close > open + delimiter
+                        && depth == 0
+                        && closes(bytes, close, delimiter)
+                    {
+                        return Some(Span {
+                            open,
+                            close: close + delimiter,
+                            delimiter,
+                            last: idx,
+                        });
+                    }
+                    close += 1;
+                }
+            }
+            Some(Event::Start(tag)) if is_inline(tag) => depth += 1,
+            Some(Event::End(tag)) if is_inline(tag) => depth = depth.checked_sub(1)?,
+            Some(Event::SoftBreak)
+            | Some(Event::HardBreak)
+            | Some(Event::Code(_))
+            | Some(Event::Html(_)) => {}
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn closes(bytes: &[u8], close: usize, delimiter: usize) -> bool {
+    if delimiter == 2 {
+        return true;
+    }
+    !bytes[close - 1].is_ascii_whitespace()
+        && !bytes.get(close + 1).is_some_and(|b| b.is_ascii_digit())
+}
+
+fn escaped(bytes: &[u8], idx: usize) -> bool {
+    bytes[..idx]
+        .iter()
+        .rev()
+        .take_while(|b| **b == b'\\')
+        .count()
+        % 2
+        == 1
+}
+
+fn is_inline(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..)
+    )
+}
+
+/// The TeX between the delimiters of `span`, without the container prefixes
+/// (`> `, list indentation) that follow its line endings.
+fn math_source(source: &str, events: &[(Option<Event>, Range<usize>)], span: &Span) -> String {
+    let mut value = String::new();
+    let mut cur = span.open + span.delimiter;
+    let end = span.close - span.delimiter;
+    for (idx, (event, range)) in events.iter().enumerate() {
+        if let Some(Event::SoftBreak) | Some(Event::HardBreak) = event {
+            if range.start >= cur && range.end <= end {
+                value.push_str(&source[cur..range.start]);
+                value.push('\n');
+                cur = events
+                    .get(idx + 1)
+                    .map_or(range.end, |(_, next)| next.start);
+            }
+        }
+    }
+    if cur < end {
+        value.push_str(&source[cur..end]);
+    }
+    value.trim().to_string()
+}
+
+/// Unwrap the paragraphs holding nothing but a `$$` span into display math,
+/// the other `$$` spans are inline.
+fn display_paragraphs(events: Vec<(ParseEvent, Range<usize>)>) -> Vec<(ParseEvent, Range<usize>)> {
+    let mut out: Vec<(ParseEvent, Range<usize>)> = Vec::with_capacity(events.len());
+    // Whether the last event is a `$$` span still inside its paragraph.
+    let mut display = false;
+    for (event, range) in events {
+        let alone = display
+            && matches!(event, ParseEvent::Markdown(Event::End(Tag::Paragraph)))
+            && out.len() >= 2
+            && matches!(
+                out[out.len() - 2].0,
+                ParseEvent::Markdown(Event::Start(Tag::Paragraph))
+            );
+        if alone {
+            let math = out.pop().unwrap().0;
+            let start = out.pop().unwrap().1.start;
+            out.push((math, start..range.end));
+            display = false;
+            continue;
+        }
+        if display {
+            if let Some((ParseEvent::Math(value), _)) = out.last_mut() {
+                let value = std::mem::take(value);
+                out.last_mut().unwrap().0 = ParseEvent::InlineMath(value);
+            }
+        }
+        display = matches!(event, ParseEvent::Math(_));
+        out.push((event, range));
+    }
+    out
+}
+
+/// Convert a TeX formula to MathML.
+///
+/// Covers the common subset: scripts, `\frac`, `\sqrt`, Greek letters,
+/// operators and relations, `\left`/`\right`, `\text`, font commands and
+/// matrix environments. Unknown commands are rendered as errors in place.
+pub fn to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = TexParser {
+        tokens: tokenize(tex),
+        pos: 0,
+    };
+    let body = parser.row(&[]);
+    let mut out = String::from("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"");
+    if display {
+        out.push_str(" display=\"block\"");
+    }
+    out.push_str("><semantics>");
+    out.push_str(&body);
+    out.push_str("<annotation encoding=\"application/x-tex\">");
+    escape_html(tex.trim(), &mut out);
+    out.push_str("</annotation></semantics></math>");
+    out
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Command(String),
+    Char(char),
+    Open,
+    Close,
+    Sup,
+    Sub,
+    Align,
+    NewRow,
+}
+
+fn tokenize(tex: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = tex.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '\\' => match chars.next() {
+                Some('\\') => Token::NewRow,
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let mut name = c.to_string();
+                    while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                        name.push(c);
+                    }
+                    Token::Command(name)
+                }
+                Some(c) => Token::Command(c.to_string()),
+                None => Token::Char('\\'),
+            },
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '^' => Token::Sup,
+            '_' => Token::Sub,
+            '&' => Token::Align,
+            '%' => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            _ => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+struct TexParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl TexParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(Token::Char(c)) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn at_stop(&self, stops: &[Token]) -> bool {
+        match self.peek() {
+            None | Some(Token::Close) => true,
+            Some(Token::Command(name)) if name == "right" || name == "end" => true,
+            Some(token) => stops.contains(token),
+        }
+    }
+
+    /// Parse items up to a closing brace, `\right`, `\end` or one of `stops`,
+    /// as a single `<mrow>`.
+    fn row(&mut self, stops: &[Token]) -> String {
+        let mut items = Vec::new();
+        loop {
+            self.skip_space();
+            if self.at_stop(stops) {
+                break;
+            }
+            if let Some(item) = self.scripted() {
+                items.push(item);
+            }
+        }
+        mrow(items)
+    }
+
+    /// An item with its sub- and superscripts.
+    fn scripted(&mut self) -> Option<String> {
+        let base = self.item()?;
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            self.skip_space();
+            match self.peek() {
+                Some(Token::Sub) if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.argument());
+                }
+                Some(Token::Sup) if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.argument());
+                }
+                Some(Token::Char('\'')) if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some("<mo>\u{2032}</mo>".to_string());
+                }
+                _ => break,
+            }
+        }
+        // Big operators take their limits above and below in display style.
+        let (under, over) = if is_large_operator(&base) {
+            ("munder", "mover")
+        } else {
+            ("msub", "msup")
+        };
+        Some(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", under, base, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", over, base, sup),
+            (Some(sub), Some(sup)) if under == "munder" => {
+                format!("<munderover>{}{}{}</munderover>", base, sub, sup)
+            }
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base, sub, sup),
+        })
+    }
+
+    /// A braced group or a single item, as the argument of a command or script.
+    fn argument(&mut self) -> String {
+        self.skip_space();
+        match self.peek() {
+            Some(Token::Open) => {
+                self.pos += 1;
+                let row = self.row(&[]);
+                self.expect_close();
+                row
+            }
+            _ => self.item().unwrap_or_else(|| "<mrow></mrow>".to_string()),
+        }
+    }
+
+    /// The raw text of a braced argument, for `\text` and environment names.
+    fn text_argument(&mut self) -> String {
+        self.skip_space();
+        if self.peek() != Some(&Token::Open) {
+            return match self.next() {
+                Some(Token::Char(c)) => c.to_string(),
+                _ => String::new(),
+            };
+        }
+        self.pos += 1;
+        let mut text = String::new();
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token {
+                Token::Close if depth == 0 => break,
+                Token::Close => {
+                    depth -= 1;
+                    text.push('}');
+                }
+                Token::Open => {
+                    depth += 1;
+                    text.push('{');
+                }
+                Token::Char(c) => text.push(c),
+                Token::Command(name) if name.len() == 1 => text.push_str(&name),
+                Token::Command(name) => {
+                    text.push('\\');
+                    text.push_str(&name);
+                }
+                Token::Sup => text.push('^'),
+                Token::Sub => text.push('_'),
+                Token::Align => text.push('&'),
+                Token::NewRow => text.push_str("\\\\"),
+            }
+        }
+        text
+    }
+
+    fn expect_close(&mut self) {
+        if self.peek() == Some(&Token::Close) {
+            self.pos += 1;
+        }
+    }
+
+    fn item(&mut self) -> Option<String> {
+        self.skip_space();
+        let token = self.next()?;
+        Some(match token {
+            Token::Open => {
+                let row = self.row(&[]);
+                self.expect_close();
+                row
+            }
+            Token::Char(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(Token::Char(c)) = self.peek() {
+                    if !c.is_ascii_digit() && *c != '.' {
+                        break;
+                    }
+                    number.push(*c);
+                    self.pos += 1;
+                }
+                leaf("mn", &number)
+            }
+            Token::Char(c) if c.is_alphabetic() => leaf("mi", &c.to_string()),
+            Token::Char(c) => leaf("mo", &c.to_string()),
+            Token::Command(name) => self.command(&name),
+            // Stray scripts or alignment marks.
+            Token::Sup | Token::Sub | Token::Align | Token::NewRow | Token::Close => {
+                return None;
+            }
+        })
+    }
+
+    fn command(&mut self, name: &str) -> String {
+        if let Some(symbol) = greek(name) {
+            return leaf("mi", symbol);
+        }
+        if let Some(symbol) = operator(name) {
+            return leaf("mo", symbol);
+        }
+        match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let top = self.argument();
+                let bottom = self.argument();
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    top, bottom
+                )
+            }
+            "sqrt" => {
+                self.skip_space();
+                if self.peek() == Some(&Token::Char('[')) {
+                    self.pos += 1;
+                    let index = self.row(&[Token::Char(']')]);
+                    if self.peek() == Some(&Token::Char(']')) {
+                        self.pos += 1;
+                    }
+                    let base = self.argument();
+                    format!("<mroot>{}{}</mroot>", base, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.argument())
+                }
+            }
+            "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "sinh" | "cosh" | "tanh" | "log"
+            | "ln" | "exp" | "lim" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "arg"
+            | "deg" | "dim" | "ker" | "arcsin" | "arccos" | "arctan" => leaf("mi", name),
+            "text" | "textrm" | "mbox" | "operatorname" => {
+                let text = self.text_argument();
+                leaf(
+                    if name == "operatorname" {
+                        "mi"
+                    } else {
+                        "mtext"
+                    },
+                    &text,
+                )
+            }
+            "mathbf" | "mathit" | "mathrm" | "mathbb" | "mathcal" | "mathsf" | "mathtt"
+            | "mathfrak" | "boldsymbol" => {
+                let variant = match name {
+                    "mathbf" | "boldsymbol" => "bold",
+                    "mathit" => "italic",
+                    "mathrm" => "normal",
+                    "mathbb" => "double-struck",
+                    "mathcal" => "script",
+                    "mathsf" => "sans-serif",
+                    "mathtt" => "monospace",
+                    _ => "fraktur",
+                };
+                format!(
+                    "<mstyle mathvariant=\"{}\">{}</mstyle>",
+                    variant,
+                    self.argument()
+                )
+            }
+            "hat" | "bar" | "overline" | "vec" | "dot" | "ddot" | "tilde" | "widehat" => {
+                let accent = match name {
+                    "hat" | "widehat" => "^",
+                    "bar" | "overline" => "\u{af}",
+                    "vec" => "\u{2192}",
+                    "dot" => "\u{2d9}",
+                    "ddot" => "\u{a8}",
+                    _ => "~",
+                };
+                format!(
+                    "<mover accent=\"true\">{}<mo>{}</mo></mover>",
+                    self.argument(),
+                    accent
+                )
+            }
+            "underline" => format!(
+                "<munder accentunder=\"true\">{}<mo>_</mo></munder>",
+                self.argument()
+            ),
+            "left" => {
+                let open = self.delimiter();
+                let body = self.row(&[]);
+                let close = match self.peek() {
+                    Some(Token::Command(name)) if name == "right" => {
+                        self.pos += 1;
+                        self.delimiter()
+                    }
+                    _ => String::new(),
+                };
+                format!("<mrow>{}{}{}</mrow>", open, body, close)
+            }
+            "begin" => self.environment(),
+            "," | ":" | ";" | " " | "quad" | "qquad" | "!" => {
+                let width = match name {
+                    "," => "0.1667em",
+                    ":" => "0.2222em",
+                    ";" => "0.2778em",
+                    " " => "0.25em",
+                    "quad" => "1em",
+                    "qquad" => "2em",
+                    _ => "-0.1667em",
+                };
+                format!("<mspace width=\"{}\"></mspace>", width)
+            }
+            "{" | "}" | "$" | "%" | "&" | "#" | "_" | "|" => leaf("mo", name),
+            _ => {
+                let mut out = String::from("<merror><mtext>\\");
+                escape_html(name, &mut out);
+                out.push_str("</mtext></merror>");
+                out
+            }
+        }
+    }
+
+    /// The fence following `\left` or `\right`.
+    fn delimiter(&mut self) -> String {
+        self.skip_space();
+        let symbol = match self.next() {
+            Some(Token::Char('.')) => return String::new(),
+            Some(Token::Char(c)) => c.to_string(),
+            Some(Token::Command(name)) => match name.as_str() {
+                "{" | "lbrace" => "{".to_string(),
+                "}" | "rbrace" => "}".to_string(),
+                "|" => "\u{2016}".to_string(),
+                "langle" => "\u{27e8}".to_string(),
+                "rangle" => "\u{27e9}".to_string(),
+                "lfloor" => "\u{230a}".to_string(),
+                "rfloor" => "\u{230b}".to_string(),
+                "lceil" => "\u{2308}".to_string(),
+                "rceil" => "\u{2309}".to_string(),
+                _ => return String::new(),
+            },
+            _ => return String::new(),
+        };
+        let mut out = String::from("<mo fence=\"true\" stretchy=\"true\">");
+        escape_html(&symbol, &mut out);
+        out.push_str("</mo>");
+        out
+    }
+
+    /// A `\begin{...}` environment, as a table of rows split on `\\` and
+    /// cells split on `&`.
+    fn environment(&mut self) -> String {
+        let name = self.text_argument();
+        let mut rows = Vec::new();
+        loop {
+            let mut cells = Vec::new();
+            loop {
+                cells.push(self.row(&[Token::Align, Token::NewRow]));
+                match self.peek() {
+                    Some(Token::Align) => self.pos += 1,
+                    _ => break,
+                }
+            }
+            rows.push(cells);
+            match self.peek() {
+                Some(Token::NewRow) => self.pos += 1,
+                _ => break,
+            }
+        }
+        // `\end{name}`, or a stray closing brace.
+        match self.next() {
+            Some(Token::Command(end)) if end == "end" => {
+                self.text_argument();
+            }
+            Some(_) => self.pos -= 1,
+            None => {}
+        }
+        if rows
+            .last()
+            .is_some_and(|cells| cells.len() == 1 && cells[0] == "<mrow></mrow>")
+        {
+            rows.pop();
+        }
+        let align = match name.trim_end_matches('*') {
+            "cases" => " columnalign=\"left\"",
+            "aligned" | "align" | "split" => " columnalign=\"right left\"",
+            _ => "",
+        };
+        let mut table = format!("<mtable{}>", align);
+        for cells in rows {
+            table.push_str("<mtr>");
+            for cell in cells {
+                table.push_str("<mtd>");
+                table.push_str(&cell);
+                table.push_str("</mtd>");
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+        let (open, close) = match name.trim_end_matches('*') {
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "Bmatrix" | "cases" => ("{", ""),
+            "vmatrix" => ("|", "|"),
+            "Vmatrix" => ("\u{2016}", "\u{2016}"),
+            _ => ("", ""),
+        };
+        let (open, close) = match name.as_str() {
+            "Bmatrix" => ("{", "}"),
+            _ => (open, close),
+        };
+        if open.is_empty() && close.is_empty() {
+            return table;
+        }
+        let fence = |symbol: &str| {
+            if symbol.is_empty() {
+                String::new()
+            } else {
+                format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", symbol)
+            }
+        };
+        format!("<mrow>{}{}{}</mrow>", fence(open), table, fence(close))
+    }
+}
+
+fn mrow(items: Vec<String>) -> String {
+    if items.len() == 1 {
+        return items.into_iter().next().unwrap();
+    }
+    format!("<mrow>{}</mrow>", items.concat())
+}
+
+fn leaf(tag: &str, text: &str) -> String {
+    let mut out = format!("<{}>", tag);
+    escape_html(text, &mut out);
+    out.push_str("</");
+    out.push_str(tag);
+    out.push('>');
+    out
+}
+
+fn is_large_operator(item: &str) -> bool {
+    [
+        "\u{2211}", "\u{220f}", "\u{22c3}", "\u{22c2}", "lim", "max", "min",
+    ]
+    .iter()
+    .any(|symbol| item == leaf("mo", symbol) || item == leaf("mi", symbol))
+}
+
+fn greek(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "\u{3b1}",
+        "beta" => "\u{3b2}",
+        "gamma" => "\u{3b3}",
+        "delta" => "\u{3b4}",
+        "epsilon" => "\u{3f5}",
+        "varepsilon" => "\u{3b5}",
+        "zeta" => "\u{3b6}",
+        "eta" => "\u{3b7}",
+        "theta" => "\u{3b8}",
+        "vartheta" => "\u{3d1}",
+        "iota" => "\u{3b9}",
+        "kappa" => "\u{3ba}",
+        "lambda" => "\u{3bb}",
+        "mu" => "\u{3bc}",
+        "nu" => "\u{3bd}",
+        "xi" => "\u{3be}",
+        "pi" => "\u{3c0}",
+        "varpi" => "\u{3d6}",
+        "rho" => "\u{3c1}",
+        "varrho" => "\u{3f1}",
+        "sigma" => "\u{3c3}",
+        "varsigma" => "\u{3c2}",
+        "tau" => "\u{3c4}",
+        "upsilon" => "\u{3c5}",
+        "phi" => "\u{3d5}",
+        "varphi" => "\u{3c6}",
+        "chi" => "\u{3c7}",
+        "psi" => "\u{3c8}",
+        "omega" => "\u{3c9}",
+        "Gamma" => "\u{393}",
+        "Delta" => "\u{394}",
+        "Theta" => "\u{398}",
+        "Lambda" => "\u{39b}",
+        "Xi" => "\u{39e}",
+        "Pi" => "\u{3a0}",
+        "Sigma" => "\u{3a3}",
+        "Upsilon" => "\u{3a5}",
+        "Phi" => "\u{3a6}",
+        "Psi" => "\u{3a8}",
+        "Omega" => "\u{3a9}",
+        "infty" => "\u{221e}",
+        "partial" => "\u{2202}",
+        "nabla" => "\u{2207}",
+        "ell" => "\u{2113}",
+        "hbar" => "\u{210f}",
+        "emptyset" | "varnothing" => "\u{2205}",
+        "Re" => "\u{211c}",
+        "Im" => "\u{2111}",
+        "aleph" => "\u{2135}",
+        _ => return None,
+    })
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "sum" => "\u{2211}",
+        "prod" => "\u{220f}",
+        "coprod" => "\u{2210}",
+        "int" => "\u{222b}",
+        "iint" => "\u{222c}",
+        "iiint" => "\u{222d}",
+        "oint" => "\u{222e}",
+        "bigcup" => "\u{22c3}",
+        "bigcap" => "\u{22c2}",
+        "pm" => "\u{b1}",
+        "mp" => "\u{2213}",
+        "times" => "\u{d7}",
+        "div" => "\u{f7}",
+        "cdot" => "\u{22c5}",
+        "ast" => "\u{2217}",
+        "star" => "\u{22c6}",
+        "circ" => "\u{2218}",
+        "bullet" => "\u{2219}",
+        "oplus" => "\u{2295}",
+        "otimes" => "\u{2297}",
+        "cup" => "\u{222a}",
+        "cap" => "\u{2229}",
+        "setminus" => "\u{2216}",
+        "wedge" | "land" => "\u{2227}",
+        "vee" | "lor" => "\u{2228}",
+        "neg" | "lnot" => "\u{ac}",
+        "leq" | "le" => "\u{2264}",
+        "geq" | "ge" => "\u{2265}",
+        "neq" | "ne" => "\u{2260}",
+        "ll" => "\u{226a}",
+        "gg" => "\u{226b}",
+        "approx" => "\u{2248}",
+        "sim" => "\u{223c}",
+        "simeq" => "\u{2243}",
+        "cong" => "\u{2245}",
+        "equiv" => "\u{2261}",
+        "propto" => "\u{221d}",
+        "in" => "\u{2208}",
+        "notin" => "\u{2209}",
+        "ni" => "\u{220b}",
+        "subset" => "\u{2282}",
+        "supset" => "\u{2283}",
+        "subseteq" => "\u{2286}",
+        "supseteq" => "\u{2287}",
+        "forall" => "\u{2200}",
+        "exists" => "\u{2203}",
+        "nexists" => "\u{2204}",
+        "to" | "rightarrow" => "\u{2192}",
+        "leftarrow" | "gets" => "\u{2190}",
+        "leftrightarrow" => "\u{2194}",
+        "Rightarrow" | "implies" => "\u{21d2}",
+        "Leftarrow" => "\u{21d0}",
+        "Leftrightarrow" | "iff" => "\u{21d4}",
+        "mapsto" => "\u{21a6}",
+        "uparrow" => "\u{2191}",
+        "downarrow" => "\u{2193}",
+        "mid" => "\u{2223}",
+        "parallel" => "\u{2225}",
+        "perp" => "\u{22a5}",
+        "angle" => "\u{2220}",
+        "ldots" | "dots" => "\u{2026}",
+        "cdots" => "\u{22ef}",
+        "vdots" => "\u{22ee}",
+        "ddots" => "\u{22f1}",
+        "prime" => "\u{2032}",
+        "langle" => "\u{27e8}",
+        "rangle" => "\u{27e9}",
+        "lfloor" => "\u{230a}",
+        "rfloor" => "\u{230b}",
+        "lceil" => "\u{2308}",
+        "rceil" => "\u{2309}",
+        "vert" => "|",
+        "Vert" => "\u{2016}",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_mathml;
+
+    /// The MathML of `tex`, without the `<math>` and annotation around it.
+    fn body(tex: &str) -> String {
+        let mathml = to_mathml(tex, false);
+        let start = mathml.find("<semantics>").unwrap() + "<semantics>".len();
+        let end = mathml.find("<annotation").unwrap();
+        mathml[start..end].to_string()
+    }
+
+    #[test]
+    fn renders_fractions() {
+        assert_eq!(body("\\frac{a}{b}"), "<mfrac><mi>a</mi><mi>b</mi></mfrac>");
+        assert_eq!(
+            body("\\frac{\\frac{1}{2}}{x}"),
+            "<mfrac><mfrac><mn>1</mn><mn>2</mn></mfrac><mi>x</mi></mfrac>"
+        );
+        // A missing argument is empty rather than an error.
+        assert_eq!(body("\\frac{1}"), "<mfrac><mn>1</mn><mrow></mrow></mfrac>");
+    }
+
+    #[test]
+    fn renders_scripts() {
+        assert_eq!(body("x_i"), "<msub><mi>x</mi><mi>i</mi></msub>");
+        assert_eq!(
+            body("x_1^2"),
+            "<msubsup><mi>x</mi><mn>1</mn><mn>2</mn></msubsup>"
+        );
+        assert_eq!(
+            body("x^{n+1}"),
+            "<msup><mi>x</mi><mrow><mi>n</mi><mo>+</mo><mn>1</mn></mrow></msup>"
+        );
+        assert_eq!(
+            body("x_{i_j}"),
+            "<msub><mi>x</mi><msub><mi>i</mi><mi>j</mi></msub></msub>"
+        );
+        assert_eq!(
+            body("\\sum_{i}^n"),
+            "<munderover><mo>∑</mo><mi>i</mi><mi>n</mi></munderover>"
+        );
+        assert_eq!(body("x^"), "<msup><mi>x</mi><mrow></mrow></msup>");
+    }
+
+    #[test]
+    fn renders_unknown_commands_as_errors() {
+        assert_eq!(body("\\foo"), "<merror><mtext>\\foo</mtext></merror>");
+        assert_eq!(
+            body("\\foo{x}^2"),
+            "<mrow><merror><mtext>\\foo</mtext></merror><msup><mi>x</mi><mn>2</mn></msup></mrow>"
+        );
+    }
+
+    #[test]
+    fn escapes_the_source() {
+        let mathml = to_mathml("a<b", true);
+        assert!(mathml.contains(" display=\"block\""));
+        assert!(mathml.contains("<mo>&lt;</mo>"));
+        assert!(mathml.contains(">a&lt;b</annotation>"));
+    }
+}
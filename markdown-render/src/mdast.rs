@@ -1,14 +1,21 @@
 use std::{
-    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
     fmt::{self, Debug},
-    rc::Rc,
+    hash::{Hash, Hasher},
+    mem,
+    ops::{Index, IndexMut},
 };
 
-use pulldown_cmark::{Alignment, HeadingLevel, LinkType};
+use pulldown_cmark::{Alignment, LinkType};
 
-use web_sys::{Document, Element, Text as TextElement};
-
-use crate::event::Position;
+use crate::{
+    dom::{DocNode, Dom},
+    event::Position,
+    highlight::highlight,
+    math::to_mathml,
+    patch::dom_at,
+    sanitize::HtmlPolicy,
+};
 
 #[derive(Clone)]
 pub enum Node {
@@ -16,11 +23,16 @@ pub enum Node {
     /// Root.
     Root(Root, ElementNode),
 
+    // Content:
+    /// Definition.
+    Definition(Definition, ElementNode),
+
     // Container:
     /// Block quote.
     BlockQuote(BlockQuote, ElementNode),
 
     /// Footnote definition.
+    FootnoteDefinition(FootnoteDefinition, ElementNode),
 
     /// MDX: JSX element (container).
 
@@ -31,8 +43,10 @@ pub enum Node {
     /// MDX.js ESM.
 
     /// Toml.
+    Toml(Toml, ElementNode),
 
     /// Yaml.
+    Yaml(Yaml, ElementNode),
 
     // Phrasing:
     /// Break.
@@ -43,6 +57,10 @@ pub enum Node {
     InlineCode(InlineCode, ElementNode),
 
     /// Math (phrasing).
+    InlineMath(InlineMath, ElementNode),
+
+    /// Html (phrasing).
+    InlineHtml(InlineHtml, ElementNode),
 
     /// Delete.
     Delete(Delete, ElementNode),
@@ -52,6 +70,7 @@ pub enum Node {
 
     // MDX: expression (text).
     /// Footnote reference.
+    FootnoteReference(FootnoteReference, ElementNode),
 
     /// Html (phrasing).
     Html(Html, ElementNode),
@@ -60,11 +79,13 @@ pub enum Node {
     Image(Image, ElementNode),
 
     /// Image reference.
+    ImageReference(ImageReference, ElementNode),
 
     /// Link.
     Link(Link, ElementNode),
 
     /// Link reference.
+    LinkReference(LinkReference, ElementNode),
 
     /// Strong
     Strong(Strong, ElementNode),
@@ -77,10 +98,14 @@ pub enum Node {
     Code(Code, ElementNode),
 
     /// Math (flow).
+    Math(Math, ElementNode),
 
     /// Heading.
     Heading(Heading, ElementNode),
 
+    /// Thematic break.
+    ThematicBreak(ThematicBreak, ElementNode),
+
     /// Table
     Table(Table, ElementNode),
 
@@ -103,34 +128,11 @@ pub enum Node {
 }
 
 impl PartialEq for Node {
+    /// Two nodes are equal when they have the same type and the same
+    /// structural hash, i.e. identical content and children.
     fn eq(&self, other: &Self) -> bool {
-        // TODO
-        match (self, other) {
-            (Node::Root(_, _), Node::Root(_, _)) => true,
-            (Node::BlockQuote(_, _), Node::BlockQuote(_, _)) => true,
-            (Node::List(_, _), Node::List(_, _)) => true,
-            (Node::SoftBreak(_, _), Node::SoftBreak(_, _)) => true,
-            (Node::HardBreak(_, _), Node::HardBreak(_, _)) => true,
-            (Node::InlineCode(_, _), Node::InlineCode(_, _)) => true,
-            (Node::Delete(_, _), Node::Delete(_, _)) => true,
-            (Node::Emphasis(_, _), Node::Emphasis(_, _)) => true,
-            (Node::Html(_, _), Node::Html(_, _)) => true,
-            (Node::Image(_, _), Node::Image(_, _)) => true,
-            (Node::Link(_, _), Node::Link(_, _)) => true,
-            (Node::Strong(_, _), Node::Strong(_, _)) => true,
-            (Node::Text(_, _), Node::Text(_, _)) => true,
-            (Node::Code(_, _), Node::Code(_, _)) => true,
-            (Node::Heading(heading_self, _), Node::Heading(heading_other, _)) => {
-                heading_self.depth == heading_other.depth
-            }
-            (Node::Table(_, _), Node::Table(_, _)) => true,
-            (Node::TableHead(_, _), Node::TableHead(_, _)) => true,
-            (Node::TableRow(_, _), Node::TableRow(_, _)) => true,
-            (Node::TableCell(_, _), Node::TableCell(_, _)) => true,
-            (Node::ListItem(_, _), Node::ListItem(_, _)) => true,
-            (Node::Paragraph(_, _), Node::Paragraph(_, _)) => true,
-            _ => false,
-        }
+        mem::discriminant(self) == mem::discriminant(other)
+            && self.subtree_hash() == other.subtree_hash()
     }
 }
 
@@ -140,15 +142,26 @@ impl fmt::Debug for Node {
         match self {
             Node::Root(x, _) => x.fmt(f),
             Node::BlockQuote(x, _) => x.fmt(f),
+            Node::FootnoteDefinition(x, _) => x.fmt(f),
             Node::List(x, _) => x.fmt(f),
+            Node::Toml(x, _) => x.fmt(f),
+            Node::Yaml(x, _) => x.fmt(f),
             Node::SoftBreak(x, _) => x.fmt(f),
             Node::HardBreak(x, _) => x.fmt(f),
+            Node::ThematicBreak(x, _) => x.fmt(f),
             Node::InlineCode(x, _) => x.fmt(f),
+            Node::InlineMath(x, _) => x.fmt(f),
+            Node::InlineHtml(x, _) => x.fmt(f),
+            Node::Math(x, _) => x.fmt(f),
             Node::Delete(x, _) => x.fmt(f),
+            Node::FootnoteReference(x, _) => x.fmt(f),
             Node::Emphasis(x, _) => x.fmt(f),
             Node::Html(x, _) => x.fmt(f),
             Node::Image(x, _) => x.fmt(f),
             Node::Link(x, _) => x.fmt(f),
+            Node::LinkReference(x, _) => x.fmt(f),
+            Node::ImageReference(x, _) => x.fmt(f),
+            Node::Definition(x, _) => x.fmt(f),
             Node::Strong(x, _) => x.fmt(f),
             Node::Text(x, _) => x.fmt(f),
             Node::Code(x, _) => x.fmt(f),
@@ -165,23 +178,27 @@ impl fmt::Debug for Node {
 
 impl Node {
     #[must_use]
-    pub fn children(&self) -> Option<&Vec<Rc<RefCell<Node>>>> {
+    pub fn children(&self) -> Option<&Vec<NodeId>> {
         match self {
             // Parent.
             Node::Root(x, _) => Some(&x.children),
             Node::Paragraph(x, _) => Some(&x.children),
             Node::Heading(x, _) => Some(&x.children),
             Node::BlockQuote(x, _) => Some(&x.children),
+            Node::FootnoteDefinition(x, _) => Some(&x.children),
             Node::List(x, _) => Some(&x.children),
             Node::ListItem(x, _) => Some(&x.children),
             Node::Emphasis(x, _) => Some(&x.children),
             Node::Strong(x, _) => Some(&x.children),
             Node::Link(x, _) => Some(&x.children),
+            Node::LinkReference(x, _) => Some(&x.children),
             Node::Table(x, _) => Some(&x.children),
             Node::TableHead(x, _) => Some(&x.children),
             Node::TableRow(x, _) => Some(&x.children),
             Node::TableCell(x, _) => Some(&x.children),
             Node::Delete(x, _) => Some(&x.children),
+            Node::InlineHtml(x, _) => Some(&x.children),
+            Node::Html(x, _) => Some(&x.children),
             _ => None,
         }
     }
@@ -191,7 +208,12 @@ impl Node {
             Node::Text(x, _) => x.value.as_ref(),
             Node::Code(x, _) => x.value.as_ref(),
             Node::InlineCode(x, _) => x.value.as_ref(),
+            Node::InlineMath(x, _) => x.value.as_ref(),
+            Node::InlineHtml(x, _) => x.value.as_ref(),
+            Node::Math(x, _) => x.value.as_ref(),
             Node::Html(x, _) => x.value.as_ref(),
+            Node::Toml(x, _) => x.value.as_ref(),
+            Node::Yaml(x, _) => x.value.as_ref(),
             // Node::Paragraph(x,_) => Some(&x.children),
             // Node::Heading(x, _) => Some(&x.children),
             // Node::BlockQuote(x, _) => Some(&x.children),
@@ -226,6 +248,9 @@ impl Node {
             // Node::Strong(x, _) => Some(&x.children),
             Node::Link(x, _) => x.url.as_ref(),
             Node::Image(x, _) => x.url.as_ref(),
+            Node::LinkReference(x, _) => x.url.as_ref(),
+            Node::ImageReference(x, _) => x.url.as_ref(),
+            Node::Definition(x, _) => x.url.as_ref(),
             // Node::Table(x, _) => Some(&x.children),
             // Node::TableHead(x, _) => Some(&x.children),
             // Node::TableRow(x, _) => Some(&x.children),
@@ -252,6 +277,9 @@ impl Node {
             // Node::Strong(x, _) => Some(&x.children),
             Node::Link(x, _) => x.title.as_ref(),
             Node::Image(x, _) => x.title.as_ref(),
+            Node::LinkReference(x, _) => x.title.as_ref(),
+            Node::ImageReference(x, _) => x.title.as_ref(),
+            Node::Definition(x, _) => x.title.as_ref(),
             // Node::Table(x, _) => Some(&x.children),
             // Node::TableHead(x, _) => Some(&x.children),
             // Node::TableRow(x, _) => Some(&x.children),
@@ -347,55 +375,107 @@ impl Node {
     //     }
     // }
 
-    pub fn children_mut(&mut self) -> Option<&mut Vec<Rc<RefCell<Node>>>> {
+    /// Cached structural hash of this node and its subtree, see [`Arena::compute_hash`].
+    pub fn subtree_hash(&self) -> u64 {
+        match self {
+            Node::Root(_, node) => node.hash,
+            Node::Paragraph(_, node) => node.hash,
+            Node::Heading(_, node) => node.hash,
+            Node::Text(_, node) => node.hash,
+            Node::BlockQuote(_, node) => node.hash,
+            Node::FootnoteDefinition(_, node) => node.hash,
+            Node::FootnoteReference(_, node) => node.hash,
+            Node::List(_, node) => node.hash,
+            Node::Toml(_, node) => node.hash,
+            Node::Yaml(_, node) => node.hash,
+            Node::SoftBreak(_, node) => node.hash,
+            Node::HardBreak(_, node) => node.hash,
+            Node::ThematicBreak(_, node) => node.hash,
+            Node::InlineCode(_, node) => node.hash,
+            Node::InlineMath(_, node) => node.hash,
+            Node::InlineHtml(_, node) => node.hash,
+            Node::Math(_, node) => node.hash,
+            Node::Delete(_, node) => node.hash,
+            Node::Emphasis(_, node) => node.hash,
+            Node::Html(_, node) => node.hash,
+            Node::Image(_, node) => node.hash,
+            Node::Link(_, node) => node.hash,
+            Node::LinkReference(_, node) => node.hash,
+            Node::ImageReference(_, node) => node.hash,
+            Node::Definition(_, node) => node.hash,
+            Node::Strong(_, node) => node.hash,
+            Node::Code(_, node) => node.hash,
+            Node::Table(_, node) => node.hash,
+            Node::TableHead(_, node) => node.hash,
+            Node::TableRow(_, node) => node.hash,
+            Node::TableCell(_, node) => node.hash,
+            Node::ListItem(_, node) => node.hash,
+        }
+    }
+
+    fn subtree_hash_mut(&mut self) -> &mut u64 {
+        match self {
+            Node::Root(_, node) => &mut node.hash,
+            Node::Paragraph(_, node) => &mut node.hash,
+            Node::Heading(_, node) => &mut node.hash,
+            Node::Text(_, node) => &mut node.hash,
+            Node::BlockQuote(_, node) => &mut node.hash,
+            Node::FootnoteDefinition(_, node) => &mut node.hash,
+            Node::FootnoteReference(_, node) => &mut node.hash,
+            Node::List(_, node) => &mut node.hash,
+            Node::Toml(_, node) => &mut node.hash,
+            Node::Yaml(_, node) => &mut node.hash,
+            Node::SoftBreak(_, node) => &mut node.hash,
+            Node::HardBreak(_, node) => &mut node.hash,
+            Node::ThematicBreak(_, node) => &mut node.hash,
+            Node::InlineCode(_, node) => &mut node.hash,
+            Node::InlineMath(_, node) => &mut node.hash,
+            Node::InlineHtml(_, node) => &mut node.hash,
+            Node::Math(_, node) => &mut node.hash,
+            Node::Delete(_, node) => &mut node.hash,
+            Node::Emphasis(_, node) => &mut node.hash,
+            Node::Html(_, node) => &mut node.hash,
+            Node::Image(_, node) => &mut node.hash,
+            Node::Link(_, node) => &mut node.hash,
+            Node::LinkReference(_, node) => &mut node.hash,
+            Node::ImageReference(_, node) => &mut node.hash,
+            Node::Definition(_, node) => &mut node.hash,
+            Node::Strong(_, node) => &mut node.hash,
+            Node::Code(_, node) => &mut node.hash,
+            Node::Table(_, node) => &mut node.hash,
+            Node::TableHead(_, node) => &mut node.hash,
+            Node::TableRow(_, node) => &mut node.hash,
+            Node::TableCell(_, node) => &mut node.hash,
+            Node::ListItem(_, node) => &mut node.hash,
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<NodeId>> {
         match self {
             // Parent.
             Node::Root(x, _) => Some(&mut x.children),
             Node::Paragraph(x, _) => Some(&mut x.children),
             Node::Heading(x, _) => Some(&mut x.children),
             Node::BlockQuote(x, _) => Some(&mut x.children),
+            Node::FootnoteDefinition(x, _) => Some(&mut x.children),
             Node::List(x, _) => Some(&mut x.children),
             Node::ListItem(x, _) => Some(&mut x.children),
             Node::Emphasis(x, _) => Some(&mut x.children),
             Node::Strong(x, _) => Some(&mut x.children),
             Node::Link(x, _) => Some(&mut x.children),
+            Node::LinkReference(x, _) => Some(&mut x.children),
             Node::Table(x, _) => Some(&mut x.children),
             Node::TableHead(x, _) => Some(&mut x.children),
             Node::TableRow(x, _) => Some(&mut x.children),
             Node::TableCell(x, _) => Some(&mut x.children),
             Node::Delete(x, _) => Some(&mut x.children),
+            Node::InlineHtml(x, _) => Some(&mut x.children),
+            Node::Html(x, _) => Some(&mut x.children),
             // Non-parent.
             _ => None,
         }
     }
 
-    pub fn create(&mut self, doc: &Document, parent_rc: Rc<RefCell<Node>>) {
-        let parent = parent_rc.borrow();
-
-        if let Some(parent_element) = parent.node_mut() {
-            match parent_element {
-                DocNode::Element(parent_node) => {
-                    let child_element = self.create_node(doc, Some(Rc::clone(&parent_rc))).unwrap();
-                    match child_element {
-                        DocNode::Element(child_node) => {
-                            parent_node.append_child(child_node).unwrap();
-                        }
-                        DocNode::Text(child_node) => {
-                            parent_node.append_child(child_node).unwrap();
-                        }
-                    }
-                }
-                DocNode::Text(_) => {}
-            }
-        }
-        if let Some(children) = self.children() {
-            for child in children {
-                let mut cur_child = child.borrow_mut();
-                cur_child.create(doc, Rc::new(RefCell::new(self.clone())));
-            }
-        }
-    }
-
     pub fn node_mut(&self) -> Option<&DocNode> {
         match self {
             // Parent.
@@ -404,15 +484,26 @@ impl Node {
             Node::Heading(_, node) => node.cur.as_ref(),
             Node::Text(_, node) => node.cur.as_ref(),
             Node::BlockQuote(_, node) => node.cur.as_ref(),
+            Node::FootnoteDefinition(_, node) => node.cur.as_ref(),
+            Node::FootnoteReference(_, node) => node.cur.as_ref(),
             Node::List(_, node) => node.cur.as_ref(),
+            Node::Toml(_, node) => node.cur.as_ref(),
+            Node::Yaml(_, node) => node.cur.as_ref(),
             Node::SoftBreak(_, node) => node.cur.as_ref(),
             Node::HardBreak(_, node) => node.cur.as_ref(),
+            Node::ThematicBreak(_, node) => node.cur.as_ref(),
             Node::InlineCode(_, node) => node.cur.as_ref(),
+            Node::InlineMath(_, node) => node.cur.as_ref(),
+            Node::InlineHtml(_, node) => node.cur.as_ref(),
+            Node::Math(_, node) => node.cur.as_ref(),
             Node::Delete(_, node) => node.cur.as_ref(),
             Node::Emphasis(_, node) => node.cur.as_ref(),
             Node::Html(_, node) => node.cur.as_ref(),
             Node::Image(_, node) => node.cur.as_ref(),
             Node::Link(_, node) => node.cur.as_ref(),
+            Node::LinkReference(_, node) => node.cur.as_ref(),
+            Node::ImageReference(_, node) => node.cur.as_ref(),
+            Node::Definition(_, node) => node.cur.as_ref(),
             Node::Strong(_, node) => node.cur.as_ref(),
             Node::Code(_, node) => node.cur.as_ref(),
             Node::Table(_, node) => node.cur.as_ref(),
@@ -432,15 +523,26 @@ impl Node {
             Node::Heading(_, node) => node.cur = el.cloned(),
             Node::Text(_, node) => node.cur = el.cloned(),
             Node::BlockQuote(_, node) => node.cur = el.cloned(),
+            Node::FootnoteDefinition(_, node) => node.cur = el.cloned(),
+            Node::FootnoteReference(_, node) => node.cur = el.cloned(),
             Node::List(_, node) => node.cur = el.cloned(),
+            Node::Toml(_, node) => node.cur = el.cloned(),
+            Node::Yaml(_, node) => node.cur = el.cloned(),
             Node::SoftBreak(_, node) => node.cur = el.cloned(),
             Node::HardBreak(_, node) => node.cur = el.cloned(),
+            Node::ThematicBreak(_, node) => node.cur = el.cloned(),
             Node::InlineCode(_, node) => node.cur = el.cloned(),
+            Node::InlineMath(_, node) => node.cur = el.cloned(),
+            Node::InlineHtml(_, node) => node.cur = el.cloned(),
+            Node::Math(_, node) => node.cur = el.cloned(),
             Node::Delete(_, node) => node.cur = el.cloned(),
             Node::Emphasis(_, node) => node.cur = el.cloned(),
             Node::Html(_, node) => node.cur = el.cloned(),
             Node::Image(_, node) => node.cur = el.cloned(),
             Node::Link(_, node) => node.cur = el.cloned(),
+            Node::LinkReference(_, node) => node.cur = el.cloned(),
+            Node::ImageReference(_, node) => node.cur = el.cloned(),
+            Node::Definition(_, node) => node.cur = el.cloned(),
             Node::Strong(_, node) => node.cur = el.cloned(),
             Node::Code(_, node) => node.cur = el.cloned(),
             Node::Table(_, node) => node.cur = el.cloned(),
@@ -475,214 +577,899 @@ impl Node {
     // _=>{}
     // }
     // }
-    pub fn create_node(
+    #[must_use]
+    pub fn position(&self) -> Option<&Position> {
+        match self {
+            Node::Root(x, _) => x.position.as_ref(),
+            Node::BlockQuote(x, _) => x.position.as_ref(),
+            Node::FootnoteDefinition(x, _) => x.position.as_ref(),
+            Node::FootnoteReference(x, _) => x.position.as_ref(),
+            Node::List(x, _) => x.position.as_ref(),
+            Node::Toml(x, _) => x.position.as_ref(),
+            Node::Yaml(x, _) => x.position.as_ref(),
+            Node::SoftBreak(x, _) => x.position.as_ref(),
+            Node::HardBreak(x, _) => x.position.as_ref(),
+            Node::ThematicBreak(x, _) => x.position.as_ref(),
+            Node::InlineCode(x, _) => x.position.as_ref(),
+            Node::InlineMath(x, _) => x.position.as_ref(),
+            Node::InlineHtml(x, _) => x.position.as_ref(),
+            Node::Math(x, _) => x.position.as_ref(),
+            Node::Delete(x, _) => x.position.as_ref(),
+            Node::Emphasis(x, _) => x.position.as_ref(),
+            Node::Html(x, _) => x.position.as_ref(),
+            Node::Image(x, _) => x.position.as_ref(),
+            Node::Link(x, _) => x.position.as_ref(),
+            Node::LinkReference(x, _) => x.position.as_ref(),
+            Node::ImageReference(x, _) => x.position.as_ref(),
+            Node::Definition(x, _) => x.position.as_ref(),
+            Node::Strong(x, _) => x.position.as_ref(),
+            Node::Text(x, _) => x.position.as_ref(),
+            Node::Code(x, _) => x.position.as_ref(),
+            Node::Heading(x, _) => x.position.as_ref(),
+            Node::Table(x, _) => x.position.as_ref(),
+            Node::TableHead(x, _) => x.position.as_ref(),
+            Node::TableRow(x, _) => x.position.as_ref(),
+            Node::TableCell(x, _) => x.position.as_ref(),
+            Node::ListItem(x, _) => x.position.as_ref(),
+            Node::Paragraph(x, _) => x.position.as_ref(),
+        }
+    }
+
+    pub fn position_mut(&mut self) -> Option<&mut Position> {
+        match self {
+            Node::Root(x, _) => x.position.as_mut(),
+            Node::BlockQuote(x, _) => x.position.as_mut(),
+            Node::FootnoteDefinition(x, _) => x.position.as_mut(),
+            Node::FootnoteReference(x, _) => x.position.as_mut(),
+            Node::List(x, _) => x.position.as_mut(),
+            Node::Toml(x, _) => x.position.as_mut(),
+            Node::Yaml(x, _) => x.position.as_mut(),
+            Node::SoftBreak(x, _) => x.position.as_mut(),
+            Node::HardBreak(x, _) => x.position.as_mut(),
+            Node::ThematicBreak(x, _) => x.position.as_mut(),
+            Node::InlineCode(x, _) => x.position.as_mut(),
+            Node::InlineMath(x, _) => x.position.as_mut(),
+            Node::InlineHtml(x, _) => x.position.as_mut(),
+            Node::Math(x, _) => x.position.as_mut(),
+            Node::Delete(x, _) => x.position.as_mut(),
+            Node::Emphasis(x, _) => x.position.as_mut(),
+            Node::Html(x, _) => x.position.as_mut(),
+            Node::Image(x, _) => x.position.as_mut(),
+            Node::Link(x, _) => x.position.as_mut(),
+            Node::LinkReference(x, _) => x.position.as_mut(),
+            Node::ImageReference(x, _) => x.position.as_mut(),
+            Node::Definition(x, _) => x.position.as_mut(),
+            Node::Strong(x, _) => x.position.as_mut(),
+            Node::Text(x, _) => x.position.as_mut(),
+            Node::Code(x, _) => x.position.as_mut(),
+            Node::Heading(x, _) => x.position.as_mut(),
+            Node::Table(x, _) => x.position.as_mut(),
+            Node::TableHead(x, _) => x.position.as_mut(),
+            Node::TableRow(x, _) => x.position.as_mut(),
+            Node::TableCell(x, _) => x.position.as_mut(),
+            Node::ListItem(x, _) => x.position.as_mut(),
+            Node::Paragraph(x, _) => x.position.as_mut(),
+        }
+    }
+
+    // pub fn position_set(&mut self, position: Option<Position>) {
+    //     match self {
+    //         Node::Root(x) => x.position = position,
+    //         // Node::BlockQuote(x) => x.position = position,
+    //         // Node::FootnoteDefinition(x) => x.position = position,
+    //         // Node::MdxJsxFlowElement(x) => x.position = position,
+    //         Node::List(x) => x.position = position,
+    //         // Node::MdxjsEsm(x) => x.position = position,
+    //         // Node::Toml(x) => x.position = position,
+    //         // Node::Yaml(x) => x.position = position,
+    //         // Node::Break(x) => x.position = position,
+    //         // Node::InlineCode(x) => x.position = position,
+    //         // Node::InlineMath(x) => x.position = position,
+    //         // Node::Delete(x) => x.position = position,
+    //         // Node::Emphasis(x) => x.position = position,
+    //         // Node::MdxTextExpression(x) => x.position = position,
+    //         // Node::FootnoteReference(x) => x.position = position,
+    //         // Node::Html(x) => x.position = position,
+    //         // Node::Image(x) => x.position = position,
+    //         // Node::ImageReference(x) => x.position = position,
+    //         // Node::MdxJsxTextElement(x) => x.position = position,
+    //         // Node::Link(x) => x.position = position,
+    //         // Node::LinkReference(x) => x.position = position,
+    //         // Node::Strong(x) => x.position = position,
+    //         Node::Text(x) => x.position = position,
+    //         // Node::Code(x) => x.position = position,
+    //         // Node::Math(x) => x.position = position,
+    //         // Node::MdxFlowExpression(x) => x.position = position,
+    //         Node::Heading(x) => x.position = position,
+    //         // Node::Table(x) => x.position = position,
+    //         // Node::ThematicBreak(x) => x.position = position,
+    //         // Node::TableRow(x) => x.position = position,
+    //         // Node::TableCell(x) => x.position = position,
+    //         // Node::ListItem(x) => x.position = position,
+    //         // Node::Definition(x) => x.position = position,
+    //         // Node::Paragraph(x) => x.position = position,
+    //     }
+    // }
+}
+
+/// The index of a node in an [`Arena`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The nodes of document trees, stored side by side and linked by
+/// [`NodeId`]: nodes hold the ids of their children, the arena the id of
+/// the parent of each node.
+///
+/// A context keeps the tree it rendered and the one it renders next in the
+/// same arena, sharing the blocks an edit leaves alone. The nodes no tree
+/// uses anymore stay until [`Arena::compact`].
+#[derive(Clone, Debug, Default)]
+pub struct Arena {
+    nodes: Vec<Node>,
+    parents: Vec<Option<NodeId>>,
+    /// The shifts of positions not applied to the descendants of a node
+    /// yet, in bytes and lines, see [`Arena::shift`].
+    shifts: HashMap<NodeId, (isize, isize)>,
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of nodes, those no tree uses anymore included.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.parents.clear();
+        self.shifts.clear();
+    }
+
+    /// Make room for `additional` more nodes.
+    pub fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+        self.parents.reserve(additional);
+    }
+
+    /// Add `node`, which becomes the parent of its children.
+    pub fn push(&mut self, node: Node) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(node);
+        self.parents.push(None);
+        self.adopt(id);
+        id
+    }
+
+    /// Make `id` the parent of its children again, once they are changed.
+    /// The front matter and definitions of a root count as its children.
+    pub fn adopt(&mut self, id: NodeId) {
+        for child in links(&self.nodes[id.index()]) {
+            self.parents[child.index()] = Some(id);
+        }
+    }
+
+    /// Add `child` after the children of `parent`.
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        if let Some(children) = self[parent].children_mut() {
+            children.push(child);
+            self.parents[child.index()] = Some(parent);
+        }
+    }
+
+    /// The parent of `id` in the last tree it was put in.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.parents[id.index()]
+    }
+
+    /// The child `idx` of `id`.
+    pub fn child(&self, id: NodeId, idx: usize) -> Option<NodeId> {
+        self[id].children()?.get(idx).copied()
+    }
+
+    /// The node after `id` among the children of its parent.
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self[self.parent(id)?].children()?;
+        let idx = siblings.iter().position(|sibling| *sibling == id)?;
+        siblings.get(idx + 1).copied()
+    }
+
+    /// The node before `id` among the children of its parent.
+    pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self[self.parent(id)?].children()?;
+        let idx = siblings.iter().position(|sibling| *sibling == id)?;
+        siblings.get(idx.checked_sub(1)?).copied()
+    }
+
+    /// Plain text content of `id` and its descendants.
+    pub fn text_content(&self, id: NodeId) -> String {
+        let node = &self[id];
+        match node {
+            Node::Image(x, _) => x.alt.clone(),
+            Node::ImageReference(x, _) => x.alt.clone(),
+            Node::SoftBreak(_, _) | Node::HardBreak(_, _) => " ".to_string(),
+            _ => match node.children() {
+                Some(children) => children
+                    .iter()
+                    .map(|child| self.text_content(*child))
+                    .collect(),
+                None => node.value().cloned().unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Compute and cache the structural hash of `id`.
+    ///
+    /// The hash covers the node type, its content (value, url, title, depth...)
+    /// and the cached hashes of its children, but not its position, so that
+    /// identical blocks hash the same wherever they are in the document.
+    /// Children must have been hashed before their parent.
+    pub fn compute_hash(&mut self, id: NodeId) -> u64 {
+        let node = &self[id];
+        let mut hasher = DefaultHasher::new();
+        mem::discriminant(node).hash(&mut hasher);
+        node.value().hash(&mut hasher);
+        node.url().hash(&mut hasher);
+        node.title().hash(&mut hasher);
+        match node {
+            Node::Heading(x, _) => {
+                x.depth.hash(&mut hasher);
+                x.id.hash(&mut hasher);
+                x.classes.hash(&mut hasher);
+                x.slug.hash(&mut hasher);
+            }
+            Node::List(x, _) => {
+                x.start.hash(&mut hasher);
+                x.spread.hash(&mut hasher);
+            }
+            Node::Code(x, _) => x.lang.hash(&mut hasher),
+            Node::ListItem(x, _) => x.checked.hash(&mut hasher),
+            Node::Image(x, _) => x.alt.hash(&mut hasher),
+            Node::LinkReference(x, _) => {
+                x.label.hash(&mut hasher);
+                (x.link_type as u8).hash(&mut hasher);
+            }
+            Node::ImageReference(x, _) => {
+                x.alt.hash(&mut hasher);
+                x.label.hash(&mut hasher);
+                (x.link_type as u8).hash(&mut hasher);
+            }
+            Node::Definition(x, _) => x.label.hash(&mut hasher),
+            Node::InlineHtml(x, _) => x.close.hash(&mut hasher),
+            Node::Html(x, _) => {
+                x.close.hash(&mut hasher);
+                x.content.hash(&mut hasher);
+            }
+            Node::FootnoteDefinition(x, _) => {
+                x.label.hash(&mut hasher);
+                x.number.hash(&mut hasher);
+                x.occurrence.hash(&mut hasher);
+            }
+            Node::FootnoteReference(x, _) => {
+                x.label.hash(&mut hasher);
+                x.number.hash(&mut hasher);
+                x.occurrence.hash(&mut hasher);
+            }
+            Node::Table(x, _) => {
+                for alignment in &x.alignment {
+                    (*alignment as u8).hash(&mut hasher);
+                }
+            }
+            Node::TableCell(x, _) => (x.align as u8).hash(&mut hasher),
+            _ => {}
+        }
+        if let Some(children) = node.children() {
+            children.len().hash(&mut hasher);
+            for child in children {
+                self[*child].subtree_hash().hash(&mut hasher);
+            }
+        }
+        let hash = hasher.finish();
+        *self[id].subtree_hash_mut() = hash;
+        hash
+    }
+
+    /// Move the positions of `id` and of its descendants by `offset` bytes
+    /// and `lines` lines, as when the source before them is edited.
+    /// Columns are kept, the edit having to end at a line start.
+    ///
+    /// Only the positions of `id` move now, those of its descendants when
+    /// the arena is compacted: until then, read them with
+    /// [`Arena::position`] or [`Arena::shifted`].
+    pub fn shift(&mut self, id: NodeId, offset: isize, lines: isize) {
+        let node = &mut self[id];
+        if let Some(position) = node.position_mut() {
+            position.shift(offset, lines);
+        }
+        let extra = match node {
+            Node::Code(x, _) => x.value_position.as_mut(),
+            Node::ListItem(x, _) => x.checked_position.as_mut(),
+            Node::InlineHtml(x, _) => x.close_position.as_mut(),
+            Node::Html(x, _) => x.close_position.as_mut(),
+            _ => None,
+        };
+        if let Some(position) = extra {
+            position.shift(offset, lines);
+        }
+        if node.children().is_some_and(|children| !children.is_empty()) {
+            let shift = self.shifts.entry(id).or_default();
+            shift.0 += offset;
+            shift.1 += lines;
+        }
+    }
+
+    /// Apply the shifts left to the descendants of the nodes.
+    fn settle(&mut self) {
+        let mut shifts: Vec<_> = mem::take(&mut self.shifts).into_iter().collect();
+        while let Some((id, (offset, lines))) = shifts.pop() {
+            let mut idx = 0;
+            while let Some(child) = self.child(id, idx) {
+                self.shift(child, offset, lines);
+                idx += 1;
+            }
+            shifts.extend(self.shifts.drain());
+        }
+    }
+
+    /// The position of `id` in the source, with the shifts left to its
+    /// ancestors applied.
+    pub fn position(&self, id: NodeId) -> Option<Position> {
+        self.shifted(id, self[id].position())
+    }
+
+    /// `position`, of `id` or of a part of it, with the shifts left to the
+    /// ancestors of `id` applied.
+    pub fn shifted(&self, id: NodeId, position: Option<&Position>) -> Option<Position> {
+        let mut position = position?.clone();
+        let mut parent = self.parent(id).filter(|_| !self.shifts.is_empty());
+        while let Some(id) = parent {
+            if let Some((offset, lines)) = self.shifts.get(&id) {
+                position.shift(*offset, *lines);
+            }
+            parent = self.parent(id);
+        }
+        Some(position)
+    }
+
+    /// A copy of `id` whose descendants are copied too, instead of shared
+    /// with it.
+    pub fn deep_clone(&mut self, id: NodeId) -> NodeId {
+        let mut node = self[id].clone();
+        if let Some(children) = node.children_mut() {
+            for child in children.iter_mut() {
+                *child = self.deep_clone(*child);
+            }
+        }
+        let clone = self.push(node);
+        if let Some(shift) = self.shifts.get(&id).copied() {
+            self.shifts.insert(clone, shift);
+        }
+        clone
+    }
+
+    /// Keep only the tree of `root`, renumbering its nodes in document
+    /// order. Returns the new id of `root`; other ids are invalidated.
+    ///
+    /// Nodes are moved in place, the capacity is kept for the next tree.
+    pub fn compact(&mut self, root: NodeId) -> NodeId {
+        self.settle();
+        let mut ids: Vec<Option<NodeId>> = vec![None; self.nodes.len()];
+        let mut len = 0;
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            ids[id.index()] = Some(NodeId(len as u32));
+            len += 1;
+            let first = stack.len();
+            stack.extend(links(&self[id]));
+            stack[first..].reverse();
+        }
+        // Each swap puts a node in its place for good.
+        let mut places = ids.clone();
+        for idx in 0..places.len() {
+            while let Some(place) = places[idx].filter(|place| place.index() != idx) {
+                self.nodes.swap(idx, place.index());
+                places.swap(idx, place.index());
+            }
+        }
+        self.nodes.truncate(len);
+        self.parents.clear();
+        self.parents.resize(len, None);
+        for node in &mut self.nodes {
+            relink(node, |child| ids[child.index()].unwrap());
+        }
+        for idx in 0..len {
+            self.adopt(NodeId(idx as u32));
+        }
+        NodeId(0)
+    }
+
+    /// The number of nodes in the tree of `root`, to tell how much of the
+    /// arena [`Arena::compact`] would free.
+    pub fn count(&self, root: NodeId) -> usize {
+        let mut count = 0;
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            count += 1;
+            stack.extend(links(&self[id]));
+        }
+        count
+    }
+
+    /// Child indices leading from `id` to its deepest descendant whose
+    /// position contains the byte `offset`.
+    pub fn path_at(&self, id: NodeId, offset: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut cur = id;
+        while let Some(idx) = self[cur].children().and_then(|children| {
+            children.iter().position(|child| {
+                self.position(*child)
+                    .is_some_and(|position| position.contains(offset))
+            })
+        }) {
+            path.push(idx);
+            cur = self.child(cur, idx).unwrap();
+        }
+        path
+    }
+
+    /// Whether `id` is a table with body rows, rendered in a `<tbody>`.
+    pub(crate) fn table_body(&self, id: NodeId) -> bool {
+        match &self[id] {
+            Node::Table(x, _) => x
+                .children
+                .iter()
+                .any(|row| matches!(self[*row], Node::TableRow(_, _))),
+            _ => false,
+        }
+    }
+
+    /// Path from the DOM node of `id` to the one of its child `idx`.
+    ///
+    /// Children are rendered as the matching children of the DOM node,
+    /// except where the element has content of its own in front of them.
+    pub fn dom_child_path(&self, id: NodeId, idx: usize) -> Vec<usize> {
+        match &self[id] {
+            // After the footnote number.
+            Node::FootnoteDefinition(_, _) => vec![idx + 1],
+            // After the checkbox.
+            Node::ListItem(x, _) if x.checked.is_some() => vec![idx + 1],
+            // After the opening bracket of an undefined reference.
+            Node::LinkReference(x, _) if x.url.is_none() => vec![idx + 1],
+            // The head row is the `<tr>` of the `<thead>`, other rows are in
+            // the `<tbody>` that follows it, rendered with the first of them.
+            Node::Table(x, _) => {
+                let head = x
+                    .children
+                    .first()
+                    .is_some_and(|row| matches!(self[*row], Node::TableHead(_, _)));
+                match (head, idx) {
+                    (true, 0) => vec![0],
+                    (true, _) => vec![1, idx - 1],
+                    (false, _) => vec![0, idx],
+                }
+            }
+            Node::TableHead(_, _) => vec![0, idx],
+            // In the element its tags leave open.
+            Node::Html(x, _) => {
+                let (offset, prefix) = x.content.split_last().unwrap();
+                let mut path = prefix.to_vec();
+                path.push(offset + idx);
+                path
+            }
+            _ => vec![idx],
+        }
+    }
+
+    /// Render `id` and its descendants into the DOM node of `parent`.
+    pub fn create<D: Dom>(&mut self, id: NodeId, dom: &D, parent: NodeId, policy: &HtmlPolicy) {
+        let parent_node = self[parent]
+            .node_mut()
+            .and_then(DocNode::get::<D::Node>)
+            .filter(|node| dom.is_element(node))
+            .cloned();
+        if let Some(parent_node) = parent_node {
+            let head = matches!(self[id], Node::TableHead(_, _));
+            let child_element = self
+                .create_node(id, dom, Some(parent), policy)
+                .and_then(DocNode::get::<D::Node>)
+                .unwrap()
+                .clone();
+            let (container, before) = match (&self[parent], head) {
+                // Footnote definitions end with their back reference,
+                // undefined references with their label.
+                (Node::FootnoteDefinition(_, _), _) => {
+                    (parent_node.clone(), dom.last_child(&parent_node))
+                }
+                (Node::LinkReference(x, _), _) if x.url.is_none() => {
+                    (parent_node.clone(), dom.last_child(&parent_node))
+                }
+                // The head goes before the `<tbody>`, rows in it.
+                (Node::Table(_, _), true) => (parent_node.clone(), dom.first_child(&parent_node)),
+                (Node::Table(_, _), _) => (dom.last_child(&parent_node).unwrap(), None),
+                // The cells of the head go in its row.
+                (Node::TableHead(_, _), _) => (dom.first_child(&parent_node).unwrap(), None),
+                // Blocks between HTML tags go in the element they leave open.
+                (Node::Html(x, _), _) => {
+                    let (offset, prefix) = x.content.split_last().unwrap();
+                    let container = dom_at(dom, &parent_node, prefix).unwrap();
+                    let idx = x.children.iter().position(|child| *child == id).unwrap();
+                    let before = dom.child(&container, offset + idx);
+                    (container, before)
+                }
+                _ => (parent_node, None),
+            };
+            dom.insert_before(&container, &child_element, before.as_ref());
+        }
+        let mut idx = 0;
+        while let Some(child) = self.child(id, idx) {
+            self.create(child, dom, id, policy);
+            idx += 1;
+        }
+    }
+
+    /// Create the DOM node of `id`, without its children, which are
+    /// rendered in it by [`Arena::create`]. `parent` is the node it is
+    /// rendered in.
+    pub fn create_node<D: Dom>(
         &mut self,
-        document: &Document,
-        parent: Option<Rc<RefCell<Node>>>,
+        id: NodeId,
+        dom: &D,
+        parent: Option<NodeId>,
+        policy: &HtmlPolicy,
     ) -> Option<&DocNode> {
-        match self {
-            Node::Root(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("div").unwrap()));
-                node.parent = parent;
+        let in_head = parent.is_some_and(|parent| matches!(self[parent], Node::TableHead(_, _)));
+        let body = self.table_body(id);
+        match &mut self[id] {
+            Node::Root(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("div")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::BlockQuote(x, node) => {
-                node.cur = Some(DocNode::Element(
-                    document.create_element("blockquote").unwrap(),
-                ));
-                node.parent = parent;
+            Node::BlockQuote(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("blockquote")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
+            Node::FootnoteDefinition(x, node) => {
+                let div = dom.create_element("div");
+                dom.set_attribute(&div, "class", "footnote-definition");
+                dom.set_attribute(&div, "id", &x.id());
+                let number = dom.create_element("sup");
+                dom.set_attribute(&number, "class", "footnote-definition-label");
+                dom.set_text_content(&number, &x.number.to_string());
+                let back = dom.create_element("a");
+                dom.set_attribute(&back, "class", "footnote-backref");
+                dom.set_attribute(&back, "href", &format!("#fnref-{}", x.label));
+                dom.set_text_content(&back, "\u{21a9}");
+                dom.append_child(&div, &number);
+                dom.append_child(&div, &back);
+                node.cur = Some(DocNode::new(div));
+                node.cur.as_ref()
+            }
+            Node::FootnoteReference(x, node) => {
+                let sup = dom.create_element("sup");
+                dom.set_attribute(&sup, "class", "footnote-reference");
+                if x.number == 0 {
+                    dom.set_text_content(&sup, &format!("[^{}]", x.label));
+                } else {
+                    dom.set_attribute(&sup, "id", &x.id());
+                    let link = dom.create_element("a");
+                    dom.set_attribute(&link, "href", &format!("#fn-{}", x.label));
+                    dom.set_text_content(&link, &x.number.to_string());
+                    dom.append_child(&sup, &link);
+                }
+                node.cur = Some(DocNode::new(sup));
+                node.cur.as_ref()
+            }
             Node::List(x, node) => {
-                node.cur = match x.index {
-                    None => Some(DocNode::Element(document.create_element("ul").unwrap())),
-                    _ => Some(DocNode::Element(document.create_element("ol").unwrap())),
+                let list = match x.start {
+                    None => dom.create_element("ul"),
+                    Some(start) => {
+                        let list = dom.create_element("ol");
+                        if start != 1 {
+                            dom.set_attribute(&list, "start", &start.to_string());
+                        }
+                        list
+                    }
                 };
-                node.parent = parent;
+                node.cur = Some(DocNode::new(list));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::SoftBreak(x, node) => {
-                node.cur = Some(DocNode::Text(document.create_text_node(" ")));
-                node.parent = parent;
+            // A line ending, as in the source, for the text of `<pre>`.
+            Node::SoftBreak(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_text_node("\n")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::HardBreak(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("br").unwrap()));
-                node.parent = parent;
+            Node::HardBreak(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("br")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
+            Node::ThematicBreak(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("hr")));
+                node.cur.as_ref()
+            }
             Node::InlineCode(x, node) => {
-                let code = document.create_element("code").unwrap();
-                let content = document.create_text_node(&x.value.clone().unwrap());
-                code.append_child(&content).unwrap();
-                node.cur = Some(DocNode::Element(code));
-                node.parent = parent;
+                let code = dom.create_element("code");
+                let content = dom.create_text_node(&x.value.clone().unwrap());
+                dom.append_child(&code, &content);
+                node.cur = Some(DocNode::new(code));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::Delete(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("del").unwrap()));
-                node.parent = parent;
+            Node::InlineHtml(x, node) => {
+                let mut html = policy.render(x.value.as_deref().unwrap_or_default());
+                node.cur = Some(match &x.close {
+                    // The element holding the children.
+                    Some(close) => {
+                        html.push_str(&policy.render(close));
+                        let element = create_fragment(dom, &html, "span");
+                        if dom.is_element(&element) && dom.child_count(&element) == 0 {
+                            DocNode::new(element)
+                        } else {
+                            DocNode::new(contents_wrapper(dom, "span"))
+                        }
+                    }
+                    None => DocNode::new(create_fragment(dom, &html, "span")),
+                });
+                node.cur.as_ref()
+            }
+            Node::InlineMath(x, node) => {
+                let span = dom.create_element("span");
+                dom.set_attribute(&span, "class", "math math-inline");
+                append_html(
+                    dom,
+                    &span,
+                    &to_mathml(x.value.as_deref().unwrap_or_default(), false),
+                );
+                node.cur = Some(DocNode::new(span));
+                node.cur.as_ref()
+            }
+            Node::Math(x, node) => {
+                let div = dom.create_element("div");
+                dom.set_attribute(&div, "class", "math math-display");
+                append_html(
+                    dom,
+                    &div,
+                    &to_mathml(x.value.as_deref().unwrap_or_default(), true),
+                );
+                node.cur = Some(DocNode::new(div));
+                node.cur.as_ref()
+            }
+            Node::Delete(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("del")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::Emphasis(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("em").unwrap()));
-                node.parent = parent;
+            Node::Emphasis(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("em")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
+            // Front matter is not rendered.
+            Node::Toml(_, _) | Node::Yaml(_, _) => None,
+            // The lines of the block end with a line ending, as in the source.
             Node::Html(x, node) => {
-                let div = document.create_element("div").unwrap();
-                div.set_inner_html(&x.value.clone()?);
-                node.cur = Some(DocNode::Element(div));
-                node.parent = parent;
+                let mut html = policy.render(x.value.as_deref()?);
+                html.push('\n');
+                node.cur = Some(if x.content.is_empty() {
+                    DocNode::new(create_fragment(dom, &html, "div"))
+                } else {
+                    // The element holding the children.
+                    if let Some(close) = &x.close {
+                        html.push_str(&policy.render(close));
+                        html.push('\n');
+                    }
+                    DocNode::new(create_block_fragment(dom, &html))
+                });
                 node.cur.as_ref()
-                // for child in x.children{
-                //     child.create_node(document);
-                // }
             }
             Node::Image(x, node) => {
-                let link = document.create_element("img").unwrap();
-                if let Some(src) = &x.url {
-                    link.set_attribute("src", src).unwrap();
+                let link = dom.create_element("img");
+                if let Some(src) = x.url.as_deref().and_then(|url| policy.link_url(url)) {
+                    dom.set_attribute(&link, "src", &src);
                 }
-                if let Some(title) = &x.title {
-                    link.set_attribute("title", title).unwrap();
+                dom.set_attribute(&link, "alt", &x.alt);
+                if let Some(title) = x.title.as_deref().filter(|title| !title.is_empty()) {
+                    dom.set_attribute(&link, "title", title);
                 }
-                node.cur = Some(DocNode::Element(link));
-                node.parent = parent;
+                node.cur = Some(DocNode::new(link));
+                node.cur.as_ref()
+            }
+            // Not rendered, like front matter.
+            Node::Definition(_, _) => None,
+            Node::LinkReference(x, node) => {
+                let link = match &x.url {
+                    Some(url) => {
+                        let link = dom.create_element("a");
+                        if let Some(url) = policy.link_url(url) {
+                            dom.set_attribute(&link, "href", &url);
+                        }
+                        if let Some(title) = x.title.as_deref().filter(|title| !title.is_empty()) {
+                            dom.set_attribute(&link, "title", title);
+                        }
+                        link
+                    }
+                    // Undefined, shown as written.
+                    None => {
+                        let link = contents_wrapper(dom, "span");
+                        dom.append_child(&link, &dom.create_text_node("["));
+                        let close = reference_close(x.link_type, &x.label);
+                        dom.append_child(&link, &dom.create_text_node(&close));
+                        link
+                    }
+                };
+                node.cur = Some(DocNode::new(link));
+                node.cur.as_ref()
+            }
+            Node::ImageReference(x, node) => {
+                node.cur = Some(match &x.url {
+                    Some(url) => {
+                        let image = dom.create_element("img");
+                        if let Some(url) = policy.link_url(url) {
+                            dom.set_attribute(&image, "src", &url);
+                        }
+                        dom.set_attribute(&image, "alt", &x.alt);
+                        if let Some(title) = x.title.as_deref().filter(|title| !title.is_empty()) {
+                            dom.set_attribute(&image, "title", title);
+                        }
+                        DocNode::new(image)
+                    }
+                    None => DocNode::new(dom.create_text_node(&format!(
+                        "![{}{}",
+                        x.alt,
+                        reference_close(x.link_type, &x.label)
+                    ))),
+                });
                 node.cur.as_ref()
             }
             Node::Link(x, node) => {
-                let link = document.create_element("a").unwrap();
-                if let Some(url) = &x.url {
-                    link.set_attribute("href", url).unwrap();
+                let link = dom.create_element("a");
+                if let Some(url) = x.url.as_deref().and_then(|url| policy.link_url(url)) {
+                    dom.set_attribute(&link, "href", &url);
                 }
-                if let Some(title) = &x.title {
-                    link.set_attribute("title", title).unwrap();
+                if let Some(title) = x.title.as_deref().filter(|title| !title.is_empty()) {
+                    dom.set_attribute(&link, "title", title);
                 }
-                node.cur = Some(DocNode::Element(link));
-                node.parent = parent;
+                node.cur = Some(DocNode::new(link));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::Strong(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("strong").unwrap()));
-                node.parent = parent;
+            Node::Strong(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("strong")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
             Node::Text(x, node) => {
-                node.cur = Some(DocNode::Text(
-                    document.create_text_node(&x.value.clone().unwrap()),
+                node.cur = Some(DocNode::new(
+                    dom.create_text_node(&x.value.clone().unwrap()),
                 ));
-                node.parent = parent;
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
             Node::Code(x, node) => {
-                let pre = document.create_element("pre").unwrap();
-                let code = document.create_element("code").unwrap();
-                let content = document
-                    .create_text_node(&x.value.clone().map_or_else(|| "".to_string(), |x| x));
-                pre.append_child(&code)
-                    .unwrap()
-                    .append_child(&content)
-                    .unwrap();
-                node.cur = Some(DocNode::Element(pre));
-                node.parent = parent;
+                let pre = dom.create_element("pre");
+                let code = dom.create_element("code");
+                if let Some(lang) = x.lang.as_deref().filter(|lang| !lang.is_empty()) {
+                    dom.set_attribute(&code, "class", &format!("language-{}", lang));
+                }
+                let value = x.value.as_deref().unwrap_or_default();
+                for token in highlight(x.lang.as_deref(), value) {
+                    dom.append_child(&code, &create_token(dom, token.class, token.text));
+                }
+                dom.append_child(&pre, &code);
+                node.cur = Some(DocNode::new(pre));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
             Node::Heading(x, node) => {
-                node.cur = Some(DocNode::Element(
-                    document
-                        .create_element(create_heading_text(x.depth))
-                        .unwrap(),
-                ));
-                node.parent = parent;
+                let heading = dom.create_element(create_heading_text(x.depth));
+                if !x.slug.is_empty() {
+                    dom.set_attribute(&heading, "id", &x.slug);
+                }
+                if !x.classes.is_empty() {
+                    dom.set_attribute(&heading, "class", &x.classes.join(" "));
+                }
+                node.cur = Some(DocNode::new(heading));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::Table(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("table").unwrap()));
-                node.parent = parent;
+            Node::Table(_, node) => {
+                let table = dom.create_element("table");
+                if body {
+                    dom.append_child(&table, &dom.create_element("tbody"));
+                }
+                node.cur = Some(DocNode::new(table));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::TableHead(x, node) => {
-                // let head = document.create_element("thead").unwrap();
-                let tr = document.create_element("tr").unwrap();
-
-                // head.append_child(&tr).unwrap();
-                node.cur = Some(DocNode::Element(tr));
-                node.parent = parent;
+            Node::TableHead(_, node) => {
+                let head = dom.create_element("thead");
+                let tr = dom.create_element("tr");
+                dom.append_child(&head, &tr);
+                node.cur = Some(DocNode::new(head));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::TableRow(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("tr").unwrap()));
-                node.parent = parent;
+            Node::TableRow(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("tr")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
             Node::TableCell(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("th").unwrap()));
-                node.parent = parent;
+                let cell = dom.create_element(if in_head { "th" } else { "td" });
+                if let Some(style) = x.style() {
+                    dom.set_attribute(&cell, "style", style);
+                }
+                node.cur = Some(DocNode::new(cell));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
             Node::ListItem(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("li").unwrap()));
-                node.parent = parent;
+                let item = dom.create_element("li");
+                if let Some(checked) = x.checked {
+                    dom.set_attribute(&item, "class", "task-list-item");
+                    let input = dom.create_element("input");
+                    dom.set_attribute(&input, "type", "checkbox");
+                    dom.set_checked(&input, checked);
+                    dom.append_child(&item, &input);
+                }
+                node.cur = Some(DocNode::new(item));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
                 // }
             }
-            Node::Paragraph(x, node) => {
-                node.cur = Some(DocNode::Element(document.create_element("p").unwrap()));
-                node.parent = parent;
+            Node::Paragraph(_, node) => {
+                node.cur = Some(DocNode::new(dom.create_element("p")));
                 node.cur.as_ref()
                 // for child in x.children{
                 //     child.create_node(document);
@@ -690,136 +1477,97 @@ impl Node {
             }
         }
     }
-    // #[must_use]
-    // pub fn position(&self) -> Option<&Position> {
-    //     match self {
-    //         Node::Root(x) => x.position.as_ref(),
-    //         // Node::BlockQuote(x) => x.position.as_ref(),
-    //         // Node::FootnoteDefinition(x) => x.position.as_ref(),
-    //         // Node::MdxJsxFlowElement(x) => x.position.as_ref(),
-    //         Node::List(x) => x.position.as_ref(),
-    //         // Node::MdxjsEsm(x) => x.position.as_ref(),
-    //         // Node::Toml(x) => x.position.as_ref(),
-    //         // Node::Yaml(x) => x.position.as_ref(),
-    //         // Node::Break(x) => x.position.as_ref(),
-    //         // Node::InlineCode(x) => x.position.as_ref(),
-    //         // Node::InlineMath(x) => x.position.as_ref(),
-    //         // Node::Delete(x) => x.position.as_ref(),
-    //         // Node::Emphasis(x) => x.position.as_ref(),
-    //         // Node::MdxTextExpression(x) => x.position.as_ref(),
-    //         // Node::FootnoteReference(x) => x.position.as_ref(),
-    //         // Node::Html(x) => x.position.as_ref(),
-    //         // Node::Image(x) => x.position.as_ref(),
-    //         // Node::ImageReference(x) => x.position.as_ref(),
-    //         // Node::MdxJsxTextElement(x) => x.position.as_ref(),
-    //         // Node::Link(x) => x.position.as_ref(),
-    //         // Node::LinkReference(x) => x.position.as_ref(),
-    //         // Node::Strong(x) => x.position.as_ref(),
-    //         Node::Text(x) => x.position.as_ref(),
-    //         // Node::Code(x) => x.position.as_ref(),
-    //         // Node::Math(x) => x.position.as_ref(),
-    //         // Node::MdxFlowExpression(x) => x.position.as_ref(),
-    //         Node::Heading(x) => x.position.as_ref(),
-    //         // Node::Table(x) => x.position.as_ref(),
-    //         // Node::ThematicBreak(x) => x.position.as_ref(),
-    //         // Node::TableRow(x) => x.position.as_ref(),
-    //         // Node::TableCell(x) => x.position.as_ref(),
-    //         // Node::ListItem(x) => x.position.as_ref(),
-    //         // Node::Definition(x) => x.position.as_ref(),
-    //         // Node::Paragraph(x) => x.position.as_ref(),
-    //     }
-    // }
+}
 
-    // pub fn position_mut(&mut self) -> Option<&mut Position> {
-    //     match self {
-    //         Node::Root(x) => x.position.as_mut(),
-    //         // Node::BlockQuote(x) => x.position.as_mut(),
-    //         // Node::FootnoteDefinition(x) => x.position.as_mut(),
-    //         // Node::MdxJsxFlowElement(x) => x.position.as_mut(),
-    //         Node::List(x) => x.position.as_mut(),
-    //         // Node::MdxjsEsm(x) => x.position.as_mut(),
-    //         // Node::Toml(x) => x.position.as_mut(),
-    //         // Node::Yaml(x) => x.position.as_mut(),
-    //         // Node::Break(x) => x.position.as_mut(),
-    //         // Node::InlineCode(x) => x.position.as_mut(),
-    //         // Node::InlineMath(x) => x.position.as_mut(),
-    //         // Node::Delete(x) => x.position.as_mut(),
-    //         // Node::Emphasis(x) => x.position.as_mut(),
-    //         // Node::MdxTextExpression(x) => x.position.as_mut(),
-    //         // Node::FootnoteReference(x) => x.position.as_mut(),
-    //         // Node::Html(x) => x.position.as_mut(),
-    //         // Node::Image(x) => x.position.as_mut(),
-    //         // Node::ImageReference(x) => x.position.as_mut(),
-    //         // Node::MdxJsxTextElement(x) => x.position.as_mut(),
-    //         // Node::Link(x) => x.position.as_mut(),
-    //         // Node::LinkReference(x) => x.position.as_mut(),
-    //         // Node::Strong(x) => x.position.as_mut(),
-    //         Node::Text(x) => x.position.as_mut(),
-    //         // Node::Code(x) => x.position.as_mut(),
-    //         // Node::Math(x) => x.position.as_mut(),
-    //         // Node::MdxFlowExpression(x) => x.position.as_mut(),
-    //         Node::Heading(x) => x.position.as_mut(),
-    //         // Node::Table(x) => x.position.as_mut(),
-    //         // Node::ThematicBreak(x) => x.position.as_mut(),
-    //         // Node::TableRow(x) => x.position.as_mut(),
-    //         // Node::TableCell(x) => x.position.as_mut(),
-    //         // Node::ListItem(x) => x.position.as_mut(),
-    //         // Node::Definition(x) => x.position.as_mut(),
-    //         // Node::Paragraph(x) => x.position.as_mut(),
-    //     }
-    // }
+impl Index<NodeId> for Arena {
+    type Output = Node;
 
-    // pub fn position_set(&mut self, position: Option<Position>) {
-    //     match self {
-    //         Node::Root(x) => x.position = position,
-    //         // Node::BlockQuote(x) => x.position = position,
-    //         // Node::FootnoteDefinition(x) => x.position = position,
-    //         // Node::MdxJsxFlowElement(x) => x.position = position,
-    //         Node::List(x) => x.position = position,
-    //         // Node::MdxjsEsm(x) => x.position = position,
-    //         // Node::Toml(x) => x.position = position,
-    //         // Node::Yaml(x) => x.position = position,
-    //         // Node::Break(x) => x.position = position,
-    //         // Node::InlineCode(x) => x.position = position,
-    //         // Node::InlineMath(x) => x.position = position,
-    //         // Node::Delete(x) => x.position = position,
-    //         // Node::Emphasis(x) => x.position = position,
-    //         // Node::MdxTextExpression(x) => x.position = position,
-    //         // Node::FootnoteReference(x) => x.position = position,
-    //         // Node::Html(x) => x.position = position,
-    //         // Node::Image(x) => x.position = position,
-    //         // Node::ImageReference(x) => x.position = position,
-    //         // Node::MdxJsxTextElement(x) => x.position = position,
-    //         // Node::Link(x) => x.position = position,
-    //         // Node::LinkReference(x) => x.position = position,
-    //         // Node::Strong(x) => x.position = position,
-    //         Node::Text(x) => x.position = position,
-    //         // Node::Code(x) => x.position = position,
-    //         // Node::Math(x) => x.position = position,
-    //         // Node::MdxFlowExpression(x) => x.position = position,
-    //         Node::Heading(x) => x.position = position,
-    //         // Node::Table(x) => x.position = position,
-    //         // Node::ThematicBreak(x) => x.position = position,
-    //         // Node::TableRow(x) => x.position = position,
-    //         // Node::TableCell(x) => x.position = position,
-    //         // Node::ListItem(x) => x.position = position,
-    //         // Node::Definition(x) => x.position = position,
-    //         // Node::Paragraph(x) => x.position = position,
-    //     }
-    // }
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.index()]
+    }
+}
+
+impl IndexMut<NodeId> for Arena {
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.index()]
+    }
+}
+
+/// The nodes `node` links to: its children, and the front matter and
+/// definitions of a root.
+fn links(node: &Node) -> impl Iterator<Item = NodeId> + '_ {
+    let (frontmatter, definitions) = match node {
+        Node::Root(x, _) => (x.frontmatter, x.definitions.as_slice()),
+        _ => (None, &[][..]),
+    };
+    node.children()
+        .into_iter()
+        .flatten()
+        .chain(definitions)
+        .copied()
+        .chain(frontmatter)
+}
+
+/// Replace the ids `node` links to with `map` of them.
+fn relink(node: &mut Node, map: impl Fn(NodeId) -> NodeId) {
+    if let Node::Root(x, _) = node {
+        x.frontmatter = x.frontmatter.map(&map);
+        for id in &mut x.definitions {
+            *id = map(*id);
+        }
+    }
+    for id in node.children_mut().into_iter().flatten() {
+        *id = map(*id);
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Root {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
+    /// Leading `Yaml` or `Toml` block. It is kept out of `children` as it is
+    /// not rendered.
+    pub frontmatter: Option<NodeId>,
+    /// Link reference definitions, in source order, duplicates included.
+    /// They are not rendered either.
+    pub definitions: Vec<NodeId>,
+}
+
+impl Root {
+    /// The children with the definitions put back among them in source
+    /// order. Definitions nested in containers come out at the top level,
+    /// definitions without a position at the end.
+    pub fn flow(&self, arena: &Arena) -> Vec<NodeId> {
+        let offset = |id: NodeId| {
+            arena[id]
+                .position()
+                .map_or(usize::MAX, |position| position.start.offset)
+        };
+        let mut flow = Vec::new();
+        let mut definitions = self.definitions.iter().copied().peekable();
+        for child in &self.children {
+            while let Some(definition) = definitions.next_if(|x| offset(*x) < offset(*child)) {
+                flow.push(definition);
+            }
+            flow.push(*child);
+        }
+        flow.extend(definitions);
+        flow
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Heading {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
     pub depth: u8,
+    /// Id given with a `{#id}` attribute.
+    pub id: Option<String>,
+    /// Classes given with `{.class}` attributes.
+    pub classes: Vec<String>,
+    /// Anchor of the heading: its `id`, or a slug of its text unique in the
+    /// document, see [`crate::slug::assign`].
+    pub slug: String,
 }
 #[derive(Clone, Debug, PartialEq)]
 pub struct Text {
@@ -829,14 +1577,36 @@ pub struct Text {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct List {
-    pub index: Option<u64>,
-    pub children: Vec<Rc<RefCell<Node>>>,
+    /// Number of the first item, `None` for bullet lists.
+    pub start: Option<u64>,
+    /// Whether the list is loose: its items are separated by blank lines and
+    /// their content is wrapped in paragraphs.
+    pub spread: bool,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
 }
+
+impl List {
+    /// Whether some item holds paragraphs, which the parser only emits in
+    /// loose lists.
+    pub fn has_paragraphs(&self, arena: &Arena) -> bool {
+        self.children.iter().any(|item| {
+            arena[*item].children().is_some_and(|children| {
+                children
+                    .iter()
+                    .any(|child| matches!(arena[*child], Node::Paragraph(_, _)))
+            })
+        })
+    }
+}
 #[derive(Clone, Debug, PartialEq)]
 pub struct ListItem {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
+    /// State of the task list checkbox, `None` for plain items.
+    pub checked: Option<bool>,
+    /// Where the `[ ]` or `[x]` marker sits in the source.
+    pub checked_position: Option<Position>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -844,72 +1614,205 @@ pub struct Code {
     pub lang: Option<String>,
     pub value: Option<String>,
     pub position: Option<Position>,
+    /// Where `value` sits in the source, inside the fences.
+    pub value_position: Option<Position>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Paragraph {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Emphasis {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Strong {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Table {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
     pub alignment: Vec<Alignment>,
 }
+
+impl Table {
+    /// Give every cell the alignment of its column, rehashing the cells and
+    /// rows that changed.
+    pub fn align_cells(&self, arena: &mut Arena) {
+        for row in &self.children {
+            let mut changed = false;
+            let mut idx = 0;
+            while let Some(cell) = arena.child(*row, idx) {
+                let align = self.alignment.get(idx).copied().unwrap_or(Alignment::None);
+                if let Node::TableCell(x, _) = &mut arena[cell] {
+                    if x.align != align {
+                        x.align = align;
+                        changed = true;
+                    }
+                }
+                if changed {
+                    arena.compute_hash(cell);
+                }
+                idx += 1;
+            }
+            if changed {
+                arena.compute_hash(*row);
+            }
+        }
+    }
+}
 #[derive(Clone, Debug, PartialEq)]
 pub struct TableHead {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
 }
 #[derive(Clone, Debug, PartialEq)]
 pub struct TableRow {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
 }
 #[derive(Clone, Debug, PartialEq)]
 pub struct TableCell {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
+    /// Alignment of the column of the cell, see [`Table::align_cells`].
+    pub align: Alignment,
+}
+
+impl TableCell {
+    /// Inline style of the cell for its alignment.
+    pub fn style(&self) -> Option<&'static str> {
+        match self.align {
+            Alignment::Left => Some("text-align: left"),
+            Alignment::Center => Some("text-align: center"),
+            Alignment::Right => Some("text-align: right"),
+            Alignment::None => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct BlockQuote {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
+    pub position: Option<Position>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FootnoteDefinition {
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
+    pub label: String,
+    /// Number of the footnote, in order of first reference.
+    pub number: usize,
+    /// Which definition of the label this is, starting from 1. Later ones
+    /// are duplicates, numbered as unreferenced footnotes.
+    pub occurrence: usize,
+}
+
+impl FootnoteDefinition {
+    /// Element id of the definition, the target of the references.
+    pub fn id(&self) -> String {
+        if self.occurrence > 1 {
+            format!("fn-{}-{}", self.label, self.occurrence)
+        } else {
+            format!("fn-{}", self.label)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FootnoteReference {
+    pub position: Option<Position>,
+    pub label: String,
+    /// Number of the referenced footnote, `0` when it is not defined.
+    pub number: usize,
+    /// Which reference to the footnote this is, starting from 1.
+    pub occurrence: usize,
+}
+
+impl FootnoteReference {
+    /// Element id of the reference, the target of the back references.
+    pub fn id(&self) -> String {
+        if self.occurrence > 1 {
+            format!("fnref-{}-{}", self.label, self.occurrence)
+        } else {
+            format!("fnref-{}", self.label)
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Delete {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Link {
-    pub children: Vec<Rc<RefCell<Node>>>,
+    pub children: Vec<NodeId>,
     pub position: Option<Position>,
     pub link_type: LinkType,
     pub url: Option<String>,
     pub title: Option<String>,
 }
 
+/// A reference link: `[text][label]`, `[label][]` or `[label]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkReference {
+    pub children: Vec<NodeId>,
+    pub position: Option<Position>,
+    pub label: String,
+    /// `Reference`, `Collapsed` or `Shortcut`.
+    pub link_type: LinkType,
+    /// Destination of the definition, `None` when the label is not defined.
+    pub url: Option<String>,
+    pub title: Option<String>,
+}
+
+/// A reference image: `![alt][label]`, `![label][]` or `![label]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageReference {
+    pub position: Option<Position>,
+    pub alt: String,
+    pub label: String,
+    /// `Reference`, `Collapsed` or `Shortcut`.
+    pub link_type: LinkType,
+    /// Destination of the definition, `None` when the label is not defined.
+    pub url: Option<String>,
+    pub title: Option<String>,
+}
+
+/// A link reference definition: `[label]: url "title"`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definition {
+    pub position: Option<Position>,
+    pub label: String,
+    pub url: Option<String>,
+    pub title: Option<String>,
+}
+
+/// What follows the text of a reference of `link_type` to `label` in the
+/// source: `][label]`, `][]` or `]`.
+pub fn reference_close(link_type: LinkType, label: &str) -> String {
+    match link_type {
+        LinkType::Collapsed | LinkType::CollapsedUnknown => "][]".to_string(),
+        LinkType::Shortcut | LinkType::ShortcutUnknown => "]".to_string(),
+        _ => format!("][{}]", label),
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Image {
     pub position: Option<Position>,
+    pub alt: String,
     pub link_type: LinkType,
     pub url: Option<String>,
     pub title: Option<String>,
@@ -925,52 +1828,147 @@ pub struct HardBreak {
     pub position: Option<Position>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThematicBreak {
+    pub position: Option<Position>,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct InlineCode {
     pub value: Option<String>,
     pub position: Option<Position>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct InlineMath {
+    pub value: Option<String>,
+    pub position: Option<Position>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Math {
+    pub value: Option<String>,
+    pub position: Option<Position>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Toml {
+    pub value: Option<String>,
+    pub position: Option<Position>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Yaml {
+    pub value: Option<String>,
+    pub position: Option<Position>,
+}
+
+/// Html (flow): the lines of an HTML block, without the final line ending.
+///
+/// A block leaving elements open holds the blocks up to the one closing
+/// them as `children`, with the closing block as `close`, see
+/// [`crate::raw_html::nest`].
 #[derive(Clone, Debug, PartialEq)]
 pub struct Html {
     pub value: Option<String>,
+    pub close: Option<String>,
+    pub children: Vec<NodeId>,
+    /// Child indices from the rendered block to the innermost element it
+    /// leaves open, then the index of the first child in that element.
+    /// Empty for a leaf block.
+    pub content: Vec<usize>,
+    /// Position of the whole element.
     pub position: Option<Position>,
+    pub close_position: Option<Position>,
 }
 
+/// Html (phrasing): a tag or comment, see [`crate::raw_html::nest`].
+///
+/// An opening tag followed by its closing tag holds the content between
+/// them as `children`, with the closing tag as `close`.
 #[derive(Clone, Debug, PartialEq)]
+pub struct InlineHtml {
+    pub value: Option<String>,
+    pub close: Option<String>,
+    pub children: Vec<NodeId>,
+    /// Position of the whole element.
+    pub position: Option<Position>,
+    pub close_position: Option<Position>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct ElementNode {
     pub cur: Option<DocNode>,
-    pub parent: Option<Rc<RefCell<Node>>>,
+    pub hash: u64,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct TextNode {
     pub cur: Option<DocNode>,
-    pub parent: Option<Rc<RefCell<Node>>>,
+    pub hash: u64,
 }
 
-impl ElementNode {
-    pub fn default() -> Self {
-        ElementNode {
-            cur: None,
-            parent: None,
+/// The DOM of the HTML fragment `html`: its only element or text node,
+/// inserted in place, or a `wrapper` element laid out as its content.
+fn create_fragment<D: Dom>(dom: &D, html: &str, wrapper: &str) -> D::Node {
+    let nodes = fragment_nodes(dom, html);
+    if let [node] = nodes.as_slice() {
+        if dom.is_element(node) || dom.text(node).is_some() {
+            return node.clone();
         }
     }
+    let element = contents_wrapper(dom, wrapper);
+    for node in nodes {
+        dom.append_child(&element, &node);
+    }
+    element
 }
 
-impl TextNode {
-    pub fn default() -> Self {
-        TextNode {
-            cur: None,
-            parent: None,
-        }
+/// The nodes of the HTML fragment `html`, but for whitespace between them.
+pub(crate) fn fragment_nodes<D: Dom>(dom: &D, html: &str) -> Vec<D::Node> {
+    dom.parse_html(html)
+        .into_iter()
+        .filter(|node| dom.text(node).is_none_or(|text| !text.trim().is_empty()))
+        .collect()
+}
+
+/// The DOM of the HTML fragment `html` holding blocks: its only element,
+/// or a `<div>` laid out as its content.
+pub(crate) fn create_block_fragment<D: Dom>(dom: &D, html: &str) -> D::Node {
+    let node = create_fragment(dom, html, "div");
+    if dom.is_element(&node) {
+        return node;
     }
+    let element = contents_wrapper(dom, "div");
+    dom.append_child(&element, &node);
+    element
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum DocNode {
-    Element(Element),
-    Text(TextElement),
+/// Append the nodes of the HTML fragment `html` to `element`.
+fn append_html<D: Dom>(dom: &D, element: &D::Node, html: &str) {
+    for node in dom.parse_html(html) {
+        dom.append_child(element, &node);
+    }
+}
+
+/// An element that does not generate a box of its own.
+fn contents_wrapper<D: Dom>(dom: &D, tag: &str) -> D::Node {
+    let element = dom.create_element(tag);
+    dom.set_attribute(&element, "style", "display: contents");
+    element
+}
+
+/// A highlighted `<span>` of code, or a text node for plain code.
+pub(crate) fn create_token<D: Dom>(dom: &D, class: Option<&str>, text: &str) -> D::Node {
+    match class {
+        Some(class) => {
+            let span = dom.create_element("span");
+            dom.set_attribute(&span, "class", class);
+            dom.set_text_content(&span, text);
+            span
+        }
+        None => dom.create_text_node(text),
+    }
 }
 
 fn create_heading_text(depth: u8) -> &'static str {
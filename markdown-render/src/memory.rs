@@ -0,0 +1,401 @@
+use std::{
+    cell::RefCell,
+    fmt, mem,
+    rc::{Rc, Weak},
+};
+
+use crate::{dom::Dom, html::escape_html};
+
+/// A DOM kept in memory, to render and patch documents without a browser.
+///
+/// Raw HTML is parsed into elements, text and markup kept as written, with
+/// the common entities decoded; the state of checkboxes is their `checked`
+/// attribute.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Document;
+
+/// A node of a [`Document`]. Clones refer to the same node.
+#[derive(Clone)]
+pub struct Node(Rc<RefCell<Data>>);
+
+struct Data {
+    kind: Kind,
+    parent: Weak<RefCell<Data>>,
+    children: Vec<Node>,
+}
+
+enum Kind {
+    Element {
+        tag: String,
+        attributes: Vec<(String, String)>,
+    },
+    Text(String),
+    /// Comments, doctypes and processing instructions, as written.
+    Raw(String),
+}
+
+/// Elements without content nor end tag.
+const VOID: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+impl Node {
+    fn new(kind: Kind) -> Self {
+        Node(Rc::new(RefCell::new(Data {
+            kind,
+            parent: Weak::new(),
+            children: Vec::new(),
+        })))
+    }
+
+    /// The tag name of an element.
+    pub fn tag(&self) -> Option<String> {
+        match &self.0.borrow().kind {
+            Kind::Element { tag, .. } => Some(tag.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        match &self.0.borrow().kind {
+            Kind::Element { attributes, .. } => attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone()),
+            _ => None,
+        }
+    }
+
+    /// The attributes of an element, in the order they were first set.
+    pub fn attributes(&self) -> Vec<(String, String)> {
+        match &self.0.borrow().kind {
+            Kind::Element { attributes, .. } => attributes.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn children(&self) -> Vec<Node> {
+        self.0.borrow().children.clone()
+    }
+
+    /// The text of the node and its descendants.
+    pub fn text_content(&self) -> String {
+        let data = self.0.borrow();
+        match &data.kind {
+            Kind::Text(text) => text.clone(),
+            Kind::Raw(_) => String::new(),
+            Kind::Element { .. } => data.children.iter().map(Node::text_content).collect(),
+        }
+    }
+
+    /// The HTML of the node.
+    pub fn outer_html(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out);
+        out
+    }
+
+    /// The HTML of the children of the node.
+    pub fn inner_html(&self) -> String {
+        let mut out = String::new();
+        for child in &self.0.borrow().children {
+            child.write(&mut out);
+        }
+        out
+    }
+
+    fn write(&self, out: &mut String) {
+        let data = self.0.borrow();
+        match &data.kind {
+            Kind::Text(text) => escape_html(text, out),
+            Kind::Raw(raw) => out.push_str(raw),
+            Kind::Element { tag, attributes } => {
+                out.push('<');
+                out.push_str(tag);
+                for (name, value) in attributes {
+                    out.push(' ');
+                    out.push_str(name);
+                    out.push_str("=\"");
+                    escape_html(value, out);
+                    out.push('"');
+                }
+                out.push('>');
+                if VOID.contains(&tag.as_str()) {
+                    return;
+                }
+                for child in &data.children {
+                    child.write(out);
+                }
+                out.push_str("</");
+                out.push_str(tag);
+                out.push('>');
+            }
+        }
+    }
+
+    fn detach(&self) {
+        let parent = self.0.borrow().parent.upgrade();
+        if let Some(parent) = parent {
+            parent
+                .borrow_mut()
+                .children
+                .retain(|child| !Rc::ptr_eq(&child.0, &self.0));
+        }
+        self.0.borrow_mut().parent = Weak::new();
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.outer_html())
+    }
+}
+
+impl Dom for Document {
+    type Node = Node;
+
+    fn create_element(&self, tag: &str) -> Node {
+        Node::new(Kind::Element {
+            tag: tag.to_ascii_lowercase(),
+            attributes: Vec::new(),
+        })
+    }
+
+    fn create_text_node(&self, text: &str) -> Node {
+        Node::new(Kind::Text(text.to_string()))
+    }
+
+    fn parse_html(&self, html: &str) -> Vec<Node> {
+        let root = self.create_element("template");
+        parse(self, &root, html);
+        let children = root.children();
+        for child in &children {
+            child.detach();
+        }
+        children
+    }
+
+    fn is_element(&self, node: &Node) -> bool {
+        node.tag().is_some()
+    }
+
+    fn text(&self, node: &Node) -> Option<String> {
+        match &node.0.borrow().kind {
+            Kind::Text(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    fn set_attribute(&self, element: &Node, name: &str, value: &str) {
+        if let Kind::Element { attributes, .. } = &mut element.0.borrow_mut().kind {
+            match attributes.iter_mut().find(|(key, _)| key == name) {
+                Some((_, old)) => *old = value.to_string(),
+                None => attributes.push((name.to_string(), value.to_string())),
+            }
+        }
+    }
+
+    fn remove_attribute(&self, element: &Node, name: &str) {
+        if let Kind::Element { attributes, .. } = &mut element.0.borrow_mut().kind {
+            attributes.retain(|(key, _)| key != name);
+        }
+    }
+
+    fn set_text_content(&self, node: &Node, text: &str) {
+        if !self.is_element(node) {
+            if let Kind::Text(old) | Kind::Raw(old) = &mut node.0.borrow_mut().kind {
+                *old = text.to_string();
+            }
+            return;
+        }
+        let children = mem::take(&mut node.0.borrow_mut().children);
+        for child in children {
+            child.0.borrow_mut().parent = Weak::new();
+        }
+        if !text.is_empty() {
+            self.append_child(node, &self.create_text_node(text));
+        }
+    }
+
+    fn set_checked(&self, input: &Node, checked: bool) {
+        if let Kind::Element { attributes, .. } = &mut input.0.borrow_mut().kind {
+            attributes.retain(|(key, _)| key != "checked");
+            if checked {
+                attributes.push(("checked".to_string(), String::new()));
+            }
+        }
+    }
+
+    fn parent(&self, node: &Node) -> Option<Node> {
+        node.0.borrow().parent.upgrade().map(Node)
+    }
+
+    fn child_count(&self, node: &Node) -> usize {
+        node.0.borrow().children.len()
+    }
+
+    fn child(&self, node: &Node, idx: usize) -> Option<Node> {
+        node.0.borrow().children.get(idx).cloned()
+    }
+
+    fn insert_before(&self, parent: &Node, child: &Node, before: Option<&Node>) {
+        child.detach();
+        let mut data = parent.0.borrow_mut();
+        let idx = before
+            .and_then(|before| data.children.iter().position(|child| child == before))
+            .unwrap_or(data.children.len());
+        data.children.insert(idx, child.clone());
+        child.0.borrow_mut().parent = Rc::downgrade(&parent.0);
+    }
+
+    fn remove_child(&self, parent: &Node, child: &Node) {
+        if self.parent(child).as_ref() == Some(parent) {
+            child.detach();
+        }
+    }
+}
+
+/// Append the nodes of `html` to `root`. Unclosed elements are closed at
+/// the end, unmatched end tags ignored.
+fn parse(dom: &Document, root: &Node, html: &str) {
+    let mut open = vec![root.clone()];
+    let mut rest = html;
+    while !rest.is_empty() {
+        let parent = open.last().unwrap().clone();
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").map_or(comment.len(), |idx| idx + 3);
+            let raw = Node::new(Kind::Raw(format!("<!--{}", &comment[..end])));
+            dom.append_child(&parent, &raw);
+            rest = &comment[end..];
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>').map_or(rest.len(), |idx| idx + 1);
+            dom.append_child(&parent, &Node::new(Kind::Raw(rest[..end].to_string())));
+            rest = &rest[end..];
+        } else if let Some(tag) = rest.strip_prefix("</") {
+            let end = tag.find('>').map_or(tag.len(), |idx| idx + 1);
+            let name = tag[..end].trim_end_matches('>').trim().to_ascii_lowercase();
+            if let Some(idx) = open
+                .iter()
+                .rposition(|node| node.tag().as_ref() == Some(&name))
+            {
+                open.truncate(idx.max(1));
+            }
+            rest = &tag[end..];
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+        {
+            let (element, closed, len) = start_tag(dom, rest);
+            dom.append_child(&parent, &element);
+            if !closed && !VOID.contains(&element.tag().unwrap_or_default().as_str()) {
+                open.push(element);
+            }
+            rest = &rest[len..];
+        } else {
+            let first = rest.chars().next().map_or(1, char::len_utf8);
+            let end = rest[first..]
+                .find('<')
+                .map_or(rest.len(), |idx| idx + first);
+            dom.append_child(&parent, &dom.create_text_node(&decode(&rest[..end])));
+            rest = &rest[end..];
+        }
+    }
+}
+
+/// The element of the start tag starting `html`, whether it closes itself,
+/// and the length of the tag.
+fn start_tag(dom: &Document, html: &str) -> (Node, bool, usize) {
+    let name_end = html[1..]
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .map_or(html.len(), |idx| idx + 1);
+    let element = dom.create_element(&html[1..name_end]);
+    let mut idx = name_end;
+    loop {
+        let rest = &html[idx..];
+        let trimmed = rest.trim_start();
+        idx += rest.len() - trimmed.len();
+        if trimmed.is_empty() {
+            return (element, false, idx);
+        }
+        if trimmed.starts_with('>') {
+            return (element, false, idx + 1);
+        }
+        if trimmed.starts_with("/>") {
+            return (element, true, idx + 2);
+        }
+        let name_len = trimmed
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+            .unwrap_or(trimmed.len())
+            .max(1);
+        let name = trimmed[..name_len].to_ascii_lowercase();
+        idx += name_len;
+        let rest = &html[idx..];
+        let value = match rest.trim_start().strip_prefix('=') {
+            Some(value) => {
+                let value = value.trim_start();
+                idx = html.len() - value.len();
+                match value.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let end = value[1..].find(quote).map_or(value.len(), |end| end + 2);
+                        idx += end;
+                        decode(value[1..end].trim_end_matches(quote))
+                    }
+                    _ => {
+                        let end = value
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(value.len());
+                        idx += end;
+                        decode(&value[..end])
+                    }
+                }
+            }
+            None => String::new(),
+        };
+        dom.set_attribute(&element, &name, &value);
+    }
+}
+
+/// `text` with the common named and the numeric character references
+/// decoded.
+fn decode(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find('&') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        let end = rest.find(';').filter(|end| *end <= 10);
+        let decoded = end.and_then(|end| match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            entity => {
+                let number = entity.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (decoded, end) {
+            (Some(c), Some(end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
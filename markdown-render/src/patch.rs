@@ -0,0 +1,184 @@
+use crate::{
+    dom::{DocNode, Dom},
+    mdast::{create_token, Arena, NodeId},
+    sanitize::HtmlPolicy,
+};
+
+/// A single DOM edit produced by [`crate::diff::diff`].
+///
+/// Paths are child indices starting from the rendered root, so `[]` is the
+/// root element and `[2, 0]` is the first child of its third child. Patches
+/// are applied in order and every path refers to the tree as left by the
+/// previous patches.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Patch {
+    /// Insert the node found at `source` in the new tree so that it ends up
+    /// at `path`. `html` is that node rendered, to apply the patch without
+    /// the tree.
+    Insert {
+        path: Vec<usize>,
+        source: Vec<usize>,
+        html: String,
+    },
+    /// Insert a highlighted token of code at `path`: a `<span>` with `class`,
+    /// or a text node.
+    InsertToken {
+        path: Vec<usize>,
+        class: Option<String>,
+        text: String,
+    },
+    /// Remove the node at `path`.
+    Remove { path: Vec<usize> },
+    /// Detach the node at `from` and re-insert it at `to`.
+    Move { from: Vec<usize>, to: Vec<usize> },
+    /// Replace the text of the node at `path`.
+    SetText { path: Vec<usize>, value: String },
+    /// Set an attribute on the element at `path`.
+    SetAttr {
+        path: Vec<usize>,
+        name: String,
+        value: String,
+    },
+    /// Remove an attribute from the element at `path`.
+    RemoveAttr { path: Vec<usize>, name: String },
+    /// Check or uncheck the checkbox at `path`.
+    SetChecked { path: Vec<usize>, checked: bool },
+}
+
+/// Apply `patches` to the DOM mounted under `root`, building inserted nodes
+/// from the tree of `new_root` with raw HTML rendered under `policy`.
+///
+/// Fails on a patch whose path leads to no node, in the DOM or in the tree,
+/// leaving the patches before it applied.
+pub fn apply<D: Dom>(
+    dom: &D,
+    root: &D::Node,
+    arena: &mut Arena,
+    new_root: NodeId,
+    patches: &[Patch],
+    policy: &HtmlPolicy,
+) -> Result<(), String> {
+    for patch in patches {
+        match patch {
+            Patch::Insert { path, source, .. } => {
+                let (index, parent_path) = split_path(path)?;
+                let (child_idx, parent_source) = split_path(source)?;
+                let parent_id = node_at(arena, new_root, parent_source)
+                    .ok_or_else(|| missing("tree", source))?;
+                let node_id = node_at(arena, parent_id, &[child_idx])
+                    .ok_or_else(|| missing("tree", source))?;
+                mount(dom, arena, node_id, parent_id, policy);
+                let parent = dom_at(dom, root, parent_path).ok_or_else(|| missing("DOM", path))?;
+                let child = arena[node_id]
+                    .node_mut()
+                    .and_then(DocNode::get::<D::Node>)
+                    .ok_or_else(|| format!("the node at {:?} is not rendered", source))?
+                    .clone();
+                insert_at(dom, &parent, &child, index);
+            }
+            Patch::InsertToken { path, class, text } => {
+                let (index, parent_path) = split_path(path)?;
+                let parent = dom_at(dom, root, parent_path).ok_or_else(|| missing("DOM", path))?;
+                let token = create_token(dom, class.as_deref(), text);
+                insert_at(dom, &parent, &token, index);
+            }
+            Patch::Remove { path } => {
+                let child = dom_at(dom, root, path).ok_or_else(|| missing("DOM", path))?;
+                let parent = dom.parent(&child).ok_or_else(|| missing("DOM", path))?;
+                dom.remove_child(&parent, &child);
+            }
+            Patch::Move { from, to } => {
+                let (index, parent_path) = split_path(to)?;
+                let child = dom_at(dom, root, from).ok_or_else(|| missing("DOM", from))?;
+                let parent = dom.parent(&child).ok_or_else(|| missing("DOM", from))?;
+                dom.remove_child(&parent, &child);
+                let parent = dom_at(dom, root, parent_path).ok_or_else(|| missing("DOM", to))?;
+                insert_at(dom, &parent, &child, index);
+            }
+            Patch::SetText { path, value } => {
+                let node = dom_at(dom, root, path).ok_or_else(|| missing("DOM", path))?;
+                // Inline code and code tokens keep their text in their first child.
+                match dom.first_child(&node) {
+                    Some(child) => dom.set_text_content(&child, value),
+                    None if !dom.is_element(&node) => dom.set_text_content(&node, value),
+                    None => {}
+                }
+            }
+            Patch::SetAttr { path, name, value } => {
+                if let Some(element) = dom_at(dom, root, path) {
+                    dom.set_attribute(&element, name, value);
+                }
+            }
+            Patch::RemoveAttr { path, name } => {
+                if let Some(element) = dom_at(dom, root, path) {
+                    dom.remove_attribute(&element, name);
+                }
+            }
+            Patch::SetChecked { path, checked } => {
+                if let Some(input) = dom_at(dom, root, path) {
+                    dom.set_checked(&input, *checked);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The last index of `path` and the path of its parent.
+fn split_path(path: &[usize]) -> Result<(usize, &[usize]), String> {
+    match path.split_last() {
+        Some((index, parent)) => Ok((*index, parent)),
+        None => Err("cannot insert at the root".to_string()),
+    }
+}
+
+fn missing(tree: &str, path: &[usize]) -> String {
+    format!("no node at {:?} in the {}", path, tree)
+}
+
+/// Create the detached DOM subtree of `id`, a child of `parent`.
+pub fn mount<D: Dom>(dom: &D, arena: &mut Arena, id: NodeId, parent: NodeId, policy: &HtmlPolicy) {
+    let _ = arena.create_node(id, dom, Some(parent), policy);
+    let mut idx = 0;
+    while let Some(child) = arena.child(id, idx) {
+        arena.create(child, dom, id, policy);
+        idx += 1;
+    }
+}
+
+fn node_at(arena: &Arena, root: NodeId, path: &[usize]) -> Option<NodeId> {
+    let mut cur = root;
+    for idx in path {
+        cur = arena.child(cur, *idx)?;
+    }
+    Some(cur)
+}
+
+pub(crate) fn dom_at<D: Dom>(dom: &D, root: &D::Node, path: &[usize]) -> Option<D::Node> {
+    let mut cur = root.clone();
+    for idx in path {
+        cur = dom.child(&cur, *idx)?;
+    }
+    Some(cur)
+}
+
+/// The path of `node` from `root`, if it is one of its descendants.
+pub(crate) fn dom_path<D: Dom>(dom: &D, root: &D::Node, node: &D::Node) -> Option<Vec<usize>> {
+    let mut path = Vec::new();
+    let mut cur = node.clone();
+    while &cur != root {
+        let parent = dom.parent(&cur)?;
+        let idx = (0..dom.child_count(&parent))
+            .position(|idx| dom.child(&parent, idx).as_ref() == Some(&cur))?;
+        path.push(idx);
+        cur = parent;
+    }
+    path.reverse();
+    Some(path)
+}
+
+fn insert_at<D: Dom>(dom: &D, parent: &D::Node, child: &D::Node, index: usize) {
+    let before = dom.child(parent, index);
+    dom.insert_before(parent, child, before.as_ref());
+}
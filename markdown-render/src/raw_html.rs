@@ -0,0 +1,305 @@
+use std::mem;
+
+use crate::{
+    mdast::{create_block_fragment, fragment_nodes, Arena, Node, NodeId},
+    memory,
+    sanitize::{parse_tag, HtmlPolicy},
+};
+
+/// Elements without content, never closed.
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Nest the siblings between an inline opening tag and its closing tag in
+/// the opening `InlineHtml` node, so that `<kbd>Ctrl</kbd>` renders as one
+/// element. Unmatched tags stay leaves.
+///
+/// HTML blocks are paired the same way, so that the blocks between
+/// `<details>` and `</details>` render in the element. A block left open
+/// holds the blocks after it in its container, as the browser does.
+/// Raw HTML is rendered under `policy` to find where they go.
+///
+/// Returns whether the tree changed; the hashes of changed nodes are
+/// recomputed.
+pub fn nest(arena: &mut Arena, id: NodeId, policy: &HtmlPolicy) -> bool {
+    let mut changed = false;
+    if let Some(children) = arena[id].children_mut() {
+        let mut children = mem::take(children);
+        changed = pair(arena, &mut children);
+        changed |= pair_blocks(arena, &mut children, policy);
+        *arena[id].children_mut().unwrap() = children;
+    }
+    let mut idx = 0;
+    while let Some(child) = arena.child(id, idx) {
+        changed |= nest(arena, child, policy);
+        idx += 1;
+    }
+    if changed {
+        arena.compute_hash(id);
+    }
+    changed
+}
+
+/// Pair the opening and closing tags among `children`.
+fn pair(arena: &mut Arena, children: &mut Vec<NodeId>) -> bool {
+    let mut changed = false;
+    let mut idx = 0;
+    while idx < children.len() {
+        let name = match opening(&arena[children[idx]]) {
+            Some(name) => name,
+            None => {
+                idx += 1;
+                continue;
+            }
+        };
+        if let Some(close) = closing_index(arena, children, idx, &name) {
+            let closing = arena[children.remove(close)].clone();
+            let content: Vec<_> = children.drain(idx + 1..close).collect();
+            let id = children[idx];
+            if let Node::InlineHtml(x, _) = &mut arena[id] {
+                x.close = closing.value().cloned();
+                x.children = content;
+                if let (Some(open), Some(close)) = (&mut x.position, closing.position()) {
+                    x.close_position = Some(close.clone());
+                    open.end = close.end.clone();
+                }
+            }
+            arena.adopt(id);
+            arena.compute_hash(id);
+            changed = true;
+        }
+        idx += 1;
+    }
+    changed
+}
+
+/// The name of the element opened by the leaf `node`, if it is an inline
+/// opening tag that needs closing.
+fn opening(node: &Node) -> Option<String> {
+    match node {
+        Node::InlineHtml(x, _) if x.close.is_none() => {
+            let value = x.value.as_deref()?;
+            let tag = parse_tag(value).filter(|tag| tag.len == value.len())?;
+            if tag.closing || tag.self_closing || VOID.contains(&tag.name.as_str()) {
+                return None;
+            }
+            Some(tag.name)
+        }
+        _ => None,
+    }
+}
+
+/// The index of the tag closing the element opened at `open`, skipping the
+/// nested elements of the same name.
+fn closing_index(arena: &Arena, children: &[NodeId], open: usize, name: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, child) in children.iter().enumerate().skip(open + 1) {
+        let child = &arena[*child];
+        if opening(child).as_deref() == Some(name) {
+            depth += 1;
+            continue;
+        }
+        let value = match child {
+            Node::InlineHtml(x, _) if x.close.is_none() => x.value.as_deref(),
+            _ => None,
+        };
+        let tag = value.and_then(|value| parse_tag(value).filter(|tag| tag.len == value.len()));
+        if tag.is_some_and(|tag| tag.closing && tag.name == name) {
+            if depth == 0 {
+                return Some(idx);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// Pair the HTML blocks among `children` leaving elements open with the
+/// blocks closing them.
+fn pair_blocks(arena: &mut Arena, children: &mut Vec<NodeId>, policy: &HtmlPolicy) -> bool {
+    let mut changed = false;
+    let mut idx = 0;
+    while idx < children.len() {
+        let name = match opening_block(&arena[children[idx]]) {
+            Some(name) => name,
+            None => {
+                idx += 1;
+                continue;
+            }
+        };
+        let close = closing_block_index(arena, children, idx, &name);
+        let end = close.unwrap_or(children.len());
+        if end == idx + 1 && close.is_none() {
+            idx += 1;
+            continue;
+        }
+        let closing = close.map(|close| arena[children.remove(close)].clone());
+        let content: Vec<_> = children.drain(idx + 1..end).collect();
+        let end = match &closing {
+            Some(closing) => closing.position().cloned(),
+            None => content
+                .last()
+                .and_then(|last| arena[*last].position().cloned()),
+        };
+        let id = children[idx];
+        if let Node::Html(x, _) = &mut arena[id] {
+            let close = closing
+                .as_ref()
+                .and_then(|closing| closing.value().cloned());
+            x.content = content_path(
+                x.value.as_deref().unwrap_or_default(),
+                close.as_deref(),
+                policy,
+            );
+            x.close = close;
+            x.children = content;
+            if let (Some(open), Some(end)) = (&mut x.position, end) {
+                open.end = end.end.clone();
+                if closing.is_some() {
+                    x.close_position = Some(end);
+                }
+            }
+        }
+        arena.adopt(id);
+        arena.compute_hash(id);
+        changed = true;
+        idx += 1;
+    }
+    changed
+}
+
+/// The name of the outermost element left open by the leaf HTML block
+/// `node`, if any.
+fn opening_block(node: &Node) -> Option<String> {
+    match node {
+        Node::Html(x, _) if x.children.is_empty() && x.close.is_none() => {
+            open_elements(x.value.as_deref()?).into_iter().next()
+        }
+        _ => None,
+    }
+}
+
+/// Whether the HTML block `node` leaves elements open without a block
+/// closing them, holding the blocks after it in its container.
+pub(crate) fn unclosed(node: &Node) -> bool {
+    match node {
+        Node::Html(x, _) if x.close.is_none() => {
+            !open_elements(x.value.as_deref().unwrap_or_default()).is_empty()
+        }
+        _ => false,
+    }
+}
+
+/// The names of the elements closed by the leaf HTML block `node`, if it
+/// only holds closing tags.
+fn closing_block(node: &Node) -> Option<Vec<String>> {
+    let value = match node {
+        Node::Html(x, _) if x.children.is_empty() && x.close.is_none() => x.value.as_deref()?,
+        _ => return None,
+    };
+    let mut names = Vec::new();
+    let mut rest = value.trim_start();
+    while !rest.is_empty() {
+        let tag = parse_tag(rest).filter(|tag| tag.closing)?;
+        names.push(tag.name);
+        rest = rest[tag.len..].trim_start();
+    }
+    Some(names)
+}
+
+/// The index of the block closing the element opened at `open`, skipping
+/// the nested elements of the same name.
+fn closing_block_index(
+    arena: &Arena,
+    children: &[NodeId],
+    open: usize,
+    name: &str,
+) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, child) in children.iter().enumerate().skip(open + 1) {
+        let child = &arena[*child];
+        if opening_block(child).as_deref() == Some(name) {
+            depth += 1;
+            continue;
+        }
+        let names = closing_block(child).unwrap_or_default();
+        let closed = names.iter().filter(|closed| *closed == name).count();
+        if closed > depth {
+            return Some(idx);
+        }
+        depth -= closed;
+    }
+    None
+}
+
+/// The names of the elements `html` leaves open, outermost first. Closing
+/// tags close the elements opened in it up to theirs, unmatched ones are
+/// ignored.
+fn open_elements(html: &str) -> Vec<String> {
+    let mut open: Vec<String> = Vec::new();
+    let mut rest = html;
+    while let Some(idx) = rest.find('<') {
+        rest = &rest[idx..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let tag = match parse_tag(rest) {
+            Some(tag) => tag,
+            None => {
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        rest = &rest[tag.len..];
+        if tag.closing {
+            if let Some(idx) = open.iter().rposition(|name| *name == tag.name) {
+                open.truncate(idx);
+            }
+        } else if !tag.self_closing && !VOID.contains(&tag.name.as_str()) {
+            open.push(tag.name);
+        }
+    }
+    open
+}
+
+/// Where the children of the block `open`, closed by `close` if at all, are
+/// rendered, see [`crate::mdast::Html::content`]. It is found in the
+/// in-memory DOM of the blocks rendered under `policy`.
+fn content_path(open: &str, close: Option<&str>, policy: &HtmlPolicy) -> Vec<usize> {
+    let dom = memory::Document;
+    let open = policy.render(open);
+    let mut html = format!("{}\n", open);
+    if let Some(close) = close {
+        html.push_str(&policy.render(close));
+        html.push('\n');
+    }
+    let mut node = create_block_fragment(&dom, &html);
+    let mut names = open_elements(&open).into_iter().peekable();
+    // The block is rendered as its element, or in a wrapper.
+    if let [element] = fragment_nodes(&dom, &html).as_slice() {
+        if element.tag().is_some() && names.peek() == element.tag().as_ref() {
+            names.next();
+        }
+    }
+    let mut path = Vec::new();
+    for name in names {
+        let last = node
+            .children()
+            .into_iter()
+            .enumerate()
+            .rev()
+            .find(|(_, child)| child.tag().is_some());
+        match last {
+            Some((idx, child)) if child.tag() == Some(name) => {
+                path.push(idx);
+                node = child;
+            }
+            _ => break,
+        }
+    }
+    path.push(node.children().len());
+    path
+}
@@ -0,0 +1,361 @@
+use std::{collections::HashMap, mem, ops::Range};
+
+use pulldown_cmark::LinkType;
+
+use crate::{
+    event::{LineIndex, Position},
+    mdast::{Arena, Definition, ElementNode, Node, NodeId},
+};
+
+/// A reference whose label has no definition. It is rendered as written.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Unresolved {
+    pub label: String,
+    /// `full`, `collapsed` or `shortcut`, as mdast names them.
+    pub reference_type: &'static str,
+    pub image: bool,
+    pub position: Option<Position>,
+}
+
+/// A definition, as listed by [`list`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DefinitionEntry {
+    pub label: String,
+    pub url: String,
+    pub title: Option<String>,
+    /// Whether an earlier definition has the same label: this one is unused.
+    pub duplicate: bool,
+    pub position: Option<Position>,
+}
+
+/// The definitions and unresolved references of a document.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct References {
+    pub definitions: Vec<DefinitionEntry>,
+    pub unresolved: Vec<Unresolved>,
+}
+
+/// The label matching `label`: trimmed, with inner whitespace collapsed and
+/// Unicode case folded, so that `ẞ` matches `SS` as in the parser. Going
+/// through upper case folds the letters whose lower case is not their fold.
+pub fn normalize(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .to_uppercase()
+        .to_lowercase()
+}
+
+/// The mdast `referenceType` of a reference of `link_type`.
+pub fn reference_type(link_type: LinkType) -> &'static str {
+    match link_type {
+        LinkType::Collapsed | LinkType::CollapsedUnknown => "collapsed",
+        LinkType::Shortcut | LinkType::ShortcutUnknown => "shortcut",
+        _ => "full",
+    }
+}
+
+/// `link_type` without the `Unknown` variants the parser gives to the
+/// references resolved through its callback.
+pub fn known(link_type: LinkType) -> LinkType {
+    match link_type {
+        LinkType::ReferenceUnknown => LinkType::Reference,
+        LinkType::CollapsedUnknown => LinkType::Collapsed,
+        LinkType::ShortcutUnknown => LinkType::Shortcut,
+        link_type => link_type,
+    }
+}
+
+/// The `LinkType` of an mdast `referenceType`.
+pub fn link_type(reference_type: &str) -> LinkType {
+    match reference_type {
+        "collapsed" => LinkType::Collapsed,
+        "shortcut" => LinkType::Shortcut,
+        _ => LinkType::Reference,
+    }
+}
+
+/// The label of the reference link or image written as `source`: the last
+/// bracketed part of a full reference, the text of the others.
+pub fn label(source: &str, link_type: LinkType) -> String {
+    let source = source.strip_prefix('!').unwrap_or(source);
+    let inner = match link_type {
+        LinkType::Reference | LinkType::ReferenceUnknown => {
+            source.strip_suffix(']').and_then(|rest| {
+                // The last bracket not escaped with a backslash.
+                let escaped =
+                    |idx: usize| rest[..idx].len() - rest[..idx].trim_end_matches('\\').len();
+                rest.rmatch_indices('[')
+                    .map(|(idx, _)| idx)
+                    .find(|idx| escaped(*idx) % 2 == 0)
+                    .map(|idx| &rest[idx + 1..])
+            })
+        }
+        // The parser may leave the `[]` of collapsed references out.
+        _ => source
+            .strip_suffix("[]")
+            .unwrap_or(source)
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']')),
+    };
+    inner.unwrap_or_default().to_string()
+}
+
+/// A definition as the parser kept it: the first one of its label.
+pub struct Parsed {
+    pub label: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub span: Range<usize>,
+}
+
+/// Add the definition nodes of `range` of the source of `lines` to `arena`,
+/// in source order.
+///
+/// The parser only keeps the first definition of a label; the later ones
+/// are found back on the lines outside of `covered`, the source ranges of
+/// the rendered leaf blocks.
+pub fn definitions(
+    arena: &mut Arena,
+    parsed: &[Parsed],
+    covered: &[Range<usize>],
+    lines: &LineIndex,
+    range: Range<usize>,
+) -> Vec<NodeId> {
+    if parsed.is_empty() {
+        return Vec::new();
+    }
+    let labels: HashMap<String, &Parsed> = parsed
+        .iter()
+        .map(|definition| (normalize(&definition.label), definition))
+        .collect();
+    let mut found: Vec<(Range<usize>, String, String, Option<String>)> = parsed
+        .iter()
+        .map(|x| {
+            (
+                x.span.clone(),
+                x.label.clone(),
+                x.url.clone(),
+                x.title.clone(),
+            )
+        })
+        .collect();
+    // Sorted and merged, to search them.
+    let mut spans: Vec<Range<usize>> = covered
+        .iter()
+        .chain(parsed.iter().map(|x| &x.span))
+        .map(|x| x.start..x.end.max(x.start + 1))
+        .collect();
+    spans.sort_by_key(|x| x.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+            _ => merged.push(span),
+        }
+    }
+    let mut start = range.start;
+    for line in lines.source()[range].split_inclusive('\n') {
+        let end = start + line.trim_end().len();
+        let text = line.trim_start_matches(|c: char| c.is_whitespace() || c == '>');
+        let offset = start + line.len() - text.len();
+        let idx = merged.partition_point(|x| x.start <= offset);
+        let inside = idx > 0 && offset < merged[idx - 1].end;
+        if !inside {
+            if let Some((label, url, title)) = parse_line(text.trim_end()) {
+                if labels.contains_key(&normalize(&label)) {
+                    found.push((offset..end, label, url, title));
+                }
+            }
+        }
+        start += line.len();
+    }
+    found.sort_by_key(|(span, ..)| span.start);
+    found
+        .into_iter()
+        .map(|(span, label, url, title)| {
+            let id = arena.push(Node::Definition(
+                Definition {
+                    position: Some(lines.position(span)),
+                    label,
+                    url: Some(url),
+                    title,
+                },
+                ElementNode::default(),
+            ));
+            arena.compute_hash(id);
+            id
+        })
+        .collect()
+}
+
+/// The label, destination and title of a one line definition.
+fn parse_line(text: &str) -> Option<(String, String, Option<String>)> {
+    let rest = text.strip_prefix('[')?;
+    let close = rest.find(']')?;
+    let label = &rest[..close];
+    if label.trim().is_empty() || label.contains('[') {
+        return None;
+    }
+    let rest = rest[close + 1..].strip_prefix(':')?.trim_start();
+    let (url, rest) = match rest.strip_prefix('<') {
+        Some(rest) => {
+            let end = rest.find('>')?;
+            (&rest[..end], &rest[end + 1..])
+        }
+        None => {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        }
+    };
+    let title = rest.trim();
+    let title = match title.chars().next() {
+        Some(quote @ ('"' | '\'')) => title
+            .strip_prefix(quote)
+            .and_then(|title| title.strip_suffix(quote)),
+        Some('(') => title
+            .strip_prefix('(')
+            .and_then(|title| title.strip_suffix(')')),
+        _ => None,
+    };
+    Some((
+        label.to_string(),
+        url.to_string(),
+        title.map(str::to_string),
+    ))
+}
+
+/// Move the definitions found in the tree of `root` into its `definitions`,
+/// as they come from an mdast import.
+pub fn take_definitions(arena: &mut Arena, root: NodeId) {
+    let mut definitions = Vec::new();
+    take(arena, root, &mut definitions);
+    if let Node::Root(x, _) = &mut arena[root] {
+        x.definitions.extend(definitions);
+    }
+    arena.adopt(root);
+}
+
+fn take(arena: &mut Arena, id: NodeId, definitions: &mut Vec<NodeId>) -> bool {
+    let mut changed = false;
+    if let Some(children) = arena[id].children_mut() {
+        let before = children.len();
+        let (taken, kept): (Vec<_>, Vec<_>) = mem::take(children)
+            .into_iter()
+            .partition(|child| matches!(arena[*child], Node::Definition(_, _)));
+        changed = kept.len() != before;
+        *arena[id].children_mut().unwrap() = kept;
+        definitions.extend(taken);
+    }
+    let mut idx = 0;
+    while let Some(child) = arena.child(id, idx) {
+        changed |= take(arena, child, definitions);
+        idx += 1;
+    }
+    if changed {
+        arena.compute_hash(id);
+    }
+    changed
+}
+
+/// Set the destination and title of the references under `id` from the
+/// first of `definitions` with their label, or clear them when there is
+/// none. Returns whether a reference changed; the hashes of changed nodes
+/// are recomputed.
+pub fn resolve(arena: &mut Arena, id: NodeId, definitions: &[NodeId]) -> bool {
+    let mut targets: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
+    for definition in definitions.iter().rev() {
+        if let Node::Definition(x, _) = &arena[*definition] {
+            targets.insert(normalize(&x.label), (x.url.clone(), x.title.clone()));
+        }
+    }
+    resolve_with(arena, id, &targets)
+}
+
+fn resolve_with(
+    arena: &mut Arena,
+    id: NodeId,
+    targets: &HashMap<String, (Option<String>, Option<String>)>,
+) -> bool {
+    let mut changed = false;
+    let reference = match &mut arena[id] {
+        Node::LinkReference(x, _) => Some((&x.label, &mut x.url, &mut x.title)),
+        Node::ImageReference(x, _) => Some((&x.label, &mut x.url, &mut x.title)),
+        _ => None,
+    };
+    if let Some((label, url, title)) = reference {
+        let (new_url, new_title) = match targets.get(&normalize(label)) {
+            Some((url, title)) => (Some(url.clone().unwrap_or_default()), title.clone()),
+            None => (None, None),
+        };
+        if *url != new_url || *title != new_title {
+            *url = new_url;
+            *title = new_title;
+            changed = true;
+        }
+    }
+    let mut idx = 0;
+    while let Some(child) = arena.child(id, idx) {
+        changed |= resolve_with(arena, child, targets);
+        idx += 1;
+    }
+    if changed {
+        arena.compute_hash(id);
+    }
+    changed
+}
+
+/// The definitions of `root` and its unresolved references: the references
+/// in the tree without a definition, followed by `unresolved`, the ones the
+/// parser rendered as text.
+pub fn list(arena: &Arena, root: NodeId, unresolved: &[Unresolved]) -> References {
+    let mut seen = HashMap::new();
+    let definitions = match &arena[root] {
+        Node::Root(x, _) => x
+            .definitions
+            .iter()
+            .filter_map(|definition| match &arena[*definition] {
+                Node::Definition(x, _) => Some(DefinitionEntry {
+                    label: x.label.clone(),
+                    url: x.url.clone().unwrap_or_default(),
+                    title: x.title.clone(),
+                    duplicate: seen.insert(normalize(&x.label), ()).is_some(),
+                    position: x.position.clone(),
+                }),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let mut references = Vec::new();
+    collect_unresolved(arena, root, &mut references);
+    references.extend(unresolved.iter().cloned());
+    References {
+        definitions,
+        unresolved: references,
+    }
+}
+
+fn collect_unresolved(arena: &Arena, id: NodeId, unresolved: &mut Vec<Unresolved>) {
+    let node = &arena[id];
+    let reference = match node {
+        Node::LinkReference(x, _) if x.url.is_none() => Some((&x.label, x.link_type, false)),
+        Node::ImageReference(x, _) if x.url.is_none() => Some((&x.label, x.link_type, true)),
+        _ => None,
+    };
+    if let Some((label, link_type, image)) = reference {
+        unresolved.push(Unresolved {
+            label: label.clone(),
+            reference_type: reference_type(link_type),
+            image,
+            position: arena.position(id),
+        });
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            collect_unresolved(arena, *child, unresolved);
+        }
+    }
+}
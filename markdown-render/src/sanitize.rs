@@ -0,0 +1,545 @@
+use std::{borrow::Cow, collections::BTreeSet};
+
+use wasm_bindgen::prelude::*;
+
+use crate::html::escape_html;
+
+/// How raw HTML in the markdown is rendered.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HtmlMode {
+    /// Show the HTML as text.
+    Escape,
+    /// Keep the allowed tags and attributes only.
+    Sanitize,
+    /// Insert the HTML as is. Only for content the page fully trusts.
+    Trusted,
+}
+
+/// What raw HTML may contain once rendered, see [`HtmlPolicy::render`].
+///
+/// The default sanitizes with an allow-list close to GitHub's.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HtmlPolicy {
+    pub mode: HtmlMode,
+    /// Allowed tags. Other tags are dropped but their content is kept.
+    pub tags: BTreeSet<String>,
+    /// Allowed attributes. Event handlers (`on*`) are always dropped.
+    pub attributes: BTreeSet<String>,
+    /// Schemes allowed in URL attributes. Relative URLs are always allowed.
+    pub url_schemes: BTreeSet<String>,
+    /// Escape the tags GFM filters out of trusted HTML, such as `<script>`
+    /// or `<title>`.
+    pub tag_filter: bool,
+}
+
+/// Tags dropped along with their content.
+const DROP_CONTENT: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "noscript", "noembed", "noframes", "template",
+    "textarea", "title", "xmp", "frameset",
+];
+
+/// Tags escaped in trusted HTML by the tag filter of GFM.
+const FILTERED: &[&str] = &[
+    "title",
+    "textarea",
+    "style",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "script",
+    "plaintext",
+];
+
+/// Attributes holding a URL.
+const URL_ATTRIBUTES: &[&str] = &[
+    "href",
+    "src",
+    "cite",
+    "action",
+    "formaction",
+    "poster",
+    "background",
+    "longdesc",
+    "xlink:href",
+];
+
+const TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "bdi",
+    "bdo",
+    "blockquote",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "picture",
+    "pre",
+    "q",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "small",
+    "source",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "tt",
+    "u",
+    "ul",
+    "var",
+    "wbr",
+];
+
+const ATTRIBUTES: &[&str] = &[
+    "align", "alt", "cite", "class", "colspan", "datetime", "dir", "height", "href", "id", "lang",
+    "name", "open", "rowspan", "span", "src", "start", "title", "type", "width",
+];
+
+const URL_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+impl Default for HtmlPolicy {
+    fn default() -> Self {
+        let set = |items: &[&str]| items.iter().map(|x| x.to_string()).collect();
+        HtmlPolicy {
+            mode: HtmlMode::Sanitize,
+            tags: set(TAGS),
+            attributes: set(ATTRIBUTES),
+            url_schemes: set(URL_SCHEMES),
+            tag_filter: false,
+        }
+    }
+}
+
+impl HtmlPolicy {
+    /// The HTML to insert for the raw HTML `html`.
+    pub fn render(&self, html: &str) -> String {
+        match self.mode {
+            HtmlMode::Escape => {
+                let mut out = String::new();
+                escape_html(html, &mut out);
+                out
+            }
+            HtmlMode::Sanitize => self.sanitize(html),
+            HtmlMode::Trusted if self.tag_filter => filter_tags(html),
+            HtmlMode::Trusted => html.to_string(),
+        }
+    }
+
+    /// Keep the allowed tags and attributes of `html`, dropping comments,
+    /// event handlers and URLs with a disallowed scheme.
+    pub fn sanitize(&self, html: &str) -> String {
+        let mut out = String::new();
+        let mut pos = 0;
+        while pos < html.len() {
+            let rest = &html[pos..];
+            if rest.starts_with("<!--") {
+                pos += rest.find("-->").map_or(rest.len(), |idx| idx + 3);
+                continue;
+            }
+            if rest.starts_with("<!") || rest.starts_with("<?") {
+                pos += rest.find('>').map_or(rest.len(), |idx| idx + 1);
+                continue;
+            }
+            if let Some(tag) = parse_tag(rest) {
+                pos += tag.len;
+                if !tag.closing && DROP_CONTENT.contains(&tag.name.as_str()) {
+                    pos += skip_content(&html[pos..], &tag.name);
+                } else if self.tags.contains(&tag.name) {
+                    self.push_tag(&tag, &mut out);
+                }
+                continue;
+            }
+            let c = rest.chars().next().unwrap();
+            match c {
+                '<' => out.push_str("&lt;"),
+                _ => out.push(c),
+            }
+            pos += c.len_utf8();
+        }
+        out
+    }
+
+    fn push_tag(&self, tag: &Tag, out: &mut String) {
+        out.push('<');
+        if tag.closing {
+            out.push('/');
+        }
+        out.push_str(&tag.name);
+        for (name, value) in &tag.attributes {
+            if name.starts_with("on") || !self.attributes.contains(name) {
+                continue;
+            }
+            if URL_ATTRIBUTES.contains(&name.as_str()) && !self.allows_url(value) {
+                continue;
+            }
+            out.push(' ');
+            out.push_str(name);
+            out.push_str("=\"");
+            out.push_str(&value.replace('"', "&quot;").replace('<', "&lt;"));
+            out.push('"');
+        }
+        if tag.self_closing {
+            out.push_str(" /");
+        }
+        out.push('>');
+    }
+
+    /// The URL a markdown link or image of `url` is rendered with,
+    /// percent-encoded: `None` when its scheme is not allowed. Trusted
+    /// content keeps any URL.
+    pub fn link_url<'a>(&self, url: &'a str) -> Option<Cow<'a, str>> {
+        (self.mode == HtmlMode::Trusted || self.allows_url(url)).then(|| encode_url(url))
+    }
+
+    fn allows_url(&self, url: &str) -> bool {
+        let url: String = decode_entities(url)
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+            .collect();
+        match url.find([':', '/', '?', '#']) {
+            Some(idx) if url[idx..].starts_with(':') => {
+                self.url_schemes.contains(&url[..idx].to_lowercase())
+            }
+            _ => true,
+        }
+    }
+}
+
+/// `url` with the bytes that may not appear in a URL percent-encoded, as
+/// the CommonMark reference implementation does. Existing escapes are kept.
+fn encode_url(url: &str) -> Cow<'_, str> {
+    let bytes = url.as_bytes();
+    let escaped = |idx: usize| {
+        bytes.len() > idx + 2
+            && bytes[idx + 1].is_ascii_hexdigit()
+            && bytes[idx + 2].is_ascii_hexdigit()
+    };
+    let kept = |idx: usize| {
+        let byte = bytes[idx];
+        byte.is_ascii_alphanumeric()
+            || b";/?:@&=+$,-_.!~*'()#".contains(&byte)
+            || byte == b'%' && escaped(idx)
+    };
+    if (0..bytes.len()).all(kept) {
+        return Cow::Borrowed(url);
+    }
+    let mut out = String::with_capacity(url.len());
+    for (idx, byte) in bytes.iter().enumerate() {
+        if kept(idx) {
+            out.push(*byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// `html` with the `<` of the tags in `FILTERED` escaped.
+fn filter_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    for (idx, c) in html.char_indices() {
+        let filtered = c == '<'
+            && parse_tag(&html[idx..]).is_some_and(|tag| FILTERED.contains(&tag.name.as_str()));
+        if filtered {
+            out.push_str("&lt;");
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+pub(crate) struct Tag {
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<(String, String)>,
+    pub(crate) closing: bool,
+    pub(crate) self_closing: bool,
+    /// Length of the tag in the source.
+    pub(crate) len: usize,
+}
+
+/// Parse the tag at the start of `html`, if it is one.
+pub(crate) fn parse_tag(html: &str) -> Option<Tag> {
+    let bytes = html.as_bytes();
+    if bytes.first() != Some(&b'<') {
+        return None;
+    }
+    let closing = bytes.get(1) == Some(&b'/');
+    let mut pos = if closing { 2 } else { 1 };
+    if !bytes.get(pos)?.is_ascii_alphabetic() {
+        return None;
+    }
+    let name_end = pos
+        + html[pos..]
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .unwrap_or(html.len() - pos);
+    let name = html[pos..name_end].to_lowercase();
+    pos = name_end;
+    let mut attributes = Vec::new();
+    let mut self_closing = false;
+    loop {
+        pos += html[pos..].len() - html[pos..].trim_start().len();
+        match *bytes.get(pos)? {
+            b'>' => break,
+            b'/' => {
+                self_closing = true;
+                pos += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let attr_end = pos
+            + html[pos..]
+                .find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '=')
+                .unwrap_or(html.len() - pos);
+        let attr = html[pos..attr_end].to_lowercase();
+        pos = attr_end;
+        pos += html[pos..].len() - html[pos..].trim_start().len();
+        let mut value = String::new();
+        if bytes.get(pos) == Some(&b'=') {
+            pos += 1;
+            pos += html[pos..].len() - html[pos..].trim_start().len();
+            match *bytes.get(pos)? {
+                quote @ (b'"' | b'\'') => {
+                    let end = pos + 1 + html[pos + 1..].find(quote as char)?;
+                    value = html[pos + 1..end].to_string();
+                    pos = end + 1;
+                }
+                _ => {
+                    let end = pos
+                        + html[pos..]
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(html.len() - pos);
+                    value = html[pos..end].to_string();
+                    pos = end;
+                }
+            }
+        }
+        if !attr.is_empty() {
+            self_closing = false;
+            attributes.push((attr, value));
+        }
+    }
+    Some(Tag {
+        name,
+        attributes,
+        closing,
+        self_closing,
+        len: pos + 1,
+    })
+}
+
+/// The length of the content of a `name` element up to and including its
+/// closing tag, or of the whole of `html` when it is not closed.
+fn skip_content(html: &str, name: &str) -> usize {
+    let lower = html.to_ascii_lowercase();
+    let close = format!("</{}", name);
+    match lower.find(&close) {
+        Some(idx) => {
+            idx + html[idx..]
+                .find('>')
+                .map_or(html.len() - idx, |end| end + 1)
+        }
+        None => html.len(),
+    }
+}
+
+/// Decode the character references that can hide a URL scheme. Numeric
+/// references need no `;`, as in browsers.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(idx) = rest.find('&') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+        let (decoded, len) = match rest.strip_prefix('#') {
+            Some(number) => {
+                let (radix, digits) = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => (16, hex),
+                    None => (10, number),
+                };
+                let end = digits
+                    .find(|c: char| !c.is_digit(radix))
+                    .unwrap_or(digits.len());
+                let decoded = u32::from_str_radix(&digits[..end], radix)
+                    .ok()
+                    .and_then(char::from_u32);
+                (decoded, rest.len() - digits.len() + end)
+            }
+            None => ["colon:", "Tab\t", "NewLine\n"]
+                .iter()
+                .find(|name| rest.starts_with(&name[..name.len() - 1]))
+                .map_or((None, 0), |name| (name.chars().last(), name.len() - 1)),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[len..];
+                rest = rest.strip_prefix(';').unwrap_or(rest);
+            }
+            None => out.push('&'),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{html::to_html_with_policy, parse};
+
+    fn sanitize(html: &str) -> String {
+        HtmlPolicy::default().sanitize(html)
+    }
+
+    fn render(markdown: &str, mode: HtmlMode) -> String {
+        let (arena, root) = parse(markdown);
+        let policy = HtmlPolicy {
+            mode,
+            ..HtmlPolicy::default()
+        };
+        to_html_with_policy(&arena, root, &policy)
+    }
+
+    #[test]
+    fn drops_event_handlers() {
+        assert_eq!(
+            sanitize("<img src=\"a.png\" onerror=\"alert(1)\" ONLOAD=x>"),
+            "<img src=\"a.png\">"
+        );
+        assert_eq!(sanitize("<b onclick='x'>b</b>"), "<b>b</b>");
+    }
+
+    #[test]
+    fn drops_urls_with_obfuscated_schemes() {
+        for url in [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            "jav&#x61;script:alert(1)",
+            "jav&#97script:alert(1)",
+            "java\tscript:alert(1)",
+            "javascript&colon;alert(1)",
+            " javascript:alert(1)",
+            "data:text/html,x",
+        ] {
+            let html = format!("<a href=\"{}\">x</a>", url);
+            assert_eq!(sanitize(&html), "<a>x</a>", "{:?}", url);
+        }
+        for url in [
+            "https://example.com",
+            "/path:x",
+            "#top",
+            "?q=a:b",
+            "mailto:a@b.c",
+        ] {
+            let html = format!("<a href=\"{}\">x</a>", url);
+            assert!(sanitize(&html).contains("href"), "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn drops_the_content_of_dangerous_tags() {
+        assert_eq!(sanitize("a<script>alert(1)</script>b"), "ab");
+        assert_eq!(sanitize("<style>*{}</style><p>p</p>"), "<p>p</p>");
+        assert_eq!(sanitize("<iframe src=x>inner</iframe>"), "");
+        // Tags only outside the allow-list keep their content.
+        assert_eq!(sanitize("<custom>kept</custom>"), "kept");
+    }
+
+    #[test]
+    fn percent_encodes_markdown_link_urls() {
+        assert_eq!(encode_url("/a?b=c#d"), "/a?b=c#d");
+        assert_eq!(encode_url("föö bar"), "f%C3%B6%C3%B6%20bar");
+        assert_eq!(encode_url("a%20b%2"), "a%20b%252");
+        assert_eq!(encode_url("\\[\"]"), "%5C%5B%22%5D");
+        assert_eq!(
+            render("[x](</a b> \"t\") ![y](ä.png)", HtmlMode::Sanitize),
+            "<p><a href=\"/a%20b\" title=\"t\">x</a> <img src=\"%C3%A4.png\" alt=\"y\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn drops_markdown_link_urls_with_disallowed_schemes() {
+        for markdown in [
+            "[x](javascript:alert(1))",
+            "[x](jav&#x61;script:alert(1))",
+            "[x][r]\n\n[r]: javascript:alert(1)",
+            "<javascript:alert(1)>",
+        ] {
+            let html = render(markdown, HtmlMode::Sanitize);
+            assert!(!html.contains("href"), "{:?}: {}", markdown, html);
+            assert!(html.contains("<a>"), "{:?}: {}", markdown, html);
+        }
+        for markdown in ["![x](javascript:alert(1))", "![x][r]\n\n[r]: vbscript:x"] {
+            let html = render(markdown, HtmlMode::Escape);
+            assert_eq!(html, "<p><img alt=\"x\" /></p>\n", "{:?}", markdown);
+        }
+        let html = render("[x](https://example.com)", HtmlMode::Escape);
+        assert!(html.contains("href=\"https://example.com\""));
+        let html = render("[x](javascript:void)", HtmlMode::Trusted);
+        assert!(html.contains("href=\"javascript:void\""));
+    }
+
+    #[test]
+    fn filters_gfm_tags_from_trusted_html_on_request() {
+        let mut policy = HtmlPolicy {
+            mode: HtmlMode::Trusted,
+            ..HtmlPolicy::default()
+        };
+        let html = "<b><title>t</title></b> <XMP a=1> <script/> <titles> <style";
+        assert_eq!(policy.render(html), html);
+        policy.tag_filter = true;
+        assert_eq!(
+            policy.render(html),
+            "<b>&lt;title>t&lt;/title></b> &lt;XMP a=1> &lt;script/> <titles> <style"
+        );
+    }
+}
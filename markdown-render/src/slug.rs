@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    event::Position,
+    mdast::{Arena, Node, NodeId},
+};
+
+/// An entry of the document outline, see [`toc`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TocEntry {
+    pub depth: u8,
+    pub text: String,
+    pub slug: String,
+    pub position: Option<Position>,
+    /// The following headings of greater depth, up to the next heading of
+    /// the same depth or less.
+    pub children: Vec<TocEntry>,
+}
+
+/// The anchor GitHub gives to a heading reading `text`: lowercased, with
+/// spaces turned into `-` and punctuation other than `-` and `_` dropped.
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            _ if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Give every heading under `id` its slug, in document order: its custom id
+/// if it has one, else the slug of its text, suffixed with `-1`, `-2`... when
+/// already taken.
+///
+/// Returns whether a slug changed; the hashes of changed nodes are
+/// recomputed.
+pub fn assign(arena: &mut Arena, id: NodeId) -> bool {
+    assign_with(arena, id, &mut Taken::default())
+}
+
+/// The slugs given so far, and the last suffix tried for each text, so
+/// that headings of the same text are suffixed in constant time.
+#[derive(Default)]
+struct Taken {
+    slugs: HashSet<String>,
+    suffixes: HashMap<String, usize>,
+}
+
+fn assign_with(arena: &mut Arena, id: NodeId, taken: &mut Taken) -> bool {
+    let mut changed = false;
+    if let (Some(slug), Node::Heading(x, _)) = (next_slug(arena, id, taken), &mut arena[id]) {
+        changed = x.slug != slug;
+        x.slug = slug;
+    }
+    let mut idx = 0;
+    while let Some(child) = arena.child(id, idx) {
+        changed |= assign_with(arena, child, taken);
+        idx += 1;
+    }
+    if changed {
+        arena.compute_hash(id);
+    }
+    changed
+}
+
+/// Give the headings of the re-parsed `blocks` the slugs of those of the
+/// `old` blocks they replace, when they read the same: the slugs of the
+/// document are then those [`assign`] would give.
+///
+/// Returns whether the slugs were carried over; the hashes of the blocks
+/// are recomputed.
+pub fn carry(arena: &mut Arena, old: &[NodeId], blocks: &[NodeId]) -> bool {
+    let mut old_headings = Vec::new();
+    let mut headings = Vec::new();
+    for block in old {
+        heading_ids(arena, *block, &mut old_headings);
+    }
+    for block in blocks {
+        heading_ids(arena, *block, &mut headings);
+    }
+    let source = |id: NodeId| match &arena[id] {
+        Node::Heading(x, _) => (x.id.clone(), arena.text_content(id)),
+        _ => unreachable!(),
+    };
+    if old_headings.len() != headings.len()
+        || old_headings
+            .iter()
+            .zip(&headings)
+            .any(|(old, new)| source(*old) != source(*new))
+    {
+        return false;
+    }
+    for (old, new) in old_headings.into_iter().zip(headings) {
+        let slug = match &arena[old] {
+            Node::Heading(x, _) => x.slug.clone(),
+            _ => unreachable!(),
+        };
+        if let Node::Heading(x, _) = &mut arena[new] {
+            x.slug = slug;
+        }
+        let mut cur = new;
+        arena.compute_hash(cur);
+        while !blocks.contains(&cur) {
+            cur = arena.parent(cur).unwrap();
+            arena.compute_hash(cur);
+        }
+    }
+    true
+}
+
+fn heading_ids(arena: &Arena, id: NodeId, headings: &mut Vec<NodeId>) {
+    let node = &arena[id];
+    if let Node::Heading(_, _) = node {
+        headings.push(id);
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            heading_ids(arena, *child, headings);
+        }
+    }
+}
+
+/// Whether [`assign`] would change a slug in each of the top-level
+/// `blocks` of a document.
+pub fn changes(arena: &Arena, blocks: &[NodeId]) -> Vec<bool> {
+    let mut taken = Taken::default();
+    blocks
+        .iter()
+        .map(|block| changes_with(arena, *block, &mut taken))
+        .collect()
+}
+
+fn changes_with(arena: &Arena, id: NodeId, taken: &mut Taken) -> bool {
+    let mut changed = match (next_slug(arena, id, taken), &arena[id]) {
+        (Some(slug), Node::Heading(x, _)) => x.slug != slug,
+        _ => false,
+    };
+    if let Some(children) = arena[id].children() {
+        for child in children {
+            changed |= changes_with(arena, *child, taken);
+        }
+    }
+    changed
+}
+
+/// The slug of `id` if a heading, given the slugs `taken` before it,
+/// which it is added to.
+fn next_slug(arena: &Arena, id: NodeId, taken: &mut Taken) -> Option<String> {
+    let x = match &arena[id] {
+        Node::Heading(x, _) => x,
+        _ => return None,
+    };
+    let slug = match &x.id {
+        Some(id) => id.clone(),
+        None => {
+            let base = slugify(&arena.text_content(id));
+            // The suffixes tried before are still taken.
+            let count = taken.suffixes.entry(base.clone()).or_default();
+            let mut slug = match *count {
+                0 => base.clone(),
+                count => format!("{}-{}", base, count),
+            };
+            while taken.slugs.contains(&slug) {
+                *count += 1;
+                slug = format!("{}-{}", base, count);
+            }
+            slug
+        }
+    };
+    taken.slugs.insert(slug.clone());
+    Some(slug)
+}
+
+/// The outline of the headings under `id`, nested by depth.
+pub fn toc(arena: &Arena, id: NodeId) -> Vec<TocEntry> {
+    let mut headings = Vec::new();
+    collect_headings(arena, id, &mut headings);
+    let mut entries = Vec::new();
+    let mut stack: Vec<TocEntry> = Vec::new();
+    for entry in headings {
+        while stack.last().is_some_and(|last| last.depth >= entry.depth) {
+            close(&mut stack, &mut entries);
+        }
+        stack.push(entry);
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut entries);
+    }
+    entries
+}
+
+/// Pop the innermost open entry into its parent, or into `entries`.
+fn close(stack: &mut Vec<TocEntry>, entries: &mut Vec<TocEntry>) {
+    let entry = stack.pop().unwrap();
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(entry),
+        None => entries.push(entry),
+    }
+}
+
+fn collect_headings(arena: &Arena, id: NodeId, headings: &mut Vec<TocEntry>) {
+    let node = &arena[id];
+    if let Node::Heading(x, _) = node {
+        headings.push(TocEntry {
+            depth: x.depth,
+            text: arena.text_content(id),
+            slug: x.slug.clone(),
+            position: arena.position(id),
+            children: Vec::new(),
+        });
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            collect_headings(arena, *child, headings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    /// The slugs of the top-level headings of `markdown`.
+    fn slugs(markdown: &str) -> Vec<String> {
+        let (arena, root) = parse(markdown);
+        toc(&arena, root)
+            .into_iter()
+            .map(|entry| entry.slug)
+            .collect()
+    }
+
+    #[test]
+    fn slugifies_like_github() {
+        assert_eq!(slugify(" Hello, World! a_b -c- "), "hello-world-a_b--c-");
+        assert_eq!(slugify("Ünïcode 日本"), "ünïcode-日本");
+    }
+
+    #[test]
+    fn suffixes_taken_slugs() {
+        assert_eq!(slugs("# a\n\n# a\n\n# A"), ["a", "a-1", "a-2"]);
+        assert_eq!(slugs("# a\n\n# a-1\n\n# a"), ["a", "a-1", "a-2"]);
+    }
+
+    #[test]
+    fn generated_slugs_skip_custom_ids() {
+        assert_eq!(slugs("# x {#a-1}\n\n# a\n\n# a"), ["a-1", "a", "a-2"]);
+        // Custom ids are kept as written.
+        assert_eq!(slugs("# a\n\n# b {#a}"), ["a", "a"]);
+    }
+}
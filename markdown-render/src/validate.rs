@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+
+use pulldown_cmark::LinkType;
+
+use crate::{
+    event::Position,
+    mdast::{Arena, Node, NodeId},
+    reference::{self, Unresolved},
+};
+
+/// What a [`Diagnostic`] is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// A `#fragment` link matching no heading or footnote.
+    MissingFragment,
+    /// A reference link or image to an undefined label.
+    UndefinedReference,
+    /// A definition whose label is already defined: it is unused.
+    DuplicateDefinition,
+    /// An image without alternative text.
+    MissingAlt,
+    /// A relative link to a file missing from the given file list.
+    MissingFile,
+}
+
+/// How a [`Diagnostic`] is shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in a document, to be shown at `position`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    pub position: Option<Position>,
+}
+
+/// Check the links, references and images of the document tree `root`.
+///
+/// `unresolved` are the references the parser left as text, see
+/// [`reference::list`]. Relative links are only checked when `files` is
+/// given: the paths, relative to the document, of the existing files.
+/// Root-absolute paths, like `/docs/x.md`, depend on where the document is
+/// served from and are not checked.
+pub fn validate(
+    arena: &Arena,
+    root: NodeId,
+    unresolved: &[Unresolved],
+    files: Option<&[String]>,
+) -> Vec<Diagnostic> {
+    let mut targets = HashSet::new();
+    collect_targets(arena, root, &mut targets);
+    let files: Option<HashSet<String>> = files.map(|files| {
+        files
+            .iter()
+            .filter_map(|file| normalize_path(file))
+            .collect()
+    });
+    let mut validator = Validator {
+        targets,
+        files,
+        diagnostics: Vec::new(),
+    };
+    validator.visit(arena, root);
+
+    let references = reference::list(arena, root, unresolved);
+    for definition in references.definitions {
+        // Unused, its destination does not matter.
+        if definition.duplicate {
+            validator.push(
+                Rule::DuplicateDefinition,
+                Severity::Warning,
+                format!(
+                    "`{}` is already defined, this definition is unused",
+                    definition.label
+                ),
+                definition.position,
+            );
+            continue;
+        }
+        validator.check_url(&definition.url, definition.position);
+    }
+    for reference in references.unresolved {
+        validator.push(
+            Rule::UndefinedReference,
+            Severity::Error,
+            format!("no definition for `{}`", reference.label),
+            reference.position,
+        );
+    }
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|x| x.position.as_ref().map_or(usize::MAX, |x| x.start.offset));
+    diagnostics
+}
+
+struct Validator {
+    /// The fragments linking into the document.
+    targets: HashSet<String>,
+    files: Option<HashSet<String>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator {
+    fn push(
+        &mut self,
+        rule: Rule,
+        severity: Severity,
+        message: String,
+        position: Option<Position>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            rule,
+            severity,
+            message,
+            position,
+        });
+    }
+
+    fn visit(&mut self, arena: &Arena, id: NodeId) {
+        let node = &arena[id];
+        match node {
+            // Autolinks are always absolute.
+            Node::Link(x, _) if !matches!(x.link_type, LinkType::Autolink | LinkType::Email) => {
+                self.check_url(x.url.as_deref().unwrap_or_default(), arena.position(id));
+            }
+            Node::Image(x, _) => {
+                self.check_alt(&x.alt, &arena.position(id));
+                self.check_url(x.url.as_deref().unwrap_or_default(), arena.position(id));
+            }
+            // Their destination is checked at their definition.
+            Node::ImageReference(x, _) if x.url.is_some() => {
+                self.check_alt(&x.alt, &arena.position(id))
+            }
+            _ => {}
+        }
+        if let Some(children) = node.children() {
+            for child in children {
+                self.visit(arena, *child);
+            }
+        }
+    }
+
+    fn check_alt(&mut self, alt: &str, position: &Option<Position>) {
+        if alt.trim().is_empty() {
+            self.push(
+                Rule::MissingAlt,
+                Severity::Warning,
+                "image without alternative text".to_string(),
+                position.clone(),
+            );
+        }
+    }
+
+    /// Check the fragment of a link into the document, or the file of a
+    /// relative link.
+    fn check_url(&mut self, url: &str, position: Option<Position>) {
+        if let Some(fragment) = url.strip_prefix('#') {
+            let fragment = percent_decode(fragment);
+            if !fragment.is_empty() && !self.targets.contains(&fragment) {
+                self.push(
+                    Rule::MissingFragment,
+                    Severity::Error,
+                    format!("no heading or footnote with the id `{}`", fragment),
+                    position,
+                );
+            }
+            return;
+        }
+        let files = match &self.files {
+            Some(files) => files,
+            None => return,
+        };
+        let scheme = url
+            .find([':', '/', '?', '#'])
+            .is_some_and(|idx| url[idx..].starts_with(':'));
+        // Absolute, or from the root the document is served from.
+        if scheme || url.starts_with('/') {
+            return;
+        }
+        let path = &url[..url.find(['?', '#']).unwrap_or(url.len())];
+        if path.is_empty() {
+            return;
+        }
+        let found = normalize_path(&percent_decode(path)).is_some_and(|path| {
+            files.contains(&path)
+                || files
+                    .iter()
+                    .any(|file| file.starts_with(&format!("{}/", path)))
+        });
+        if !found {
+            self.push(
+                Rule::MissingFile,
+                Severity::Error,
+                format!("no file at `{}`", path),
+                position,
+            );
+        }
+    }
+}
+
+/// The ids of the headings and footnotes under `id`.
+fn collect_targets(arena: &Arena, id: NodeId, targets: &mut HashSet<String>) {
+    let node = &arena[id];
+    match node {
+        Node::Heading(x, _) => {
+            targets.insert(x.slug.clone());
+        }
+        Node::FootnoteDefinition(x, _) => {
+            targets.insert(x.id());
+        }
+        Node::FootnoteReference(x, _) if x.number > 0 => {
+            targets.insert(x.id());
+        }
+        _ => {}
+    }
+    if let Some(children) = node.children() {
+        for child in children {
+            collect_targets(arena, *child, targets);
+        }
+    }
+}
+
+/// `path` without its empty and `.` segments and with its `..` segments
+/// applied, or `None` when it goes above the document folder.
+fn normalize_path(path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            _ => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+/// Decode the `%XX` escapes of `text`, leaving invalid ones as is.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let escape = text
+            .get(idx + 1..idx + 3)
+            .filter(|_| bytes[idx] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                out.push(byte);
+                idx += 3;
+            }
+            None => {
+                out.push(bytes[idx]);
+                idx += 1;
+            }
+        }
+    }
+    String::from_utf8(out).unwrap_or_else(|_| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompileContext;
+
+    /// The rules broken by `markdown`, given the existing `files`.
+    fn rules(markdown: &str, files: &[&str]) -> Vec<Rule> {
+        let mut context = CompileContext::new();
+        context.run(markdown);
+        let files: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+        validate(
+            &context.arena,
+            context.root,
+            &context.unresolved,
+            Some(&files),
+        )
+        .into_iter()
+        .map(|diagnostic| diagnostic.rule)
+        .collect()
+    }
+
+    #[test]
+    fn checks_relative_links_against_the_files() {
+        let markdown = "[a](x.md) [b](sub/../y.md#top) [c](sub/z%20z.md) [d](missing.md)";
+        assert_eq!(
+            rules(markdown, &["x.md", "y.md", "sub/z z.md"]),
+            [Rule::MissingFile]
+        );
+        assert_eq!(rules("[a](../x.md)", &["x.md"]), [Rule::MissingFile]);
+    }
+
+    #[test]
+    fn skips_root_absolute_and_absolute_urls() {
+        let markdown = "[a](/docs/x.md) [b](//example.com/x.md) [c](https://example.com/x.md)";
+        assert_eq!(rules(markdown, &["docs/x.md"]), []);
+        assert_eq!(rules(markdown, &[]), []);
+    }
+
+    #[test]
+    fn reports_a_duplicate_definition_once() {
+        let markdown = "[a] [b]\n\n[a]: x.md\n[a]: missing.md\n[b]: missing.md";
+        assert_eq!(
+            rules(markdown, &["x.md"]),
+            [Rule::DuplicateDefinition, Rule::MissingFile]
+        );
+    }
+
+    #[test]
+    fn checks_fragments_against_headings_and_footnotes() {
+        let markdown = "# Title\n\n[a](#title) [b](#fn-1) [c](#nope)[^1]\n\n[^1]: Note.";
+        assert_eq!(rules(markdown, &[]), [Rule::MissingFragment]);
+    }
+
+    #[test]
+    fn reports_an_invalid_file_list() {
+        let mut context = CompileContext::new();
+        context.run("[a](x.md)");
+        assert!(context.diagnostics_json(Some("[\"x.md\"")).is_err());
+        assert_eq!(context.diagnostics_json(Some("[\"x.md\"]")).unwrap(), "[]");
+        assert!(context.diagnostics_json(None).unwrap().starts_with('['));
+    }
+}
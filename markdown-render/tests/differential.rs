@@ -0,0 +1,225 @@
+//! Differential test of the incremental updates of the DOM.
+//!
+//! Random documents go through random sequences of edits, applied both as a
+//! new source given to `render`, which diffs and patches the previous DOM,
+//! and as a `SourceEdit` given to `render_edit_into`, which re-parses the
+//! edited blocks only. After every step both DOMs must equal a fresh render
+//! of the text, and so must the tree of the edited one, positions included.
+//!
+//! The run is reproducible: set `DIFFERENTIAL_SEED` to replay a failure,
+//! or to start from another case, and `DIFFERENTIAL_CASES` to run more
+//! documents than the default.
+
+use std::{
+    env,
+    panic::{self, AssertUnwindSafe},
+};
+
+use markdown_render::{dom::Dom, event::SourceEdit, memory, CompileContext};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+const CASES: u64 = 200;
+const EDITS: usize = 12;
+
+const WORDS: [&str; 12] = [
+    "alpha",
+    "beta",
+    "gamma",
+    "*em*",
+    "**strong**",
+    "`code`",
+    "[link](/a)",
+    "[ref][x]",
+    "![img](/i.png \"t\")",
+    "~~del~~",
+    "<kbd>k</kbd>",
+    "$x^2$",
+];
+
+const BLOCKS: [&str; 22] = [
+    "# Title",
+    "## Title {#custom .cls}",
+    "Setext\n===",
+    "- a\n- b",
+    "- [ ] todo\n- [x] done",
+    "1. one\n2. two",
+    "3. three\n   - nested",
+    "* loose\n\n* list",
+    "> quote\n> more",
+    "> - quoted list",
+    "```rust\nfn main() {}\n```",
+    "```\nplain\n```",
+    "    indented code",
+    "| a | b |\n| - | :-: |\n| 1 | 2 |",
+    "<div>\nblock\n</div>",
+    "<!-- comment -->",
+    "[x]: /x \"X\"",
+    "[y]: /y",
+    "Note[^n].\n\n[^n]: The *note*.",
+    "$$\nx = 1\n$$",
+    "---\ntitle: front\n---",
+    "***",
+];
+
+/// Pieces inserted by the edits, as likely to break blocks apart as to
+/// join them.
+const PIECES: [&str; 16] = [
+    "\n", "\n\n", " ", "- ", "# ", "> ", "```", "*", "[", "]", "|", "\n    ", "text", "[^n]",
+    "<div>", "1. ",
+];
+
+/// A context rendering into a `<main>` of the in-memory DOM.
+struct Preview {
+    dom: memory::Document,
+    target: memory::Node,
+    context: CompileContext,
+}
+
+impl Preview {
+    fn new() -> Self {
+        let dom = memory::Document;
+        let target = dom.create_element("main");
+        Preview {
+            dom,
+            target,
+            context: CompileContext::new(),
+        }
+    }
+
+    fn html(&self) -> String {
+        self.target.inner_html()
+    }
+
+    /// The tree as mdast JSON, with the outline and the diagnostics, which
+    /// all carry source positions.
+    fn tree(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.context.to_mdast_json(),
+            self.context.toc(),
+            self.context.diagnostics_json(None).unwrap()
+        )
+    }
+}
+
+fn rendered(source: &str) -> Preview {
+    let mut preview = Preview::new();
+    preview
+        .context
+        .render_into(&preview.dom, &preview.target, source);
+    preview
+}
+
+fn paragraph(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(1, 6);
+    (0..len)
+        .map(|_| *WORDS.choose(rng).unwrap())
+        .collect::<Vec<_>>()
+        .join(if rng.gen_bool(0.2) { "\n" } else { " " })
+}
+
+fn document(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(0, 8);
+    (0..len)
+        .map(|_| match rng.gen_bool(0.6) {
+            true => BLOCKS.choose(rng).unwrap().to_string(),
+            false => paragraph(rng),
+        })
+        .collect::<Vec<_>>()
+        .join(if rng.gen_bool(0.8) { "\n\n" } else { "\n" })
+}
+
+/// A byte offset of `source` on a char boundary.
+fn offset(rng: &mut StdRng, source: &str) -> usize {
+    let mut offset = rng.gen_range(0, source.len() + 1);
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+fn edit(rng: &mut StdRng, source: &str) -> SourceEdit {
+    let start = offset(rng, source);
+    let end = match rng.gen_range(0, 3) {
+        // Insert.
+        0 => start,
+        // Delete or replace a few bytes.
+        1 => offset(rng, &source[..(start + 8).min(source.len())]).max(start),
+        // Replace a larger range.
+        _ => offset(rng, source).max(start),
+    };
+    let replacement = match rng.gen_range(0, 4) {
+        0 => String::new(),
+        1 => paragraph(rng),
+        2 => BLOCKS.choose(rng).unwrap().to_string(),
+        _ => PIECES.choose(rng).unwrap().to_string(),
+    };
+    SourceEdit {
+        start,
+        end,
+        replacement,
+    }
+}
+
+/// Run the edits of the case `seed`, panicking at the first step whose
+/// DOM differs from a fresh render.
+fn run(seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut source = document(&mut rng);
+    let mut patched = Preview::new();
+    let mut edited = Preview::new();
+    patched
+        .context
+        .render_into(&patched.dom, &patched.target, &source);
+    edited
+        .context
+        .render_into(&edited.dom, &edited.target, &source);
+    for _ in 0..EDITS {
+        let edit = edit(&mut rng, &source);
+        let next = edit.apply(&source).unwrap();
+        let fresh = rendered(&next);
+        let expected = fresh.html();
+        patched
+            .context
+            .render_into(&patched.dom, &patched.target, &next);
+        assert_eq!(
+            patched.html(),
+            expected,
+            "render of {:?} after {:?}",
+            next,
+            source
+        );
+        edited
+            .context
+            .render_edit_into(&edited.dom, &edited.target, &edit)
+            .unwrap();
+        assert_eq!(edited.html(), expected, "edit {:?} of {:?}", edit, source);
+        assert_eq!(
+            edited.tree(),
+            fresh.tree(),
+            "tree after edit {:?} of {:?}",
+            edit,
+            source
+        );
+        source = next;
+    }
+}
+
+#[test]
+fn incremental_updates_give_the_dom_of_a_fresh_render() {
+    // One case when replaying a seed, unless asked for more.
+    let first = env::var("DIFFERENTIAL_SEED")
+        .ok()
+        .map(|seed| seed.parse().expect("DIFFERENTIAL_SEED is a number"));
+    let cases = env::var("DIFFERENTIAL_CASES")
+        .ok()
+        .map(|cases| cases.parse().expect("DIFFERENTIAL_CASES is a number"))
+        .unwrap_or(if first.is_some() { 1 } else { CASES });
+    let first = first.unwrap_or(0);
+    let seeds = first..first + cases;
+    for seed in seeds {
+        if panic::catch_unwind(AssertUnwindSafe(|| run(seed))).is_err() {
+            panic!("case failed, replay it with DIFFERENTIAL_SEED={}", seed);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Heap use of a rendering context over many renders.
+//!
+//! The allocator of this test counts the bytes in use: a context rendering
+//! the same document again and again must not keep the trees of previous
+//! renders, and must free everything when dropped.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use markdown_render::{dom::Dom, event::SourceEdit, memory, CompileContext};
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const ROUNDS: usize = 40;
+
+fn live() -> usize {
+    LIVE.load(Ordering::Relaxed)
+}
+
+fn document(sections: usize) -> String {
+    let mut source = String::new();
+    for idx in 0..sections {
+        source.push_str(&format!(
+            "## Section {idx}\n\n\
+             Some *text* with **strong**, `code` and a [link](/a{idx}).\n\n\
+             - item\n- [x] task\n  - nested\n\n\
+             > quote {idx}\n\n\
+             | a | b |\n| - | - |\n| {idx} | 2 |\n\n\
+             ```rust\nlet x = {idx};\n```\n\n"
+        ));
+    }
+    source
+}
+
+#[test]
+fn renders_do_not_keep_previous_trees() {
+    let source = document(200);
+    let other = source.replace("Some", "Other");
+    let dom = memory::Document;
+    let start = live();
+    let target = dom.create_element("main");
+
+    let mut context = CompileContext::new();
+    let mut warm = 0;
+    for round in 0..ROUNDS {
+        if round % 2 == 0 {
+            context.render_into(&dom, &target, if round % 4 == 0 { &other } else { &source });
+        } else {
+            let edit = SourceEdit {
+                start: 0,
+                end: 0,
+                replacement: format!("Paragraph {}\n\n", round),
+            };
+            context.render_edit_into(&dom, &target, &edit).unwrap();
+        }
+        if round == 3 {
+            warm = live() - start;
+        }
+    }
+    assert!(
+        live() - start < 2 * warm,
+        "the heap grew from {} to {} bytes",
+        warm,
+        live() - start
+    );
+
+    drop(context);
+    drop(target);
+    assert!(
+        live() <= start + 64 * 1024,
+        "{} bytes left after dropping the context",
+        live().saturating_sub(start)
+    );
+}
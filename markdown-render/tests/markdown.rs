@@ -0,0 +1,70 @@
+//! Round trips through the markdown serializer: the source it writes must
+//! render like the document it was written from.
+
+use markdown_render::{format_markdown, to_html};
+
+/// Sources whose text looks like markup once its escapes are gone.
+const CASES: [&str; 40] = [
+    "1\\. not a list",
+    "2\\) not a list either",
+    "a\n1\\. not a list",
+    "\\- not a list\n\n\\+ nor this",
+    "\\# not a heading",
+    "### foo \\###",
+    "\\> not a quote",
+    "&amp;copy; stays text",
+    "&amp; and &amp;#35; and &amp;lt;",
+    "[not \\] a link]",
+    "\\[foo\\]\n\n[foo]: /url",
+    "[a\\]b]\n\n[a\\]b]: /url",
+    "[link][a\\]b]\n\n[a\\]b]: /url",
+    "[Foo*bar\\]]\n\n[Foo*bar\\]]: /url",
+    "[foo][ref\\[]\n\n[ref\\[]: /uri",
+    "\\![foo]\n\n[foo]: /url",
+    "line&#10;break and tab&#9;here",
+    "&#9;indented",
+    "&#32;   four spaces",
+    "*_foo_*",
+    "foo***bar***baz",
+    "foo******bar*********baz",
+    "*foo **bar***",
+    "**foo *bar***",
+    "_a_*b*",
+    "**_foo_**",
+    "_**foo**_",
+    "***foo** bar*",
+    ">",
+    "> \n> [a]: /url\n\n[a]",
+    "- item\n\n  [b]: /b\n\n[b]",
+    "***\n\n---\n\n___",
+    "a\n\n***\n\n- b\n\n* * *",
+    "<details>\n<summary>More\n\n*hidden*\n\n[c]: /c\n\n</details>\n\n[c]",
+    "<div align=\"center\">\n\n# Title\n\n</div>\n\n<div>\n\nopen",
+    "| f\\|oo  |\n| ------ |\n| b `\\|` az |\n| b **\\|** im |",
+    "| a |\n| - |\n| [x](/a\\|b \"c\\|d\") \\\\\\| |",
+    "Foo *bar\nbaz*\n====",
+    "Foo *bar\nbaz*\n---",
+    "  Foo *bar\nbaz*\t\n====",
+];
+
+#[test]
+fn reformatted_sources_render_the_same() {
+    for source in CASES {
+        let formatted = format_markdown(source);
+        assert_eq!(
+            to_html(&formatted),
+            to_html(source),
+            "{:?} was written as {:?}",
+            source,
+            formatted
+        );
+    }
+}
+
+#[test]
+fn reformatting_is_stable() {
+    for source in CASES {
+        let formatted = format_markdown(source);
+        assert_eq!(format_markdown(&formatted), formatted, "{:?}", source);
+    }
+}
@@ -0,0 +1,386 @@
+//! Conformance with the CommonMark spec and the GFM extensions.
+//!
+//! Every example of `tests/fixtures` is rendered both to an HTML string and
+//! into the in-memory DOM, with raw HTML trusted as the spec expects, and
+//! passed through the tag filter for the GFM extensions. Both are compared
+//! with the expected HTML once normalized, and the pass rate of each
+//! section is printed (`cargo test --test spec -- --nocapture`). The
+//! examples that fail are listed in `tests/fixtures/known-failures.txt`:
+//! the test fails when an example leaves or joins that list.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+};
+
+use markdown_render::{
+    dom::Dom,
+    html, memory, parse,
+    sanitize::{HtmlMode, HtmlPolicy},
+    CompileContext,
+};
+use serde_derive::Deserialize;
+
+const FIXTURES: [&str; 2] = ["commonmark", "gfm"];
+
+/// Elements around which whitespace is not significant, as in the
+/// normalization of the spec's own test runner.
+const BLOCK: [&str; 52] = [
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "button",
+    "canvas",
+    "caption",
+    "col",
+    "colgroup",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "embed",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "iframe",
+    "li",
+    "map",
+    "object",
+    "ol",
+    "output",
+    "p",
+    "pre",
+    "progress",
+    "script",
+    "section",
+    "style",
+    "table",
+    "tbody",
+    "td",
+    "textarea",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+    "video",
+    "html",
+    "head",
+];
+
+const VOID: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+#[derive(Deserialize)]
+struct Example {
+    markdown: String,
+    html: String,
+    example: usize,
+    section: String,
+}
+
+/// A path the examples are rendered through.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Renderer {
+    /// `html::to_html`.
+    Html,
+    /// `CompileContext::render_into` a `memory::Document`.
+    Dom,
+}
+
+impl Renderer {
+    const ALL: [Renderer; 2] = [Renderer::Html, Renderer::Dom];
+
+    fn name(self) -> &'static str {
+        match self {
+            Renderer::Html => "html",
+            Renderer::Dom => "dom",
+        }
+    }
+
+    /// The normalized rendering of `markdown`, `None` if rendering panics.
+    fn render(self, markdown: &str, tag_filter: bool) -> Option<String> {
+        let dom = memory::Document;
+        let policy = HtmlPolicy {
+            mode: HtmlMode::Trusted,
+            tag_filter,
+            ..HtmlPolicy::default()
+        };
+        panic::catch_unwind(AssertUnwindSafe(|| match self {
+            Renderer::Html => {
+                let (arena, root) = parse(markdown);
+                let html = html::to_html_with_policy(&arena, root, &policy);
+                normalize(&dom, &dom.parse_html(&html))
+            }
+            Renderer::Dom => {
+                let target = dom.create_element("main");
+                let mut context = CompileContext::new();
+                context
+                    .set_html_policy(&serde_json::to_string(&policy).unwrap())
+                    .unwrap();
+                context.render_into(&dom, &target, markdown);
+                let root = &target.children()[0];
+                normalize(&dom, &root.children())
+            }
+        }))
+        .ok()
+    }
+}
+
+enum Token {
+    Tag { html: String, block: bool },
+    Text(String),
+    Pre(String),
+}
+
+/// `nodes` as HTML with the attributes sorted, the whitespace around block
+/// elements and at the start of lines removed, and other whitespace
+/// collapsed out of `<pre>`. What the renderer adds on top of the spec is
+/// left out: heading ids, empty titles, the class of task list items,
+/// `display: contents` wrappers and highlighting spans. URLs are compared
+/// as written: the expected ones are already percent-encoded.
+fn normalize(dom: &memory::Document, nodes: &[memory::Node]) -> String {
+    let mut tokens = Vec::new();
+    flatten(dom, nodes, false, &mut tokens);
+    let is_block = |token: Option<&Token>| match token {
+        Some(Token::Tag { block, .. }) => *block,
+        Some(_) => false,
+        None => true,
+    };
+    let mut out = String::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Tag { html, .. } => out.push_str(html),
+            Token::Pre(text) => html::escape_html(text, &mut out),
+            Token::Text(text) => {
+                let mut text = text.as_str();
+                let before = idx.checked_sub(1).and_then(|idx| tokens.get(idx));
+                let after_break = matches!(before, Some(Token::Tag { html, .. }) if html == "<br>");
+                if after_break || is_block(before) {
+                    text = text.trim_start_matches(' ');
+                }
+                if is_block(tokens.get(idx + 1)) {
+                    text = text.trim_end_matches(' ');
+                }
+                html::escape_html(text, &mut out);
+            }
+        }
+    }
+    out
+}
+
+fn flatten(dom: &memory::Document, nodes: &[memory::Node], pre: bool, tokens: &mut Vec<Token>) {
+    for node in nodes {
+        let tag = match node.tag() {
+            Some(tag) => tag,
+            None => {
+                match dom.text(node) {
+                    Some(text) if pre => tokens.push(Token::Pre(text)),
+                    Some(text) => push_text(&text, tokens),
+                    None => tokens.push(Token::Tag {
+                        html: node.outer_html(),
+                        block: false,
+                    }),
+                }
+                continue;
+            }
+        };
+        let wrapper = node.attribute("style").as_deref() == Some("display: contents")
+            || tag == "span"
+                && node
+                    .attribute("class")
+                    .is_some_and(|class| class.starts_with("hl-"));
+        if wrapper {
+            flatten(dom, &node.children(), pre, tokens);
+            continue;
+        }
+        let mut attributes: Vec<_> = node
+            .attributes()
+            .into_iter()
+            .filter(|(name, value)| match name.as_str() {
+                "id" => !matches!(tag.as_str(), "h1" | "h2" | "h3" | "h4" | "h5" | "h6"),
+                "class" => value != "task-list-item",
+                "title" => !value.is_empty(),
+                _ => true,
+            })
+            .collect();
+        attributes.sort();
+        let mut html = format!("<{}", tag);
+        for (name, value) in attributes {
+            html.push(' ');
+            html.push_str(&name);
+            html.push_str("=\"");
+            html::escape_html(&value, &mut html);
+            html.push('"');
+        }
+        html.push('>');
+        let block = BLOCK.contains(&tag.as_str());
+        tokens.push(Token::Tag { html, block });
+        if VOID.contains(&tag.as_str()) {
+            continue;
+        }
+        flatten(dom, &node.children(), pre || tag == "pre", tokens);
+        tokens.push(Token::Tag {
+            html: format!("</{}>", tag),
+            block,
+        });
+    }
+}
+
+/// Append `text` with its whitespace collapsed, joined to the text before.
+fn push_text(text: &str, tokens: &mut Vec<Token>) {
+    if let Some(Token::Text(last)) = tokens.last_mut() {
+        last.push_str(text);
+    } else {
+        tokens.push(Token::Text(text.to_string()));
+    }
+    if let Some(Token::Text(last)) = tokens.last_mut() {
+        *last = collapse(last);
+    }
+}
+
+fn collapse(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            space = true;
+            continue;
+        }
+        if space {
+            out.push(' ');
+            space = false;
+        }
+        out.push(c);
+    }
+    if space {
+        out.push(' ');
+    }
+    out
+}
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn load(fixture: &str) -> Vec<Example> {
+    let path = fixtures().join(format!("{}.json", fixture));
+    serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+/// A failing example, as written in `known-failures.txt`.
+type Failure = (String, usize, &'static str);
+
+fn known_failures() -> BTreeSet<String> {
+    fs::read_to_string(fixtures().join("known-failures.txt"))
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+fn describe((fixture, example, renderer): &Failure) -> String {
+    format!("{} {} {}", fixture, example, renderer)
+}
+
+#[test]
+fn conforms_to_the_spec() {
+    // Panics are reported as failures of their example.
+    panic::set_hook(Box::new(|_| {}));
+    let mut failures: Vec<Failure> = Vec::new();
+    let mut details = BTreeMap::new();
+    for fixture in FIXTURES {
+        let mut sections: Vec<(String, usize, [usize; 2])> = Vec::new();
+        for example in load(fixture) {
+            if sections.last().map(|section| &section.0) != Some(&example.section) {
+                sections.push((example.section.clone(), 0, [0; 2]));
+            }
+            let section = sections.last_mut().unwrap();
+            section.1 += 1;
+            let dom = memory::Document;
+            let expected = normalize(&dom, &dom.parse_html(&example.html));
+            for (idx, renderer) in Renderer::ALL.iter().enumerate() {
+                let actual = renderer.render(&example.markdown, fixture == "gfm");
+                if actual.as_ref() == Some(&expected) {
+                    section.2[idx] += 1;
+                    continue;
+                }
+                let failure = (fixture.to_string(), example.example, renderer.name());
+                details.insert(
+                    describe(&failure),
+                    format!(
+                        "markdown: {:?}\nexpected: {:?}\nactual:   {:?}",
+                        example.markdown,
+                        expected,
+                        actual.as_deref().unwrap_or("<panicked>")
+                    ),
+                );
+                failures.push(failure);
+            }
+        }
+        let total: usize = sections.iter().map(|section| section.1).sum();
+        for (name, count, passed) in &sections {
+            println!(
+                "{:<12} {:<40} html {:>3}/{:<3} dom {:>3}/{:<3}",
+                fixture, name, passed[0], count, passed[1], count
+            );
+        }
+        for (idx, renderer) in Renderer::ALL.iter().enumerate() {
+            let passed: usize = sections.iter().map(|section| section.2[idx]).sum();
+            println!(
+                "{:<12} {:<40} {:.1}% ({}/{})",
+                fixture,
+                renderer.name(),
+                100.0 * passed as f64 / total as f64,
+                passed,
+                total
+            );
+        }
+    }
+    let _ = panic::take_hook();
+
+    let actual: BTreeSet<String> = failures.iter().map(describe).collect();
+    if env::var_os("UPDATE_CONFORMANCE").is_some() {
+        let mut list = String::from(
+            "# Examples failing conformance: <fixture> <example> <renderer>.\n\
+             # Written by `UPDATE_CONFORMANCE=1 cargo test --test spec`.\n",
+        );
+        for failure in &failures {
+            list.push_str(&describe(failure));
+            list.push('\n');
+        }
+        fs::write(fixtures().join("known-failures.txt"), list).unwrap();
+        return;
+    }
+    let known = known_failures();
+    let regressions: Vec<_> = actual.difference(&known).collect();
+    for regression in &regressions {
+        println!("{}\n{}\n", regression, details[*regression]);
+    }
+    let fixed: Vec<_> = known.difference(&actual).collect();
+    assert!(
+        regressions.is_empty() && fixed.is_empty(),
+        "examples newly failing: {:?}; newly passing: {:?}. \
+         Update the list with `UPDATE_CONFORMANCE=1 cargo test --test spec`",
+        regressions,
+        fixed
+    );
+}
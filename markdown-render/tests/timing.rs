@@ -0,0 +1,48 @@
+//! Time taken by an edit in a large document.
+//!
+//! An edit re-parses the blocks around it and patches their DOM: its cost
+//! must not grow with the document, and stay within a frame for one of a
+//! megabyte. Timings are only meaningful optimized, so the test is ignored
+//! in debug builds: run it with `cargo test --release --test timing`.
+
+use std::time::{Duration, Instant};
+
+use markdown_render::{dom::Dom, event::SourceEdit, memory, CompileContext};
+
+const SECTION: &str = "## Section\n\nSome *text* with a [link](https://example.com) and `code`.\nMore text on a second line.\n\n- item one\n- item two\n  - nested\n\n```rust\nfn main() {}\n```\n\n> quote\n\n";
+
+const FRAME: Duration = Duration::from_millis(16);
+
+#[test]
+#[cfg_attr(debug_assertions, ignore)]
+fn edits_a_megabyte_document_within_a_frame() {
+    let mut source = String::new();
+    while source.len() < 1_000_000 {
+        source.push_str(SECTION);
+    }
+    let dom = memory::Document;
+    let target = dom.create_element("main");
+    let mut context = CompileContext::new();
+    context.render_into(&dom, &target, &source);
+
+    let middle = source.len() / 2;
+    let at = middle + source[middle..].find("Some *text*").unwrap() + 5;
+    let replacements = ["x", "\n", "y", " *z*", "\n\n"];
+    // The fastest of a few edits, the others may have compacted the arena
+    // or been preempted.
+    let fastest = replacements
+        .iter()
+        .map(|replacement| {
+            let edit = SourceEdit {
+                start: at,
+                end: at,
+                replacement: replacement.to_string(),
+            };
+            let start = Instant::now();
+            context.render_edit_into(&dom, &target, &edit).unwrap();
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    assert!(fastest < FRAME, "an edit took {:?}", fastest);
+}